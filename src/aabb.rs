@@ -1,11 +1,8 @@
 use crate::core::{
-    Normal3f,
     Point3f,
-    Matrix4x4f,
-    dot_vv,
-    dot_vn,
+    Transform,
 };
-use crate::ray::Ray;
+use crate::ray::{InverseRay, Ray};
 
 #[derive(Copy, Clone)]
 pub struct AABB {
@@ -15,71 +12,141 @@ pub struct AABB {
 
 impl AABB {
     pub fn hit(self, r: &Ray, t_min: f32, t_max: f32) -> bool {
-        let inv_dir = 1.0_f32 / r.direction().x;
-        let t0 = (self.minimum.x - r.origin().x) * inv_dir;
-        let t1 = (self.maximum.x - r.origin().x) * inv_dir;
+        self.hit_inverse(&InverseRay::from(r), t_min, t_max)
+    }
+
+    pub fn hit_inverse(&self, r: &InverseRay, t_min: f32, t_max: f32) -> bool {
+        self.range_inverse(r, t_min, t_max).is_some()
+    }
+
+    // The part of t_min to t_max that the ray spends inside the box
+    pub fn range(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        self.range_inverse(&InverseRay::from(r), t_min, t_max)
+    }
 
-        let (t0, t1) = if inv_dir < 0.0_f32 {
-            (t1, t0)
+    pub fn range_inverse(&self, r: &InverseRay, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let (near, far) = if r.direction_is_negative[0] {
+            (self.maximum.x, self.minimum.x)
         } else {
-            (t0, t1)
+            (self.minimum.x, self.maximum.x)
         };
+        let t0 = (near - r.origin.x) * r.inv_direction.x;
+        let t1 = (far - r.origin.x) * r.inv_direction.x;
 
         let t_min = if t0 > t_min { t0 } else { t_min };
-        let t_min = if t1 > t_max { t1 } else { t_max };
+        let t_max = if t1 < t_max { t1 } else { t_max };
 
         if t_max <= t_min {
-            return false;
+            return None;
         }
 
-        let inv_dir = 1.0_f32 / r.direction().y;
-        let t0 = (self.minimum.y - r.origin().y) * inv_dir;
-        let t1 = (self.maximum.y - r.origin().y) * inv_dir;
-
-        let (t0, t1) = if inv_dir < 0.0_f32 {
-            (t1, t0)
+        let (near, far) = if r.direction_is_negative[1] {
+            (self.maximum.y, self.minimum.y)
         } else {
-            (t0, t1)
+            (self.minimum.y, self.maximum.y)
         };
+        let t0 = (near - r.origin.y) * r.inv_direction.y;
+        let t1 = (far - r.origin.y) * r.inv_direction.y;
 
         let t_min = if t0 > t_min { t0 } else { t_min };
-        let t_min = if t1 > t_max { t1 } else { t_max };
+        let t_max = if t1 < t_max { t1 } else { t_max };
 
         if t_max <= t_min {
-            return false;
+            return None;
         }
 
-        let inv_dir = 1.0_f32 / r.direction().z;
-        let t0 = (self.minimum.z - r.origin().z) * inv_dir;
-        let t1 = (self.maximum.z - r.origin().z) * inv_dir;
-
-        let (t0, t1) = if inv_dir < 0.0_f32 {
-            (t1, t0)
+        let (near, far) = if r.direction_is_negative[2] {
+            (self.maximum.z, self.minimum.z)
         } else {
-            (t0, t1)
+            (self.minimum.z, self.maximum.z)
         };
+        let t0 = (near - r.origin.z) * r.inv_direction.z;
+        let t1 = (far - r.origin.z) * r.inv_direction.z;
 
         let t_min = if t0 > t_min { t0 } else { t_min };
-        let t_min = if t1 > t_max { t1 } else { t_max };
+        let t_max = if t1 < t_max { t1 } else { t_max };
 
-        if t_max <= t_min {
-            return false;
+        if t_max > t_min {
+            Some((t_min, t_max))
+        } else {
+            None
         }
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let x = self.maximum.x - self.minimum.x;
+        let y = self.maximum.y - self.minimum.y;
+        let z = self.maximum.z - self.minimum.z;
 
-        true
+        2.0 * (x * y + y * z + z * x)
+    }
 
-        // for (int a = 0; a < 3; a++) {
-        //     auto invD = 1.0f / r.direction()[a];
-        //     auto t0 = (min()[a] - r.origin()[a]) * invD;
-        //     auto t1 = (max()[a] - r.origin()[a]) * invD;
-        //     if (invD < 0.0f)
-        //         std::swap(t0, t1);
-        //     t_min = t0 > t_min ? t0 : t_min;
-        //     t_max = t1 < t_max ? t1 : t_max;
-        //     if (t_max <= t_min)
-        //         return false;
-        // }
-        // return true;
+    pub fn centroid(&self) -> Point3f {
+        Point3f {
+            x: 0.5 * (self.minimum.x + self.maximum.x),
+            y: 0.5 * (self.minimum.y + self.maximum.y),
+            z: 0.5 * (self.minimum.z + self.maximum.z),
+        }
+    }
+
+    pub fn join_point(aabb: &AABB, point: &Point3f) -> AABB {
+        AABB {
+            minimum: Point3f {
+                x: aabb.minimum.x.min(point.x),
+                y: aabb.minimum.y.min(point.y),
+                z: aabb.minimum.z.min(point.z),
+            },
+            maximum: Point3f {
+                x: aabb.maximum.x.max(point.x),
+                y: aabb.maximum.y.max(point.y),
+                z: aabb.maximum.z.max(point.z),
+            },
+        }
+    }
+
+    pub fn corners(&self) -> [Point3f; 8] {
+        let (p0, p1) = (self.minimum, self.maximum);
+
+        [
+            p0,
+            Point3f {x: p1.x, y: p0.y, z: p0.z},
+            Point3f {x: p0.x, y: p1.y, z: p0.z},
+            Point3f {x: p0.x, y: p0.y, z: p1.z},
+            Point3f {x: p1.x, y: p1.y, z: p0.z},
+            Point3f {x: p1.x, y: p0.y, z: p1.z},
+            Point3f {x: p0.x, y: p1.y, z: p1.z},
+            p1,
+        ]
+    }
+
+    // Boxes around all eight transformed corners, so a rotated box stays
+    // inside its bounds
+    pub fn transform(&self, transform: &Transform) -> AABB {
+        let corners = self.corners();
+        let first = transform.m * corners[0];
+
+        corners[1..]
+            .iter()
+            .fold(
+                AABB { minimum: first, maximum: first },
+                |joined, corner| AABB::join_point(&joined, &(transform.m * *corner)),
+            )
+    }
+
+    // Grown by delta on every side
+    pub fn expand(&self, delta: f32) -> AABB {
+        AABB {
+            minimum: Point3f {
+                x: self.minimum.x - delta,
+                y: self.minimum.y - delta,
+                z: self.minimum.z - delta,
+            },
+            maximum: Point3f {
+                x: self.maximum.x + delta,
+                y: self.maximum.y + delta,
+                z: self.maximum.z + delta,
+            },
+        }
     }
 
     pub fn join(box_a: &AABB, box_b: &AABB) -> AABB {
@@ -96,4 +163,107 @@ impl AABB {
             },
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::aabb::AABB;
+    use crate::core::{Point3f, Vector3f, gen_rotate_z, gen_translate};
+    use crate::ray::Ray;
+
+    fn unit_box() -> AABB {
+        AABB {
+            minimum: Point3f {x: -1.0, y: -1.0, z: -1.0},
+            maximum: Point3f {x:  1.0, y:  1.0, z:  1.0},
+        }
+    }
+
+    #[test]
+    fn ray_through_box() {
+        let ray = Ray {
+            a: Point3f {x: 0.5, y: 0.5, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        assert!(unit_box().hit(&ray, 0.0, 100.0));
+    }
+
+    #[test]
+    fn ray_beside_box() {
+        let ray = Ray {
+            a: Point3f {x: 1.5, y: 0.5, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        assert!(!unit_box().hit(&ray, 0.0, 100.0));
+    }
+
+    #[test]
+    fn diagonal_ray_misses_corner() {
+        // Passes every slab individually, but never all three at once
+        let ray = Ray {
+            a: Point3f {x: -2.5, y: 0.0, z: 0.0},
+            b: Vector3f {x: 1.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        assert!(!unit_box().hit(&ray, 0.0, 100.0));
+    }
+
+    #[test]
+    fn box_outside_t_range() {
+        let ray = Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        // Box spans t = 4 to t = 6
+        assert!(!unit_box().hit(&ray, 0.0, 3.0));
+        assert!(!unit_box().hit(&ray, 7.0, 100.0));
+        assert!(unit_box().hit(&ray, 5.0, 100.0));
+    }
+
+    #[test]
+    fn range_inside_box() {
+        let ray = Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        assert_eq!(unit_box().range(&ray, 0.0, 100.0), Some((4.0, 6.0)));
+        assert_eq!(unit_box().range(&ray, 5.0, 5.5), Some((5.0, 5.5)));
+        assert_eq!(unit_box().range(&ray, 0.0, 3.0), None);
+    }
+
+    #[test]
+    fn box_behind_ray() {
+        let ray = Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: 5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        assert!(!unit_box().hit(&ray, 0.0, 100.0));
+    }
+
+    #[test]
+    fn rotated_box_covers_every_corner() {
+        // Turned 45 degrees the corners swing out to sqrt(2), beyond the box
+        // made from only the minimum and maximum corners
+        let transform = gen_translate(Vector3f {x: 3.0, y: 0.0, z: 0.0}) * gen_rotate_z(0.25 * f32::consts::PI);
+        let bounds = unit_box().transform(&transform);
+
+        assert!(approx_eq!(f32, bounds.minimum.x, 3.0 - 2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.maximum.x, 3.0 + 2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.minimum.y, -2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.maximum.y, 2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.maximum.z, 1.0, epsilon = 1e-5));
+    }
+}
@@ -0,0 +1,309 @@
+use std::sync::Arc;
+
+use crate::core::Colour;
+use crate::filter::{BoxFilter, FilterTrait};
+use crate::image::Image;
+use crate::render::RenderResult;
+
+
+// Running statistics for a single pixel. The mean and variance of the samples
+// inside the pixel are updated with Welford's method so they never need the
+// individual samples, while the filtered colour is kept as a weighted sum of
+// every sample splatted onto the pixel.
+#[derive(Copy, Clone, Debug)]
+pub struct PixelStatistics {
+    pub count: u32,
+    pub mean: Colour,
+    m2: Colour,
+    pub weighted_sum: Colour,
+    pub weight: f32,
+}
+
+// Folds a stream of samples into per pixel statistics, using memory that only
+// depends on the size of the image
+pub struct Accumulator {
+    pub width: u32,
+    pub height: u32,
+    pub sample_count: u64,
+    filter: Arc<dyn FilterTrait + Send + Sync>,
+    pixels: Vec<PixelStatistics>,
+}
+
+impl PixelStatistics {
+    pub fn new() -> PixelStatistics {
+        PixelStatistics {
+            count: 0,
+            mean: Colour { r: 0.0, g: 0.0, b: 0.0 },
+            m2: Colour { r: 0.0, g: 0.0, b: 0.0 },
+            weighted_sum: Colour { r: 0.0, g: 0.0, b: 0.0 },
+            weight: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, colour: Colour) {
+        self.count += 1;
+        let delta = colour - self.mean;
+        self.mean += delta / self.count as f32;
+        self.m2 += delta * (colour - self.mean);
+    }
+
+    pub fn splat(&mut self, colour: Colour, weight: f32) {
+        self.weighted_sum += colour * weight;
+        self.weight += weight;
+    }
+
+    // Filters with negative lobes can leave almost no weight on a pixel, in
+    // which case the plain mean is the better estimate
+    pub fn filtered(&self) -> Colour {
+        if self.weight > 1e-4 {
+            self.weighted_sum / self.weight
+        } else {
+            self.mean
+        }
+    }
+
+    pub fn sum(&self) -> Colour {
+        self.mean * self.count as f32
+    }
+
+    // Sample variance of each channel, zero until there are two samples
+    pub fn variance(&self) -> Colour {
+        if self.count < 2 {
+            return Colour { r: 0.0, g: 0.0, b: 0.0 };
+        }
+        self.m2 / (self.count - 1) as f32
+    }
+}
+
+impl Default for PixelStatistics {
+    fn default() -> Self {
+        PixelStatistics::new()
+    }
+}
+
+impl Accumulator {
+    // Box filters each sample into the pixel it lands in
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator::with_filter(width, height, Arc::new(BoxFilter { radius: 0.5 }))
+    }
+
+    pub fn with_filter(width: u32, height: u32, filter: Arc<dyn FilterTrait + Send + Sync>) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            sample_count: 0,
+            filter,
+            pixels: vec![PixelStatistics::new(); (width * height) as usize],
+        }
+    }
+
+    // Samples that land outside of the image are dropped, returning false,
+    // though they can still be splatted onto pixels near the edge
+    pub fn add(&mut self, result: &RenderResult) -> bool {
+        self.splat(result);
+
+        let x = result.x_coord.floor();
+        let y = result.y_coord.floor();
+        if x < 0.0 || y < 0.0 || x >= self.width as f32 || y >= self.height as f32 {
+            return false;
+        }
+
+        let index = self.index(x as u32, y as u32);
+        self.pixels[index].add(result.colour);
+        self.sample_count += 1;
+        true
+    }
+
+    // Adds the sample to every pixel whose centre is within the filter's
+    // radius. A centre exactly on the radius only counts on one side, so a
+    // box of radius 0.5 puts every sample into exactly one pixel.
+    fn splat(&mut self, result: &RenderResult) {
+        let radius = self.filter.radius();
+        let (x_from, x_to) = pixel_range(result.x_coord, radius, self.width);
+        let (y_from, y_to) = pixel_range(result.y_coord, radius, self.height);
+
+        for y in y_from..y_to {
+            for x in x_from..x_to {
+                let weight = self.filter.evaluate(
+                    result.x_coord - (x as f32 + 0.5),
+                    result.y_coord - (y as f32 + 0.5),
+                );
+                if weight != 0.0 {
+                    let index = self.index(x, y);
+                    self.pixels[index].splat(result.colour, weight);
+                }
+            }
+        }
+    }
+
+    // Takes the renderer's coordinates, where y = 0 is the bottom row
+    pub fn pixel(&self, x: u32, y: u32) -> &PixelStatistics {
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn filtered_image(&self) -> Image {
+        self.image(|pixel| pixel.filtered())
+    }
+
+    pub fn mean_image(&self) -> Image {
+        self.image(|pixel| pixel.mean)
+    }
+
+    pub fn variance_image(&self) -> Image {
+        self.image(|pixel| pixel.variance())
+    }
+
+    fn image<F: Fn(&PixelStatistics) -> Colour>(&self, f: F) -> Image {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: self.pixels.iter().map(f).collect(),
+        }
+    }
+
+    // Stored in the same top down order as Image
+    fn index(&self, x: u32, y: u32) -> usize {
+        ((self.height - 1 - y) * self.width + x) as usize
+    }
+}
+
+// Pixels with centres in (coord - radius, coord + radius], clipped to the image
+fn pixel_range(coord: f32, radius: f32, size: u32) -> (u32, u32) {
+    let from = (coord - radius - 0.5).floor() + 1.0;
+    let to = (coord + radius - 0.5).floor() + 1.0;
+
+    (
+        from.max(0.0).min(size as f32) as u32,
+        to.max(0.0).min(size as f32) as u32,
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use std::sync::Arc;
+
+    use crate::accumulator::{Accumulator, PixelStatistics};
+    use crate::core::Colour;
+    use crate::filter::{BoxFilter, TentFilter, filter_from_name};
+    use crate::render::RenderResult;
+
+    fn sample(x_coord: f32, y_coord: f32, value: f32) -> RenderResult {
+        RenderResult {
+            x_coord,
+            y_coord,
+            time: 0.0,
+            colour: Colour { r: value, g: 2.0 * value, b: 0.0 },
+        }
+    }
+
+    #[test]
+    fn mean_and_variance() {
+        let mut pixel = PixelStatistics::new();
+        for value in &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            pixel.add(Colour { r: *value, g: 1.0, b: -*value });
+        }
+
+        assert_eq!(pixel.count, 8);
+        assert!(approx_eq!(f32, pixel.mean.r, 5.0, epsilon = 1e-6));
+        assert!(approx_eq!(f32, pixel.mean.b, -5.0, epsilon = 1e-6));
+        assert!(approx_eq!(f32, pixel.sum().r, 40.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, pixel.variance().r, 32.0 / 7.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, pixel.variance().g, 0.0));
+        assert!(approx_eq!(f32, pixel.variance().b, 32.0 / 7.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn single_sample_has_no_variance() {
+        let mut pixel = PixelStatistics::new();
+        pixel.add(Colour { r: 3.0, g: 3.0, b: 3.0 });
+
+        assert!(approx_eq!(f32, pixel.variance().r, 0.0));
+    }
+
+    #[test]
+    fn samples_land_in_their_pixel() {
+        let mut accumulator = Accumulator::new(4, 2);
+        assert!(accumulator.add(&sample(0.5, 0.5, 1.0)));
+        assert!(accumulator.add(&sample(0.999, 0.001, 3.0)));
+        assert!(accumulator.add(&sample(3.5, 1.999, 0.5)));
+
+        assert_eq!(accumulator.sample_count, 3);
+        assert_eq!(accumulator.pixel(0, 0).count, 2);
+        assert!(approx_eq!(f32, accumulator.pixel(0, 0).mean.r, 2.0));
+        assert!(approx_eq!(f32, accumulator.pixel(3, 1).mean.g, 1.0));
+
+        // Bottom left pixel is at the start of the last row
+        let image = accumulator.mean_image();
+        assert!(approx_eq!(f32, image.pixels[4].r, 2.0));
+        assert!(approx_eq!(f32, image.pixels[3].r, 0.5));
+    }
+
+    #[test]
+    fn samples_outside_image_are_dropped() {
+        let mut accumulator = Accumulator::new(4, 2);
+
+        assert!(!accumulator.add(&sample(-0.5, 0.5, 1.0)));
+        assert!(!accumulator.add(&sample(4.0, 0.5, 1.0)));
+        assert!(!accumulator.add(&sample(0.5, 2.0, 1.0)));
+        assert_eq!(accumulator.sample_count, 0);
+    }
+
+    #[test]
+    fn default_filter_matches_mean() {
+        let mut accumulator = Accumulator::new(4, 2);
+        for (index, x_coord) in [0.0, 0.5, 0.999, 1.0, 2.5, 3.999].iter().enumerate() {
+            accumulator.add(&sample(*x_coord, 1.5, index as f32));
+        }
+
+        let filtered = accumulator.filtered_image();
+        let mean = accumulator.mean_image();
+        for (filtered, mean) in filtered.pixels.iter().zip(mean.pixels.iter()) {
+            assert_eq!(filtered, mean);
+        }
+        assert!(approx_eq!(f32, accumulator.pixel(0, 1).weight, 3.0));
+        assert!(approx_eq!(f32, accumulator.pixel(1, 1).weight, 1.0));
+    }
+
+    #[test]
+    fn wide_filter_splats_into_neighbours() {
+        let mut accumulator = Accumulator::with_filter(3, 1, Arc::new(TentFilter { radius: 1.0 }));
+        accumulator.add(&sample(1.25, 0.5, 1.0));
+
+        assert!(approx_eq!(f32, accumulator.pixel(0, 0).weight, 0.25));
+        assert!(approx_eq!(f32, accumulator.pixel(1, 0).weight, 0.75));
+        assert!(approx_eq!(f32, accumulator.pixel(2, 0).weight, 0.0));
+        assert!(approx_eq!(f32, accumulator.filtered_image().pixels[0].r, 1.0));
+
+        // Only the pixel the sample landed in counts it
+        assert_eq!(accumulator.pixel(0, 0).count, 0);
+        assert_eq!(accumulator.pixel(1, 0).count, 1);
+    }
+
+    #[test]
+    fn samples_outside_image_splat_onto_edge() {
+        let mut accumulator = Accumulator::with_filter(2, 1, Arc::new(BoxFilter { radius: 1.0 }));
+
+        assert!(!accumulator.add(&sample(-0.25, 0.5, 2.0)));
+        assert!(approx_eq!(f32, accumulator.pixel(0, 0).filtered().r, 2.0));
+        assert!(approx_eq!(f32, accumulator.pixel(1, 0).weight, 0.0));
+    }
+
+    #[test]
+    fn filters_keep_flat_images_flat() {
+        for name in &["tent", "gaussian", "mitchell", "lanczos"] {
+            let mut accumulator = Accumulator::with_filter(8, 8, filter_from_name(name, None).unwrap());
+            for y in 0..32 {
+                for x in 0..32 {
+                    accumulator.add(&sample(x as f32 * 0.25 + 0.125, y as f32 * 0.25 + 0.125, 0.5));
+                }
+            }
+
+            for pixel in accumulator.filtered_image().pixels {
+                assert!(approx_eq!(f32, pixel.r, 0.5, epsilon = 1e-4), "{} {:?}", name, pixel);
+            }
+        }
+    }
+}
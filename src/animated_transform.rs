@@ -0,0 +1,276 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Decomposed,
+    Transform,
+};
+use crate::primative::{TransformTrait, swept_bounds};
+
+
+// How the motion between one keyframe and the next speeds up and slows down
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // Maps the fraction of time through a segment to the fraction of motion
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Keyframe {
+    pub time: f32,
+    pub transform: Transform,
+    // Used from this keyframe up to the next one
+    pub easing: Easing,
+}
+
+// Moves between keyframes by interpolating the translation, rotation and
+// scale separately, so a spinning object keeps its shape part way through.
+// Before the first keyframe and after the last it holds still.
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+    decomposed: Vec<Decomposed>,
+    animated: bool,
+}
+
+impl AnimatedTransform {
+    pub fn new(time_0: f32, start: Transform, time_1: f32, end: Transform) -> AnimatedTransform {
+        AnimatedTransform::from_keyframes(vec![
+            Keyframe { time: time_0, transform: start, easing: Easing::Linear },
+            Keyframe { time: time_1, transform: end, easing: Easing::Linear },
+        ])
+    }
+
+    // Needs at least one keyframe, and every time must be a number so they
+    // can be sorted. Scene files are checked for both before they get here.
+    pub fn from_keyframes(mut keyframes: Vec<Keyframe>) -> AnimatedTransform {
+        assert!(!keyframes.is_empty(), "An animated transform needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+        let decomposed = keyframes
+            .iter()
+            .map(|keyframe| keyframe.transform.decompose())
+            .collect();
+        let animated = keyframes
+            .iter()
+            .any(|keyframe| keyframe.transform.m != keyframes[0].transform.m);
+
+        AnimatedTransform {
+            keyframes,
+            decomposed,
+            animated,
+        }
+    }
+}
+
+impl TransformTrait for AnimatedTransform {
+    fn generate_transform(&self, time_t: f32) -> Transform {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if !self.animated || time_t <= first.time {
+            return first.transform;
+        }
+        if time_t >= last.time {
+            return last.transform;
+        }
+
+        // The first segment ending after time_t, which must start at or before it
+        let i = self.keyframes
+            .windows(2)
+            .position(|pair| time_t < pair[1].time)
+            .unwrap();
+        let (a, b) = (&self.keyframes[i], &self.keyframes[i + 1]);
+        let t = a.easing.apply((time_t - a.time) / (b.time - a.time));
+
+        Decomposed::lerp(t, &self.decomposed[i], &self.decomposed[i + 1]).to_transform()
+    }
+
+    // Sweeps each segment between keyframes separately, so the samples land
+    // on every keyframe in the range
+    fn motion_bounds(&self, bounds: &AABB, time_0: f32, time_1: f32) -> AABB {
+        if !self.animated {
+            return bounds.transform(&self.keyframes[0].transform);
+        }
+
+        let generate = |time| self.generate_transform(time);
+        let mut swept = swept_bounds(generate, bounds, time_0, time_0);
+        let mut start = time_0;
+        for keyframe in &self.keyframes {
+            if keyframe.time > start && keyframe.time < time_1 {
+                swept = AABB::join(&swept, &swept_bounds(generate, bounds, start, keyframe.time));
+                start = keyframe.time;
+            }
+        }
+
+        AABB::join(&swept, &swept_bounds(generate, bounds, start, time_1))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::sync::Arc;
+
+    use float_cmp::approx_eq;
+
+    use crate::aabb::AABB;
+    use crate::animated_transform::{AnimatedTransform, Easing, Keyframe};
+    use crate::core::{
+        Colour,
+        Point3f,
+        Transform,
+        Vector3f,
+        gen_rotate_y,
+        gen_scale,
+        gen_translate,
+    };
+    use crate::material2::Lambertian;
+    use crate::primative::{Primative, TransformTrait};
+    use crate::ray::Ray;
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::sphere2::Sphere;
+
+    fn assert_transforms_eq(a: &Transform, b: &Transform) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!(
+                    approx_eq!(f32, a.m.m[row][column], b.m.m[row][column], epsilon = 1e-4),
+                    "{:?} and {:?} differ",
+                    a.m,
+                    b.m,
+                );
+                assert!(
+                    approx_eq!(f32, a.m_inv.m[row][column], b.m_inv.m[row][column], epsilon = 1e-4),
+                    "{:?} and {:?} differ",
+                    a.m_inv,
+                    b.m_inv,
+                );
+            }
+        }
+    }
+
+    fn moved(x: f32) -> Transform {
+        gen_translate(Vector3f {x, y: 0.0, z: 0.0})
+    }
+
+    #[test]
+    fn interpolates_rotation_separately() {
+        // Turning a quarter turn while moving, rather than blending the
+        // matrices which would shrink the object halfway
+        let start = gen_scale(2.0, 2.0, 2.0);
+        let end = gen_translate(Vector3f {x: 4.0, y: 0.0, z: 0.0}) * gen_rotate_y(0.5 * f32::consts::PI) * start;
+        let animated = AnimatedTransform::new(0.0, start, 1.0, end);
+
+        let halfway = animated.generate_transform(0.5);
+        let expected = gen_translate(Vector3f {x: 2.0, y: 0.0, z: 0.0}) * gen_rotate_y(0.25 * f32::consts::PI) * start;
+        assert_transforms_eq(&halfway, &expected);
+
+        let p = halfway.m * Point3f {x: 1.0, y: 0.0, z: 0.0};
+        assert!(approx_eq!(f32, Vector3f::from(p - Point3f {x: 2.0, y: 0.0, z: 0.0}).length(), 2.0, epsilon = 1e-4));
+    }
+
+    #[test]
+    fn holds_outside_the_keyframes() {
+        let animated = AnimatedTransform::new(0.25, moved(1.0), 0.75, moved(3.0));
+
+        assert_transforms_eq(&animated.generate_transform(0.0), &moved(1.0));
+        assert_transforms_eq(&animated.generate_transform(0.5), &moved(2.0));
+        assert_transforms_eq(&animated.generate_transform(1.0), &moved(3.0));
+    }
+
+    #[test]
+    fn keyframes_with_easing() {
+        let animated = AnimatedTransform::from_keyframes(vec![
+            Keyframe { time: 2.0, transform: moved(5.0), easing: Easing::Linear },
+            Keyframe { time: 0.0, transform: moved(0.0), easing: Easing::EaseIn },
+            Keyframe { time: 1.0, transform: moved(4.0), easing: Easing::EaseInOut },
+        ]);
+
+        assert_transforms_eq(&animated.generate_transform(0.5), &moved(1.0));
+        assert_transforms_eq(&animated.generate_transform(1.0), &moved(4.0));
+        assert_transforms_eq(&animated.generate_transform(1.5), &moved(4.5));
+        assert_transforms_eq(&animated.generate_transform(1.75), &moved(4.84375));
+
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    }
+
+    #[test]
+    fn still_keyframes_are_not_padded() {
+        let unit = AABB {
+            minimum: Point3f {x: -1.0, y: -1.0, z: -1.0},
+            maximum: Point3f {x: 1.0, y: 1.0, z: 1.0},
+        };
+
+        let bounds = AnimatedTransform::new(0.0, moved(1.0), 1.0, moved(1.0)).motion_bounds(&unit, 0.0, 1.0);
+        assert_eq!((bounds.minimum.x, bounds.maximum.x), (0.0, 2.0));
+
+        let bounds = AnimatedTransform::new(0.0, moved(1.0), 1.0, moved(2.0)).motion_bounds(&unit, 0.0, 1.0);
+        assert!(bounds.minimum.x < 0.0 && bounds.maximum.x > 3.0);
+    }
+
+    #[test]
+    fn motion_bounds_cover_the_path() {
+        let unit = AABB {
+            minimum: Point3f {x: -1.0, y: -1.0, z: -1.0},
+            maximum: Point3f {x: 1.0, y: 1.0, z: 1.0},
+        };
+        // Out along x, then round a half turn to the other side
+        let animated = AnimatedTransform::from_keyframes(vec![
+            Keyframe { time: 0.0, transform: moved(0.0), easing: Easing::Linear },
+            Keyframe { time: 0.5, transform: moved(5.0), easing: Easing::EaseInOut },
+            Keyframe { time: 1.0, transform: gen_rotate_y(0.99 * f32::consts::PI) * moved(5.0), easing: Easing::Linear },
+        ]);
+
+        let bounds = animated.motion_bounds(&unit, 0.0, 1.0);
+        for step in 0..=100 {
+            let transform = animated.generate_transform(step as f32 / 100.0);
+            for corner in unit.corners().iter() {
+                let p = transform.m * *corner;
+                assert!(p.x >= bounds.minimum.x && p.x <= bounds.maximum.x, "{:?} outside at step {}", p, step);
+                assert!(p.z >= bounds.minimum.z && p.z <= bounds.maximum.z, "{:?} outside at step {}", p, step);
+            }
+        }
+
+        // Only the first half of the motion
+        let bounds = animated.motion_bounds(&unit, 0.0, 0.5);
+        assert!(bounds.maximum.x < 6.5 && bounds.minimum.x > -1.5);
+    }
+
+    #[test]
+    fn moving_sphere_blurs() {
+        let primative = Primative {
+            shape: Arc::new(Sphere {radius: 1.0}),
+            transform: Arc::new(AnimatedTransform::new(0.0, moved(0.0), 1.0, moved(3.0))),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5})),
+        };
+        let ray = |time| Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: 5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
+            time,
+        };
+
+        assert!(primative.collide(&ray(0.0), 0.0, 100.0).is_some());
+        assert!(primative.collide(&ray(0.3), 0.0, 100.0).is_some());
+        assert!(primative.collide(&ray(0.5), 0.0, 100.0).is_none());
+
+        let bounds = primative.bounding_box(0.0, 1.0).unwrap();
+        assert!(bounds.minimum.x <= -1.0 && bounds.minimum.x > -1.5);
+        assert!(bounds.maximum.x >= 4.0 && bounds.maximum.x < 4.5);
+    }
+}
@@ -1,14 +1,36 @@
-use std::io::{self, Read};
-use std::io::prelude::*;
+use std::io;
+use std::process;
+use std::time::{Duration, Instant};
 
 use clap::{Arg, App};
 
 extern crate rust_ray_tracing;
 
 use rust_ray_tracing::render::RenderResult;
-use rust_ray_tracing::core::Colour;
+use rust_ray_tracing::sample_stream::SampleStream;
+use rust_ray_tracing::accumulator::Accumulator;
+use rust_ray_tracing::core::{Encoding, ToneMapOperator, ToneMapping};
+use rust_ray_tracing::filter::{FILTER_NAMES, filter_from_name};
+use rust_ray_tracing::image::{Image, ImageFormat};
 
 
+fn image_format(path: &str) -> ImageFormat {
+    match ImageFormat::from_path(path) {
+        Some(format) => format,
+        None => {
+            eprintln!("Error: cannot tell the image format of {}, expected .png, .ppm or .hdr", path);
+            process::exit(1);
+        },
+    }
+}
+
+fn save_image(image: &Image, path: &str, format: ImageFormat, tone_map: &ToneMapping) {
+    if let Err(err) = image.save(path, format, tone_map) {
+        eprintln!("Error: could not write {}: {}", path, err);
+        process::exit(1);
+    }
+}
+
 fn main() {
     let matches = App::new("Ray Tracer")
        .version("0.1")
@@ -28,52 +50,143 @@ fn main() {
                .value_name("INT")
                .help("Height of image")
                .takes_value(true))
+       .arg(Arg::with_name("json")
+               .long("json")
+               .help("Read JSON lines instead of the binary sample stream")
+               .takes_value(false))
+       .arg(Arg::with_name("filter")
+               .long("filter")
+               .default_value("box")
+               .possible_values(&FILTER_NAMES)
+               .value_name("FILTER")
+               .help("Reconstruction filter used to weight samples into nearby pixels")
+               .takes_value(true))
+       .arg(Arg::with_name("filter_radius")
+               .long("filter-radius")
+               .value_name("PIXELS")
+               .help("Radius of the filter, defaults to 0.5 for box, 1 for tent, 1.5 for gaussian and 2 for mitchell and lanczos")
+               .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string()))
+               .takes_value(true))
+       .arg(Arg::with_name("snapshot")
+               .long("snapshot")
+               .value_name("FILE")
+               .help("Periodically write the image so far to a .png, .ppm or .hdr file")
+               .takes_value(true))
+       .arg(Arg::with_name("snapshot_seconds")
+               .long("snapshot-seconds")
+               .default_value("10")
+               .value_name("SECONDS")
+               .help("Time between snapshots")
+               .validator(|value| match value.parse::<f32>() {
+                   Ok(seconds) if seconds > 0.0 && seconds.is_finite() => Ok(()),
+                   Ok(_) => Err("must be a positive number of seconds".to_string()),
+                   Err(err) => Err(err.to_string()),
+               })
+               .takes_value(true))
+       .arg(Arg::with_name("snapshot_samples")
+               .long("snapshot-samples")
+               .value_name("INT")
+               .help("Also write a snapshot after this many samples")
+               .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+               .takes_value(true))
+       .arg(Arg::with_name("variance")
+               .long("variance")
+               .value_name("FILE")
+               .help("Write the per pixel variance to a .png, .ppm or .hdr file")
+               .takes_value(true))
+       .arg(Arg::with_name("tone_map")
+                .long("tone-map")
+                .default_value("clamp")
+                .possible_values(&["clamp", "reinhard", "aces"])
+                .value_name("OPERATOR")
+                .help("How colours brighter than white are brought into range")
+                .takes_value(true))
+       .arg(Arg::with_name("exposure")
+                .long("exposure")
+                .default_value("0")
+                .value_name("STOPS")
+                .help("Brightens or darkens the image before tone mapping")
+                .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string()))
+                .takes_value(true))
+       .arg(Arg::with_name("gamma")
+                .long("gamma")
+                .default_value("srgb")
+                .value_name("srgb|FLOAT")
+                .help("Encoding of 8 bit output, either srgb or a gamma where 1.0 is linear")
+                .validator(|value| value.parse::<Encoding>().map(|_| ()).map_err(|err| err.to_string()))
+                .takes_value(true))
        .get_matches();
     let n_x: usize = matches.value_of("width").unwrap().parse::<u32>().unwrap() as usize;
     let n_y: usize = matches.value_of("height").unwrap().parse::<u32>().unwrap() as usize;
-
-    let mut sorted_results : Vec<Vec<Vec<RenderResult>>> = vec![vec![vec![]; n_x]; n_y];
+    let tone_map = ToneMapping {
+        operator: matches.value_of("tone_map").unwrap().parse::<ToneMapOperator>().unwrap(),
+        exposure: matches.value_of("exposure").unwrap().parse::<f32>().unwrap(),
+        encoding: matches.value_of("gamma").unwrap().parse::<Encoding>().unwrap(),
+    };
 
     let stdin = io::stdin();
-    for line in stdin.lock().lines() {
+    let input = stdin.lock();
+
+    let stream = if matches.is_present("json") {
+        SampleStream::json(input, n_x, n_y)
+    } else {
+        match SampleStream::binary(input) {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Error: could not read sample stream: {}", err);
+                process::exit(1);
+            },
+        }
+    };
+    let (n_x, n_y) = (stream.width, stream.height);
+
+    let snapshot = matches.value_of("snapshot").map(|path| (path, image_format(path)));
+    let snapshot_seconds = matches.value_of("snapshot_seconds").map(|value| Duration::from_secs_f32(value.parse::<f32>().unwrap()));
+    let snapshot_samples = matches.value_of("snapshot_samples").map(|value| value.parse::<u64>().unwrap());
+    let variance = matches.value_of("variance").map(|path| (path, image_format(path)));
+
+    let filter_radius = matches.value_of("filter_radius").map(|value| value.parse::<f32>().unwrap());
+    let filter = filter_from_name(matches.value_of("filter").unwrap(), filter_radius).unwrap();
+
+    let mut accumulator = Accumulator::with_filter(n_x as u32, n_y as u32, filter);
+    let mut last_snapshot = Instant::now();
+    let mut samples_since_snapshot = 0;
+
+    for sample in stream.samples {
         // println!("{}", line.unwrap());
-        let input_ray: RenderResult = serde_json::from_str(&line.unwrap()).unwrap();
+        let input_ray: RenderResult = match sample {
+            Ok(input_ray) => input_ray,
+            Err(err) => {
+                eprintln!("Error: could not read sample stream: {}", err);
+                process::exit(1);
+            },
+        };
 
-        let x_coord = input_ray.x_coord as usize;
-        let y_coord = (n_y as f32 - (input_ray.y_coord + 1.0)) as usize;
+        accumulator.add(&input_ray);
+        samples_since_snapshot += 1;
 
-        if x_coord < 0 || x_coord >= n_x || y_coord < 0 || y_coord >= n_y {
-          continue
+        if let Some((path, format)) = snapshot {
+            let seconds_due = snapshot_seconds.is_some_and(|every| last_snapshot.elapsed() >= every);
+            let samples_due = snapshot_samples.is_some_and(|every| samples_since_snapshot >= every);
+            if seconds_due || samples_due {
+                save_image(&accumulator.filtered_image(), path, format, &tone_map);
+                last_snapshot = Instant::now();
+                samples_since_snapshot = 0;
+            }
         }
-        sorted_results[(n_y as f32 - (input_ray.y_coord + 1.0)) as usize][input_ray.x_coord as usize].push(input_ray);
     }
-    // let mut buffer = String::new();
- //    io::stdin().read_to_string(&mut buffer);
- //    let input_rays: RenderResult = serde_json::from_str(&buffer).unwrap();
 
-    
-    // for result in &results {
-        
-    // }
+    if let Some((path, format)) = snapshot {
+        save_image(&accumulator.filtered_image(), path, format, &tone_map);
+    }
+    if let Some((path, format)) = variance {
+        save_image(&accumulator.variance_image(), path, format, &tone_map);
+    }
 
     println!("P3\n{} {}\n255", n_x, n_y);
-    for y_results in sorted_results {
-        for pixel_results in y_results {
-            let mut col_sum = Colour { r: 0.0, g: 0.0, b: 0.0 };
-            for result in &pixel_results {
-                col_sum += result.colour;
-            }
+    for pixel in accumulator.filtered_image().pixels {
+        let [ir, ig, ib] = tone_map.to_bytes(pixel);
 
-            let col = match pixel_results.len() {
-              0 => col_sum,
-              _ => col_sum / pixel_results.len() as f32,
-            };
-
-            let ir = (255.99 * col.r) as u64;
-            let ig = (255.99 * col.g) as u64;
-            let ib = (255.99 * col.b) as u64;
-
-            println!("{} {} {}", ir, ig, ib);
-        }
+        println!("{} {} {}", ir, ig, ib);
     }
 }
@@ -1,148 +1,460 @@
-use rand;
 use rand::Rng;
 
 use crate::aabb::AABB;
-use crate::ray::Ray;
-use crate::primative::Primative;
+use crate::ray::{InverseRay, Ray};
 use crate::core::Point3f;
-use crate::shapes::base::{Interaction, ShapeTrait};
+use crate::primative::{Primative, PrimativeInteraction};
+use crate::shapes::base::ShapeTrait;
 
 
-pub struct BVHTree {
-    primatives: Vec<Primative>,
-    nodes: Vec<Node>,
-    root: Option<usize>,
+// Relative costs used by the surface area heuristic, following pbrt in
+// treating a node traversal as an eighth of a primative intersection
+const TRAVERSAL_COST: f32 = 0.125;
+const INTERSECTION_COST: f32 = 1.0;
+
+// A primative's index alongside its bounds, as the builders sort and split them
+type BoundedPrimative = (usize, AABB);
+
+#[derive(Copy, Clone, Debug)]
+pub enum SplitStrategy {
+    // Sorts along a random axis and splits the primatives into two halves,
+    // always building down to one primative per leaf
+    RandomAxis,
+    // Binned surface area heuristic, splitting along the axis with the
+    // largest spread of centroids
+    SurfaceAreaHeuristic {
+        buckets: usize,
+        max_leaf_size: usize,
+    },
 }
 
+impl Default for SplitStrategy {
+    fn default() -> Self {
+        SplitStrategy::SurfaceAreaHeuristic {
+            buckets: 12,
+            max_leaf_size: 4,
+        }
+    }
+}
 
-impl BVHTree {
+#[derive(Copy, Clone, Debug)]
+pub struct BVHStatistics {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    pub depth: usize,
+    pub average_leaf_size: f32,
+    pub sah_cost: f32,
+    pub unbounded_count: usize,
+}
+
+// Built over anything with a bounding box that rays can collide with, which
+// is usually the scene's primatives but can also be the triangles of a mesh.
+// Anything without a bounding box, such as an infinite plane, is kept out of
+// the tree and tested against every ray instead.
+pub struct BVHTree<T: ShapeTrait = Primative> {
+    primatives: Vec<T>,
+    primative_indices: Vec<usize>,
+    unbounded: Vec<usize>,
+    // Depth first, so an interior node's first child directly follows it
+    nodes: Vec<LinearNode>,
+}
+
+
+impl<T: ShapeTrait> BVHTree<T> {
     pub fn blank() -> Self {
-        return BVHTree {
+        BVHTree {
             primatives: vec![],
+            primative_indices: vec![],
+            unbounded: vec![],
             nodes: vec![],
-            root: None,
         }
     }
 
-    pub fn new(primatives: Vec<Primative>, time_0: f32, time_1: f32) -> Self {
+    pub fn new(primatives: Vec<T>, time_0: f32, time_1: f32) -> Self {
+        Self::with_strategy(primatives, time_0, time_1, SplitStrategy::default())
+    }
+
+    pub fn with_strategy(primatives: Vec<T>, time_0: f32, time_1: f32, strategy: SplitStrategy) -> Self {
         let mut nodes:Vec<Node> = vec![];
+        let mut primative_indices: Vec<usize> = vec![];
         let mut rnd_gen = rand::thread_rng();
 
-        let mut prim_list: Vec<(usize, AABB)> = primatives
-            .iter()
-            .enumerate()
-            .map(|(index, primative)| {
-                (index, primative.bounding_box(time_0, time_1))
-            })
-            .filter(|(index, aabb_option)| aabb_option.is_some())
-            .map(|(index, aabb_option)| {
-                (index, aabb_option.unwrap())
-            })
-            .collect();
-        
+        let mut prim_list: Vec<BoundedPrimative> = vec![];
+        let mut unbounded: Vec<usize> = vec![];
+        for (index, primative) in primatives.iter().enumerate() {
+            match primative.bounding_box(time_0, time_1) {
+                Some(aabb) => prim_list.push((index, aabb)),
+                None => unbounded.push(index),
+            }
+        }
 
         if prim_list.is_empty() {
-            BVHTree::blank()
+            BVHTree {
+                unbounded,
+                primatives,
+                ..Self::blank()
+            }
         } else {
-            let root = BVHTree::new_branch(
-                &mut rnd_gen,
-                &mut nodes,
-                &mut prim_list,
-            );
+            let root = match strategy {
+                SplitStrategy::RandomAxis => Self::new_branch(
+                    &mut rnd_gen,
+                    &mut nodes,
+                    &mut primative_indices,
+                    &mut prim_list,
+                ),
+                SplitStrategy::SurfaceAreaHeuristic { buckets, max_leaf_size } => Self::new_sah_branch(
+                    buckets.max(2),
+                    max_leaf_size.max(1),
+                    &mut nodes,
+                    &mut primative_indices,
+                    &mut prim_list,
+                ),
+            };
+
+            let mut linear_nodes = Vec::with_capacity(nodes.len());
+            Self::flatten(&nodes, root, &mut linear_nodes);
 
             BVHTree {
-                nodes: vec![],
-                root: Some(root),
-                primatives: primatives,
-            }}
+                nodes: linear_nodes,
+                primative_indices,
+                unbounded,
+                primatives,
+            }
+        }
+    }
+
+    fn new_leaf(nodes: &mut Vec<Node>, primative_indices: &mut Vec<usize>, primative_subsection: &[BoundedPrimative]) -> usize {
+        let first = primative_indices.len();
+        primative_indices.extend(primative_subsection.iter().map(|(index, _)| *index));
+
+        let aabb = primative_subsection[1..]
+            .iter()
+            .fold(primative_subsection[0].1, |joined, (_, aabb)| AABB::join(&joined, aabb));
+
+        nodes.push(Node::Leaf {
+            first,
+            count: primative_subsection.len(),
+            aabb,
+        });
+        nodes.len() - 1
+    }
+
+    fn new_interior(nodes: &mut Vec<Node>, left_index: usize, right_index: usize, axis: usize) -> usize {
+        let node = Node::Branch {
+            left: left_index,
+            right: right_index,
+            axis,
+            aabb: AABB::join(
+                &nodes[left_index].get_aabb(),
+                &nodes[right_index].get_aabb(),
+            ),
+        };
+
+        nodes.push(node);
+        nodes.len() - 1
     }
 
-    fn new_branch(rnd_gen: &mut rand::rngs::ThreadRng, nodes: &mut Vec<Node>, primative_subsection: &mut Vec<(usize, AABB)>) -> usize {
+    fn new_branch(
+        rnd_gen: &mut rand::rngs::ThreadRng,
+        nodes: &mut Vec<Node>,
+        primative_indices: &mut Vec<usize>,
+        primative_subsection: &mut [BoundedPrimative],
+    ) -> usize {
         if primative_subsection.len() == 1 {
-            let (primative, aabb) = primative_subsection[0];
-            let node = Node::Leaf {
-                primative: primative,
-                aabb: aabb,
-            };
-            nodes.push(node);
-            nodes.len() - 1
+            Self::new_leaf(nodes, primative_indices, primative_subsection)
         } else {
-            match rnd_gen.gen_range(0, 3) {
+            let axis = rnd_gen.gen_range(0, 3);
+            match axis {
                 0 => primative_subsection.sort_by(|(_, aabb_a), (_, aabb_b)| aabb_a.minimum.x.partial_cmp(&aabb_b.minimum.x).unwrap()),
                 1 => primative_subsection.sort_by(|(_, aabb_a), (_, aabb_b)| aabb_a.minimum.y.partial_cmp(&aabb_b.minimum.y).unwrap()),
                 _ => primative_subsection.sort_by(|(_, aabb_a), (_, aabb_b)| aabb_a.minimum.z.partial_cmp(&aabb_b.minimum.z).unwrap()),
             }
             
             let chunk_size = primative_subsection.len() / 2 + (primative_subsection.len() & 1);
-            let mut vec_chunks_iter = primative_subsection.chunks(chunk_size);
+            let (left_chunk, right_chunk) = primative_subsection.split_at_mut(chunk_size);
 
-            let mut left_chunk = vec_chunks_iter.next().unwrap().to_vec();
-            let left_index = BVHTree::new_branch(rnd_gen, nodes, &mut left_chunk);
+            let left_index = Self::new_branch(rnd_gen, nodes, primative_indices, left_chunk);
+            let right_index = Self::new_branch(rnd_gen, nodes, primative_indices, right_chunk);
 
-            let mut right_chunk = vec_chunks_iter.next().unwrap().to_vec();
-            let right_index = BVHTree::new_branch(rnd_gen, nodes, &mut right_chunk);
+            Self::new_interior(nodes, left_index, right_index, axis)
+        }
 
-            let node = Node::Branch {
-                left: Some(left_index),
-                right: Some(right_index),
-                aabb: AABB::join(
-                    &nodes[left_index].get_aabb(),
-                    &nodes[right_index].get_aabb(),
-                ),
-            };
+    }
+
+    fn new_sah_branch(
+        bucket_count: usize,
+        max_leaf_size: usize,
+        nodes: &mut Vec<Node>,
+        primative_indices: &mut Vec<usize>,
+        primative_subsection: &mut [BoundedPrimative],
+    ) -> usize {
+        let count = primative_subsection.len();
+        if count == 1 {
+            return Self::new_leaf(nodes, primative_indices, primative_subsection);
+        }
+
+        let first_centroid = primative_subsection[0].1.centroid();
+        let centroid_bounds = primative_subsection
+            .iter()
+            .fold(
+                AABB { minimum: first_centroid, maximum: first_centroid },
+                |bounds, (_, aabb)| AABB::join_point(&bounds, &aabb.centroid()),
+            );
+
+        let extent = centroid_bounds.maximum - centroid_bounds.minimum;
+        let axis = if extent.x > extent.y && extent.x > extent.z {
+            0
+        } else if extent.y > extent.z {
+            1
+        } else {
+            2
+        };
+
+        let axis_min = axis_value(&centroid_bounds.minimum, axis);
+        let axis_extent = axis_value(&centroid_bounds.maximum, axis) - axis_min;
+
+        if axis_extent <= 0.0 {
+            // Every centroid is in the same place, so no plane separates them
+            if count <= max_leaf_size {
+                return Self::new_leaf(nodes, primative_indices, primative_subsection);
+            }
+
+            let (left_chunk, right_chunk) = primative_subsection.split_at_mut(count / 2);
+            let left_index = Self::new_sah_branch(bucket_count, max_leaf_size, nodes, primative_indices, left_chunk);
+            let right_index = Self::new_sah_branch(bucket_count, max_leaf_size, nodes, primative_indices, right_chunk);
+            return Self::new_interior(nodes, left_index, right_index, axis);
+        }
+
+        let bucket_of = |aabb: &AABB| -> usize {
+            let offset = (axis_value(&aabb.centroid(), axis) - axis_min) / axis_extent;
+            ((offset * bucket_count as f32) as usize).min(bucket_count - 1)
+        };
+
+        let mut bucket_counts = vec![0usize; bucket_count];
+        let mut bucket_bounds: Vec<Option<AABB>> = vec![None; bucket_count];
+        for (_, aabb) in primative_subsection.iter() {
+            let bucket = bucket_of(aabb);
+            bucket_counts[bucket] += 1;
+            bucket_bounds[bucket] = Some(match bucket_bounds[bucket] {
+                Some(bounds) => AABB::join(&bounds, aabb),
+                None => *aabb,
+            });
+        }
+
+        // Sweep from both ends so each candidate split is costed in linear time
+        let mut below: Vec<(usize, Option<AABB>)> = Vec::with_capacity(bucket_count - 1);
+        let mut running: (usize, Option<AABB>) = (0, None);
+        for bucket in 0..bucket_count - 1 {
+            running = (running.0 + bucket_counts[bucket], join_optional(running.1, bucket_bounds[bucket]));
+            below.push(running);
+        }
+
+        let mut above: Vec<(usize, Option<AABB>)> = vec![(0, None); bucket_count - 1];
+        let mut running: (usize, Option<AABB>) = (0, None);
+        for bucket in (1..bucket_count).rev() {
+            running = (running.0 + bucket_counts[bucket], join_optional(running.1, bucket_bounds[bucket]));
+            above[bucket - 1] = running;
+        }
+
+        let node_area = primative_subsection[1..]
+            .iter()
+            .fold(primative_subsection[0].1, |joined, (_, aabb)| AABB::join(&joined, aabb))
+            .surface_area();
+
+        let mut best_split = 0;
+        let mut best_cost = f32::INFINITY;
+        for split in 0..bucket_count - 1 {
+            let (count_below, bounds_below) = below[split];
+            let (count_above, bounds_above) = above[split];
+            if count_below == 0 || count_above == 0 {
+                continue;
+            }
+
+            let area_below = bounds_below.map_or(0.0, |bounds| bounds.surface_area());
+            let area_above = bounds_above.map_or(0.0, |bounds| bounds.surface_area());
+            let cost = TRAVERSAL_COST
+                + INTERSECTION_COST * (count_below as f32 * area_below + count_above as f32 * area_above) / node_area;
+
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        let leaf_cost = INTERSECTION_COST * count as f32;
+        if count <= max_leaf_size && (leaf_cost <= best_cost || node_area <= 0.0) {
+            return Self::new_leaf(nodes, primative_indices, primative_subsection);
+        }
+
+        // Partition in place, moving everything below the split to the front
+        let mut split_at = 0;
+        for i in 0..count {
+            if bucket_of(&primative_subsection[i].1) <= best_split {
+                primative_subsection.swap(i, split_at);
+                split_at += 1;
+            }
+        }
+        let (left_chunk, right_chunk) = primative_subsection.split_at_mut(split_at);
+
+        let left_index = Self::new_sah_branch(bucket_count, max_leaf_size, nodes, primative_indices, left_chunk);
+        let right_index = Self::new_sah_branch(bucket_count, max_leaf_size, nodes, primative_indices, right_chunk);
+
+        Self::new_interior(nodes, left_index, right_index, axis)
+    }
+
+    fn flatten(nodes: &[Node], index: usize, linear_nodes: &mut Vec<LinearNode>) -> usize {
+        let position = linear_nodes.len();
+
+        match nodes[index] {
+            Node::Leaf { first, count, aabb } => {
+                linear_nodes.push(LinearNode {
+                    aabb,
+                    kind: LinearKind::Leaf { first, count },
+                });
+            },
+            Node::Branch { left, right, axis, aabb } => {
+                linear_nodes.push(LinearNode {
+                    aabb,
+                    kind: LinearKind::Interior { second_child: 0, axis },
+                });
+
+                Self::flatten(nodes, left, linear_nodes);
+                let second_child = Self::flatten(nodes, right, linear_nodes);
+                linear_nodes[position].kind = LinearKind::Interior { second_child, axis };
+            },
+        }
+
+        position
+    }
+
+    // None when the tree is empty or holds anything unbounded
+    pub fn bounds(&self) -> Option<AABB> {
+        if !self.unbounded.is_empty() {
+            return None;
+        }
+
+        self.nodes.first().map(|node| node.aabb)
+    }
+
+    pub fn statistics(&self) -> BVHStatistics {
+        let mut statistics = BVHStatistics {
+            node_count: self.nodes.len(),
+            leaf_count: 0,
+            depth: 0,
+            average_leaf_size: 0.0,
+            sah_cost: 0.0,
+            unbounded_count: self.unbounded.len(),
+        };
+
+        if !self.nodes.is_empty() {
+            let root_area = self.nodes[0].aabb.surface_area();
+            self.gather_statistics(0, 1, root_area, &mut statistics);
+        }
+
+        if statistics.leaf_count > 0 {
+            statistics.average_leaf_size = self.primative_indices.len() as f32 / statistics.leaf_count as f32;
+        }
+
+        statistics
+    }
+
+    fn gather_statistics(&self, index: usize, depth: usize, root_area: f32, statistics: &mut BVHStatistics) {
+        statistics.depth = statistics.depth.max(depth);
+
+        let node = self.nodes[index];
+        let relative_area = if root_area > 0.0 {
+            node.aabb.surface_area() / root_area
+        } else {
+            1.0
+        };
+
+        match node.kind {
+            LinearKind::Interior { second_child, .. } => {
+                statistics.sah_cost += TRAVERSAL_COST * relative_area;
+                self.gather_statistics(index + 1, depth + 1, root_area, statistics);
+                self.gather_statistics(second_child, depth + 1, root_area, statistics);
+            },
+            LinearKind::Leaf { count, .. } => {
+                statistics.leaf_count += 1;
+                statistics.sah_cost += INTERSECTION_COST * relative_area * count as f32;
+            },
+        }
+    }
+
+    pub fn get_collision(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<PrimativeInteraction> {
+        self.traverse(ray, t_min, t_max, |index, primative, t_max| {
+            primative
+                .collide(ray, t_min, t_max)
+                .map(|interaction| (interaction.t, PrimativeInteraction { primative: index, interaction }))
+        })
+    }
+
+    // Walks the tree nearest first, calling collide with each primative the
+    // ray might reach and the t of the closest hit so far. collide returns
+    // the t of any closer hit along with whatever should be reported for it,
+    // so callers can find out more than ShapeTrait::collide gives.
+    pub fn traverse<'a, H, F>(&'a self, ray: &Ray, t_min: f32, t_max: f32, mut collide: F) -> Option<H>
+        where F: FnMut(usize, &'a T, f32) -> Option<(f32, H)>
+    {
+        let mut closest = None;
+        let mut t_max = t_max;
 
-            nodes.push(node);
-            nodes.len() - 1
-        }
-
-    }
-    
-    pub fn get_collision(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
-        match self.root {
-            Some(root_n) => self.get_collision_on(root_n, ray, t_min, t_max),
-            None => None,
-        }
-    }
-
-    fn get_collision_on(&self, index: usize, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
-        let current_node = self.nodes[index];
-        match current_node {
-            Node::Branch {left, right, aabb} => {
-                match aabb.hit(ray, t_min, t_max) {
-                    true => {
-                        // get left collision
-                        let left_collsion = match left {
-                            Some(left_index) => self.get_collision_on(left_index, ray, t_min, t_max),
-                            None => None,
-                        };
-
-                        // get right collision, return it or the left collision as appropriate
-                        match (left_collsion, right) {
-                            (Some(left_col), Some(right_index)) => {
-                                let right_col = self.get_collision_on(right_index, ray, t_min, left_col.t);
-                                match right_col {
-                                    Some(_) => right_col,
-                                    None => left_collsion,
-                                }
-                            },
-                            (          None, Some(right_index)) => self.get_collision_on(right_index, ray, t_min, t_max),
-                            (Some(left_col),              None) => left_collsion,
-                            (          None,              None) => None,
+        // Done first, so a near ground plane can cull more of the tree
+        for primative in &self.unbounded {
+            if let Some((t, hit)) = collide(*primative, &self.primatives[*primative], t_max) {
+                t_max = t;
+                closest = Some(hit);
+            }
+        }
+
+        if self.nodes.is_empty() {
+            return closest;
+        }
+
+        let inverse_ray = InverseRay::from(ray);
+
+        let mut to_visit: Vec<usize> = Vec::with_capacity(64);
+        let mut current = 0;
+
+        loop {
+            let node = &self.nodes[current];
+
+            if node.aabb.hit_inverse(&inverse_ray, t_min, t_max) {
+                match node.kind {
+                    LinearKind::Leaf { first, count } => {
+                        for primative in &self.primative_indices[first..first + count] {
+                            if let Some((t, hit)) = collide(*primative, &self.primatives[*primative], t_max) {
+                                t_max = t;
+                                closest = Some(hit);
+                            }
                         }
                     },
-                    false => None,
-                }
-            },
-            Node::Leaf { primative, aabb } => {
-                match aabb.hit(ray, t_min, t_max) {
-                    true  => {
-                        let prima_clone = self.primatives[primative].clone();
-                        prima_clone.collide(ray, t_min, t_max)
+                    LinearKind::Interior { second_child, axis } => {
+                        // Visit the child nearer the ray origin first, so the
+                        // further one can often be culled by the shorter t_max
+                        if inverse_ray.direction_is_negative[axis] {
+                            to_visit.push(current + 1);
+                            current = second_child;
+                        } else {
+                            to_visit.push(second_child);
+                            current += 1;
+                        }
+                        continue;
                     },
-                    false => None,
                 }
-            },
+            }
+
+            match to_visit.pop() {
+                Some(next) => current = next,
+                None => break,
+            }
         }
+
+        closest
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primatives.is_empty()
     }
 }
 
@@ -150,22 +462,316 @@ impl BVHTree {
 #[derive(Copy, Clone)]
 enum Node {
     Branch {
-        left: Option<usize>,
-        right: Option<usize>,
+        left: usize,
+        right: usize,
+        axis: usize,
         aabb: AABB,
     },
     Leaf {
-        primative: usize,
+        first: usize,
+        count: usize,
         aabb: AABB,
     },
 }
 
 
+#[derive(Copy, Clone)]
+struct LinearNode {
+    aabb: AABB,
+    kind: LinearKind,
+}
+
+#[derive(Copy, Clone)]
+enum LinearKind {
+    Interior {
+        second_child: usize,
+        axis: usize,
+    },
+    Leaf {
+        first: usize,
+        count: usize,
+    },
+}
+
+
+fn axis_value(point: &Point3f, axis: usize) -> f32 {
+    match axis {
+        0 => point.x,
+        1 => point.y,
+        _ => point.z,
+    }
+}
+
+fn join_optional(bounds: Option<AABB>, other: Option<AABB>) -> Option<AABB> {
+    match (bounds, other) {
+        (Some(a), Some(b)) => Some(AABB::join(&a, &b)),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    }
+}
+
+
 impl Node {
     fn get_aabb(&self) -> AABB {
         match self {
-            Self::Branch { left, right, aabb} => *aabb,
-            Self::Leaf { primative, aabb } => *aabb,
+            Self::Branch { aabb, .. } => *aabb,
+            Self::Leaf { aabb, .. } => *aabb,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::Rng;
+
+    use crate::bvh_tree::{BVHTree, SplitStrategy};
+    use crate::core::{
+        Colour,
+        Point3f,
+        Vector3f,
+        gen_scale,
+        gen_translate,
+    };
+    use crate::material2::Lambertian;
+    use crate::primative::{Primative, PrimativeInteraction, StaticTransform};
+    use crate::ray::Ray;
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::plane::Plane;
+    use crate::shapes::sphere2::Sphere;
+
+    fn random_spheres(count: usize) -> Vec<Primative> {
+        let mut rng = rand::thread_rng();
+        let material = Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5}));
+
+        (0..count).map(|_| {
+            let radius = rng.gen_range(0.1, 2.0);
+            let centre = Vector3f {
+                x: rng.gen_range(-20.0, 20.0),
+                y: rng.gen_range(-20.0, 20.0),
+                z: rng.gen_range(-20.0, 20.0),
+            };
+
+            Primative {
+                shape: Arc::new(Sphere {radius: 1.0}),
+                transform: Arc::new(StaticTransform {
+                    transform: gen_translate(centre) * gen_scale(radius, radius, radius),
+                }),
+                material: material.clone(),
+            }
+        }).collect()
+    }
+
+    fn random_ray() -> Ray {
+        let mut rng = rand::thread_rng();
+        Ray {
+            a: Point3f {
+                x: rng.gen_range(-30.0, 30.0),
+                y: rng.gen_range(-30.0, 30.0),
+                z: rng.gen_range(-30.0, 30.0),
+            },
+            b: Vector3f {
+                x: rng.gen_range(-1.0, 1.0),
+                y: rng.gen_range(-1.0, 1.0),
+                z: rng.gen_range(-1.0, 1.0),
+            },
+            time: 0.0,
         }
     }
+
+    fn brute_force(primatives: &[Primative], ray: &Ray, t_min: f32, t_max: f32) -> Option<PrimativeInteraction> {
+        let mut closest = None;
+        let mut t_max = t_max;
+
+        for (index, primative) in primatives.iter().enumerate() {
+            if let Some(interaction) = primative.collide(ray, t_min, t_max) {
+                t_max = interaction.t;
+                closest = Some(PrimativeInteraction {
+                    primative: index,
+                    interaction,
+                });
+            }
+        }
+
+        closest
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree: BVHTree = BVHTree::new(vec![], 0.0, 1.0);
+
+        assert!(tree.get_collision(&random_ray(), 0.0, 1000.0).is_none());
+    }
+
+    #[test]
+    fn single_primative() {
+        let primatives = random_spheres(1);
+        let tree = BVHTree::new(primatives.clone(), 0.0, 1.0);
+
+        let centre = primatives[0].transform.generate_transform(0.0).m * Point3f {x: 0.0, y: 0.0, z: 0.0};
+        let ray = Ray {
+            a: Point3f {x: centre.x, y: centre.y, z: centre.z + 50.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
+            time: 0.0,
+        };
+
+        let collision = tree.get_collision(&ray, 0.0, 1000.0).expect("Expected the ray to hit the sphere");
+        assert_eq!(collision.primative, 0);
+    }
+
+    fn ground_plane() -> Primative {
+        Primative {
+            shape: Arc::new(Plane {}),
+            transform: Arc::new(StaticTransform {
+                transform: gen_translate(Vector3f {x: 0.0, y: -5.0, z: 0.0}),
+            }),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5})),
+        }
+    }
+
+    #[test]
+    fn only_unbounded_primatives() {
+        let tree = BVHTree::new(vec![ground_plane()], 0.0, 1.0);
+        let ray = Ray {
+            a: Point3f {x: 100.0, y: 0.0, z: -300.0},
+            b: Vector3f {x: 0.0, y: -1.0, z: 0.0},
+            time: 0.0,
+        };
+
+        let collision = tree.get_collision(&ray, 0.0, 1000.0).expect("Expected the ray to hit the plane");
+        assert_eq!(collision.primative, 0);
+        assert_eq!(collision.interaction.t, 5.0);
+        assert!(tree.bounds().is_none());
+        assert_eq!(tree.statistics().unbounded_count, 1);
+    }
+
+    #[test]
+    fn unbounded_primatives_are_not_dropped() {
+        let mut primatives = random_spheres(100);
+        primatives.insert(40, ground_plane());
+        let tree = BVHTree::new(primatives.clone(), 0.0, 1.0);
+
+        for _ in 0..2000 {
+            let ray = random_ray();
+
+            let expected = brute_force(&primatives, &ray, 0.0, 1000.0).map(|hit| hit.primative);
+            let result = tree.get_collision(&ray, 0.0, 1000.0).map(|hit| hit.primative);
+            assert_eq!(expected, result);
+        }
+
+        let statistics = tree.statistics();
+        assert_eq!(statistics.unbounded_count, 1);
+        assert_eq!(
+            (statistics.average_leaf_size * statistics.leaf_count as f32).round() as usize,
+            100,
+        );
+    }
+
+    fn assert_matches_brute_force(strategy: SplitStrategy) {
+        for _ in 0..5 {
+            let primatives = random_spheres(200);
+            let tree = BVHTree::with_strategy(primatives.clone(), 0.0, 1.0, strategy);
+
+            let mut hits = 0;
+            for _ in 0..2000 {
+                let ray = random_ray();
+
+                let expected = brute_force(&primatives, &ray, 0.0, 1000.0);
+                let result = tree.get_collision(&ray, 0.0, 1000.0);
+
+                match (expected, result) {
+                    (None, None) => (),
+                    (Some(expected), Some(result)) => {
+                        hits += 1;
+                        assert_eq!(
+                            expected.interaction.t,
+                            result.interaction.t,
+                            "BVH hit primative {} but brute force hit {}",
+                            result.primative,
+                            expected.primative,
+                        );
+                    },
+                    (Some(expected), None) => panic!("BVH missed primative {} at t {}", expected.primative, expected.interaction.t),
+                    (None, Some(result)) => panic!("BVH hit primative {} which brute force missed", result.primative),
+                }
+            }
+
+            assert!(hits > 0, "Randomised scene produced no hits to compare");
+        }
+    }
+
+    #[test]
+    fn random_axis_matches_brute_force() {
+        assert_matches_brute_force(SplitStrategy::RandomAxis);
+    }
+
+    #[test]
+    fn sah_matches_brute_force() {
+        assert_matches_brute_force(SplitStrategy::default());
+    }
+
+    #[test]
+    fn sah_with_large_leaves_matches_brute_force() {
+        assert_matches_brute_force(SplitStrategy::SurfaceAreaHeuristic {
+            buckets: 4,
+            max_leaf_size: 16,
+        });
+    }
+
+    #[test]
+    fn random_axis_statistics() {
+        let tree = BVHTree::with_strategy(random_spheres(64), 0.0, 1.0, SplitStrategy::RandomAxis);
+        let statistics = tree.statistics();
+
+        // A full binary tree over 64 single primative leaves
+        assert_eq!(statistics.leaf_count, 64);
+        assert_eq!(statistics.node_count, 127);
+        assert_eq!(statistics.depth, 7);
+        assert_eq!(statistics.average_leaf_size, 1.0);
+    }
+
+    #[test]
+    fn sah_statistics() {
+        let max_leaf_size = 4;
+        let tree = BVHTree::with_strategy(
+            random_spheres(500),
+            0.0,
+            1.0,
+            SplitStrategy::SurfaceAreaHeuristic { buckets: 12, max_leaf_size },
+        );
+        let statistics = tree.statistics();
+
+        assert_eq!(statistics.node_count, 2 * statistics.leaf_count - 1);
+        assert!(statistics.average_leaf_size >= 1.0);
+        assert!(statistics.average_leaf_size <= max_leaf_size as f32);
+        assert_eq!(
+            (statistics.average_leaf_size * statistics.leaf_count as f32).round() as usize,
+            500,
+        );
+    }
+
+    #[test]
+    fn sah_is_cheaper_with_a_large_ground_sphere() {
+        // Roughly the final_weekend layout: one huge sphere under many small ones
+        let mut primatives = random_spheres(300);
+        primatives.push(Primative {
+            shape: Arc::new(Sphere {radius: 1.0}),
+            transform: Arc::new(StaticTransform {
+                transform: gen_translate(Vector3f {x: 0.0, y: -1000.0, z: 0.0}) * gen_scale(1000.0, 1000.0, 1000.0),
+            }),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5})),
+        });
+
+        let random_axis = BVHTree::with_strategy(primatives.clone(), 0.0, 1.0, SplitStrategy::RandomAxis);
+        let sah = BVHTree::with_strategy(primatives, 0.0, 1.0, SplitStrategy::default());
+
+        assert!(
+            sah.statistics().sah_cost < random_axis.statistics().sah_cost,
+            "SAH cost {} was not below random axis cost {}",
+            sah.statistics().sah_cost,
+            random_axis.statistics().sah_cost,
+        );
+    }
 }
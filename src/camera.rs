@@ -6,8 +6,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::core::{
     Point3f,
+    Transform,
     Vector3f,
     cross,
+    gen_perspective,
 };
 use crate::ray::Ray;
 
@@ -64,6 +66,39 @@ impl Camera {
         }
     }
 
+    // Placed by a camera to world transform, looking down the camera's -z
+    // axis with +y up, as gen_look_at sets up. The corners of the image are
+    // the edges of the screen taken back through a perspective projection
+    // whose near plane is at the focus distance.
+    pub fn from_transform(
+        camera_to_world: &Transform,
+        vfov: f32,
+        aspect: f32,
+        aperture: f32,
+        focus_dist: f32,
+        time_0: f32,
+        time_1: f32,
+    ) -> Camera {
+        let screen_to_camera = gen_perspective(vfov, focus_dist, 2.0 * focus_dist).m_inv;
+        let corner = |x: f32, y: f32| {
+            camera_to_world.m * screen_to_camera.project(Point3f {x: aspect * x, y, z: 0.0})
+        };
+        let lower_left_corner = corner(-1.0, -1.0);
+
+        Camera {
+            origin: camera_to_world.m * Point3f {x: 0.0, y: 0.0, z: 0.0},
+            lower_left_corner,
+            horizontal: corner(1.0, -1.0) - lower_left_corner,
+            vertical: corner(-1.0, 1.0) - lower_left_corner,
+            u: (camera_to_world.m * Vector3f {x: 1.0, y: 0.0, z: 0.0}).unit_vector(),
+            v: (camera_to_world.m * Vector3f {x: 0.0, y: 1.0, z: 0.0}).unit_vector(),
+            w: (camera_to_world.m * Vector3f {x: 0.0, y: 0.0, z: 1.0}).unit_vector(),
+            lens_radius: aperture / 2.0,
+            time_0,
+            time_1,
+        }
+    }
+
     pub fn get_ray(self, s: f32, t: f32) -> Ray {
         let rd = self.lens_radius * Vector3f::rnd_in_unit_disc();
         let offset = self.u * rd.x + self.v * rd.y;
@@ -82,3 +117,37 @@ impl Camera {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use crate::camera::Camera;
+    use crate::core::{Point3f, Vector3f, gen_look_at};
+
+    #[test]
+    fn from_transform_matches_create() {
+        let look_from = Point3f {x: 3.0, y: 2.0, z: 5.0};
+        let look_at = Point3f {x: -1.0, y: 0.5, z: 0.0};
+        let up = Vector3f {x: 0.0, y: 1.0, z: 0.0};
+
+        let created = Camera::create(look_from, look_at, up, 40.0, 1.5, 0.0, 4.0, 0.0, 0.0);
+        let placed = Camera::from_transform(
+            &gen_look_at(look_from, look_at, up).inverse(),
+            40.0,
+            1.5,
+            0.0,
+            4.0,
+            0.0,
+            0.0,
+        );
+
+        for &(s, t) in [(0.0, 0.0), (0.5, 0.5), (1.0, 0.25), (0.1, 0.9)].iter() {
+            let expected = created.get_ray(s, t);
+            let ray = placed.get_ray(s, t);
+            assert!(approx_eq!(Point3f, ray.a, expected.a, epsilon = 1e-5));
+            assert!(approx_eq!(Vector3f, ray.b, expected.b, epsilon = 1e-5));
+        }
+    }
+}
@@ -13,13 +13,24 @@ use std::ops::{
 use std::clone::Clone;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct Colour {
     pub r : f32,
     pub g : f32,
     pub b : f32,
 }
 
+impl Colour {
+    // Applies f to each channel
+    pub fn map<F: Fn(f32) -> f32>(self, f: F) -> Colour {
+        Colour {
+            r: f(self.r),
+            g: f(self.g),
+            b: f(self.b),
+        }
+    }
+}
+
 impl Add for Colour {
     type Output = Colour;
 
@@ -9,6 +9,10 @@ use crate::core::{
 };
 
 
+// Pivots this much smaller than the largest entry count as zero
+const SINGULAR_EPSILON: f32 = 1e-7;
+
+
 #[derive(Clone, Copy, Debug)]
 pub struct Matrix4x4<T> {
     pub m : [[T; 4]; 4],
@@ -28,6 +32,76 @@ impl Matrix4x4f {
             ]
         }
     }
+
+    // Expanded along the first row, using the 3x3 minors
+    pub fn determinant(&self) -> f32 {
+        let m = &self.m;
+        // The 3x3 determinant of the bottom three rows in columns a, b and c
+        let minor = |a: usize, b: usize, c: usize| {
+            m[1][a] * (m[2][b] * m[3][c] - m[2][c] * m[3][b])
+                - m[1][b] * (m[2][a] * m[3][c] - m[2][c] * m[3][a])
+                + m[1][c] * (m[2][a] * m[3][b] - m[2][b] * m[3][a])
+        };
+
+        m[0][0] * minor(1, 2, 3) - m[0][1] * minor(0, 2, 3) + m[0][2] * minor(0, 1, 3) - m[0][3] * minor(0, 1, 2)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None when the matrix is
+    // singular, or so close to it that the inverse would be meaningless.
+    pub fn inverse(&self) -> Option<Matrix4x4f> {
+        let largest = self.m
+            .iter()
+            .flat_map(|row| row.iter())
+            .fold(0.0f32, |largest, value| largest.max(value.abs()));
+        if largest == 0.0 || !largest.is_finite() {
+            return None;
+        }
+
+        let mut a = self.m;
+        let mut inverse = indentity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|r0, r1| a[*r0][column].abs().partial_cmp(&a[*r1][column].abs()).unwrap())
+                .unwrap();
+            if a[pivot][column].abs() <= SINGULAR_EPSILON * largest {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for c in 0..4 {
+                a[column][c] *= scale;
+                inverse[column][c] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for c in 0..4 {
+                    a[row][c] -= factor * a[column][c];
+                    inverse[row][c] -= factor * inverse[column][c];
+                }
+            }
+        }
+
+        Some(Matrix4x4f { m: inverse })
+    }
+
+    // Applies the full matrix to a point, dividing through by the w it ends
+    // up with, as is needed for perspective projections
+    pub fn project(&self, p: Point3f) -> Point3f {
+        let w = self.m[3][0] * p.x + self.m[3][1] * p.y + self.m[3][2] * p.z + self.m[3][3];
+        let projected = *self * p;
+
+        Point3f {
+            x: projected.x / w,
+            y: projected.y / w,
+            z: projected.z / w,
+        }
+    }
 }
 
 pub fn indentity() -> Matrix4x4f {
@@ -137,7 +211,9 @@ impl PartialEq for Matrix4x4f {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::Matrix4x4f;
+    use float_cmp::approx_eq;
+
+    use crate::core::{Matrix4x4f, indentity};
 
     // Scenario: Transposing a matrix
     //   Given the following matrix A:
@@ -174,4 +250,125 @@ mod tests {
             }
         );
     }
-}
\ No newline at end of file
+
+    // Scenario: Calculating the determinant of a 4x4 matrix
+    //   Given the following 4x4 matrix A:
+    //     | -2 | -8 |  3 |  5 |
+    //     | -3 |  1 |  7 |  3 |
+    //     |  1 |  2 | -9 |  6 |
+    //     | -6 |  7 |  7 | -9 |
+    //   Then determinant(A) = -4071
+    #[test]
+    fn determinant() {
+        let m = Matrix4x4f { m:
+            [
+                [-2.0, -8.0,  3.0,  5.0],
+                [-3.0,  1.0,  7.0,  3.0],
+                [ 1.0,  2.0, -9.0,  6.0],
+                [-6.0,  7.0,  7.0, -9.0],
+            ]
+        };
+
+        assert_eq!(m.determinant(), -4071.0);
+        assert_eq!(indentity().determinant(), 1.0);
+    }
+
+    // Scenario: Testing a noninvertible matrix for invertibility
+    //   Given the following 4x4 matrix A:
+    //     | -4 |  2 | -2 | -3 |
+    //     |  9 |  6 |  2 |  6 |
+    //     |  0 | -5 |  1 | -5 |
+    //     |  0 |  0 |  0 |  0 |
+    //   Then determinant(A) = 0
+    //     And A is not invertible
+    #[test]
+    fn noninvertible() {
+        let m = Matrix4x4f { m:
+            [
+                [-4.0,  2.0, -2.0, -3.0],
+                [ 9.0,  6.0,  2.0,  6.0],
+                [ 0.0, -5.0,  1.0, -5.0],
+                [ 0.0,  0.0,  0.0,  0.0],
+            ]
+        };
+
+        assert_eq!(m.determinant(), 0.0);
+        assert!(m.inverse().is_none());
+    }
+
+    // Scenario: Calculating the inverse of another matrix
+    //   Given the following 4x4 matrix A:
+    //     |  8 | -5 |  9 |  2 |
+    //     |  7 |  5 |  6 |  1 |
+    //     | -6 |  0 |  9 |  6 |
+    //     | -3 |  0 | -9 | -4 |
+    //   Then inverse(A) is the following 4x4 matrix:
+    //     | -0.15385 | -0.15385 | -0.28205 | -0.53846 |
+    //     | -0.07692 |  0.12308 |  0.02564 |  0.03077 |
+    //     |  0.35897 |  0.35897 |  0.43590 |  0.92308 |
+    //     | -0.69231 | -0.69231 | -0.76923 | -1.92308 |
+    #[test]
+    fn inverse() {
+        let m = Matrix4x4f { m:
+            [
+                [ 8.0, -5.0,  9.0,  2.0],
+                [ 7.0,  5.0,  6.0,  1.0],
+                [-6.0,  0.0,  9.0,  6.0],
+                [-3.0,  0.0, -9.0, -4.0],
+            ]
+        };
+        let expected = [
+            [-0.15385, -0.15385, -0.28205, -0.53846],
+            [-0.07692,  0.12308,  0.02564,  0.03077],
+            [ 0.35897,  0.35897,  0.43590,  0.92308],
+            [-0.69231, -0.69231, -0.76923, -1.92308],
+        ];
+
+        let inverse = m.inverse().unwrap();
+        for (row, expected_row) in inverse.m.iter().zip(expected.iter()) {
+            for (value, expected) in row.iter().zip(expected_row.iter()) {
+                assert!(approx_eq!(f32, *value, *expected, epsilon = 1e-5));
+            }
+        }
+    }
+
+    // Scenario: Multiplying a product by its inverse
+    //   Given the following 4x4 matrix A:
+    //       |  3 | -9 |  7 |  3 |
+    //       |  3 | -8 |  2 | -9 |
+    //       | -4 |  4 |  4 |  1 |
+    //       | -6 |  5 | -1 |  1 |
+    //     And the following 4x4 matrix B:
+    //       |  8 |  2 |  2 |  2 |
+    //       |  3 | -1 |  7 |  0 |
+    //       |  7 |  0 |  5 |  4 |
+    //       |  6 | -2 |  0 |  5 |
+    //     And C ← A * B
+    //   Then C * inverse(B) = A
+    #[test]
+    fn product_by_inverse() {
+        let a = Matrix4x4f { m:
+            [
+                [ 3.0, -9.0,  7.0,  3.0],
+                [ 3.0, -8.0,  2.0, -9.0],
+                [-4.0,  4.0,  4.0,  1.0],
+                [-6.0,  5.0, -1.0,  1.0],
+            ]
+        };
+        let b = Matrix4x4f { m:
+            [
+                [8.0,  2.0, 2.0, 2.0],
+                [3.0, -1.0, 7.0, 0.0],
+                [7.0,  0.0, 5.0, 4.0],
+                [6.0, -2.0, 0.0, 5.0],
+            ]
+        };
+
+        let c = (a * b) * b.inverse().unwrap();
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!(approx_eq!(f32, c.m[row][column], a.m[row][column], epsilon = 1e-4));
+            }
+        }
+    }
+}
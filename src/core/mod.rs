@@ -33,9 +33,16 @@ pub use self::matrix::{
     indentity,
 };
 
+mod quaternion;
+pub use self::quaternion::Quaternion;
+
 mod transforms;
 pub use self::transforms::{
+    Decomposed,
     Transform,
+    gen_identity,
+    gen_look_at,
+    gen_perspective,
     gen_translate,
     gen_scale,
     gen_rotate_x,
@@ -47,6 +54,14 @@ pub use self::transforms::{
 mod colour;
 pub use self::colour::Colour;
 
+mod tone_map;
+pub use self::tone_map::{
+    Encoding,
+    ParseToneMapError,
+    ToneMapOperator,
+    ToneMapping,
+};
+
 use std::ops::{
     Add,
     Mul,
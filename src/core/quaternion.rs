@@ -0,0 +1,402 @@
+use std::cmp::PartialEq;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use float_cmp::{ApproxEq, F32Margin};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    Matrix4x4f,
+    Transform,
+    Vector3f,
+    cross,
+    dot_vv,
+};
+
+
+// A rotation, kept as a unit quaternion so it can be interpolated smoothly
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Quaternion {
+    pub v: Vector3f,
+    pub w: f32,
+}
+
+impl Quaternion {
+    // Turning theta radians anticlockwise about axis, the same as gen_rotate
+    pub fn from_axis_angle(theta: f32, axis: Vector3f) -> Quaternion {
+        let (sin_half, cos_half) = (theta / 2.0).sin_cos();
+        Quaternion {
+            v: axis.unit_vector() * sin_half,
+            w: cos_half,
+        }
+    }
+
+    // Turning x radians about the x axis, then y about the y axis, then z
+    // about the z axis, the same as gen_rotate_z(z) * gen_rotate_y(y) * gen_rotate_x(x)
+    pub fn from_euler(x: f32, y: f32, z: f32) -> Quaternion {
+        Quaternion::from_axis_angle(z, Vector3f {x: 0.0, y: 0.0, z: 1.0})
+            * Quaternion::from_axis_angle(y, Vector3f {x: 0.0, y: 1.0, z: 0.0})
+            * Quaternion::from_axis_angle(x, Vector3f {x: 1.0, y: 0.0, z: 0.0})
+    }
+
+    // The (x, y, z) angles given to from_euler, with y in -pi/2..pi/2. When y
+    // is a quarter turn x and z turn about the same axis, so z is taken as 0.
+    pub fn to_euler(self) -> (f32, f32, f32) {
+        let m = self.to_matrix().m;
+        // atan2 rather than asin, which loses precision near a quarter turn
+        let cos_y = (m[0][0] * m[0][0] + m[1][0] * m[1][0]).sqrt();
+        let y = (-m[2][0]).atan2(cos_y);
+
+        if cos_y < 1e-4 {
+            ((-m[1][2]).atan2(m[1][1]), y, 0.0)
+        } else {
+            (m[2][1].atan2(m[2][2]), y, m[1][0].atan2(m[0][0]))
+        }
+    }
+
+    pub fn conjugate(&self) -> Quaternion {
+        Quaternion {
+            v: -self.v,
+            w: self.w,
+        }
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f32 {
+        dot_vv(&self.v, &other.v) + self.w * other.w
+    }
+
+    pub fn length(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalise(self) -> Quaternion {
+        self * (1.0 / self.length())
+    }
+
+    // From the rotation in the upper 3x3 of m, which must be orthonormal.
+    // Follows pbrt in picking the largest diagonal term to divide by.
+    pub fn from_matrix(m: &Matrix4x4f) -> Quaternion {
+        let m = &m.m;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        if trace > 0.0 {
+            let s = (trace + 1.0).sqrt();
+            let w = s / 2.0;
+            let s = 0.5 / s;
+
+            Quaternion {
+                v: Vector3f {
+                    x: (m[2][1] - m[1][2]) * s,
+                    y: (m[0][2] - m[2][0]) * s,
+                    z: (m[1][0] - m[0][1]) * s,
+                },
+                w,
+            }
+        } else {
+            let i = if m[1][1] > m[0][0] {
+                if m[2][2] > m[1][1] { 2 } else { 1 }
+            } else if m[2][2] > m[0][0] {
+                2
+            } else {
+                0
+            };
+            let j = (i + 1) % 3;
+            let k = (j + 1) % 3;
+
+            let s = (m[i][i] - (m[j][j] + m[k][k]) + 1.0).sqrt();
+            let mut q = [0.0; 3];
+            q[i] = s * 0.5;
+            let s = if s != 0.0 { 0.5 / s } else { s };
+            q[j] = (m[j][i] + m[i][j]) * s;
+            q[k] = (m[k][i] + m[i][k]) * s;
+
+            Quaternion {
+                v: Vector3f {x: q[0], y: q[1], z: q[2]},
+                w: (m[k][j] - m[j][k]) * s,
+            }
+        }
+    }
+
+    pub fn to_matrix(self) -> Matrix4x4f {
+        let (x, y, z, w) = (self.v.x, self.v.y, self.v.z, self.w);
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        let (xy, xz, yz) = (x * y, x * z, y * z);
+        let (wx, wy, wz) = (x * w, y * w, z * w);
+
+        Matrix4x4f { m:
+            [
+                [1.0 - 2.0 * (yy + zz),       2.0 * (xy - wz),       2.0 * (xz + wy), 0.0],
+                [      2.0 * (xy + wz), 1.0 - 2.0 * (xx + zz),       2.0 * (yz - wx), 0.0],
+                [      2.0 * (xz - wy),       2.0 * (yz + wx), 1.0 - 2.0 * (xx + yy), 0.0],
+                [                  0.0,                   0.0,                   0.0, 1.0],
+            ]
+        }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        Transform {
+            m: self.to_matrix(),
+            m_inv: self.conjugate().to_matrix(),
+        }
+    }
+
+    // Spherical linear interpolation, turning at a constant rate from q1 at
+    // t = 0 to q2 at t = 1 the short way round
+    pub fn slerp(t: f32, q1: &Quaternion, q2: &Quaternion) -> Quaternion {
+        let mut q2 = *q2;
+        let mut cos_theta = q1.dot(&q2);
+        if cos_theta < 0.0 {
+            q2 = -q2;
+            cos_theta = -cos_theta;
+        }
+
+        // Nearly parallel, where the perpendicular below is unstable
+        if cos_theta > 0.9995 {
+            return (*q1 * (1.0 - t) + q2 * t).normalise();
+        }
+
+        let theta = cos_theta.min(1.0).acos();
+        let theta_p = theta * t;
+        let perpendicular = (q2 - *q1 * cos_theta).normalise();
+
+        *q1 * theta_p.cos() + perpendicular * theta_p.sin()
+    }
+}
+
+impl Add for Quaternion {
+    type Output = Quaternion;
+
+    fn add(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            v: self.v + rhs.v,
+            w: self.w + rhs.w,
+        }
+    }
+}
+
+impl Sub for Quaternion {
+    type Output = Quaternion;
+
+    fn sub(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            v: self.v - rhs.v,
+            w: self.w - rhs.w,
+        }
+    }
+}
+
+impl Mul<f32> for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: f32) -> Quaternion {
+        Quaternion {
+            v: self.v * rhs,
+            w: self.w * rhs,
+        }
+    }
+}
+
+// Composes rotations like Transform does, so (a * b) turns by b and then a.
+// Renormalise after long chains to stop the rotation drifting into a scale.
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            v: rhs.v * self.w + self.v * rhs.w + cross(&self.v, &rhs.v),
+            w: self.w * rhs.w - dot_vv(&self.v, &rhs.v),
+        }
+    }
+}
+
+impl Neg for Quaternion {
+    type Output = Quaternion;
+
+    fn neg(self) -> Quaternion {
+        Quaternion {
+            v: -self.v,
+            w: -self.w,
+        }
+    }
+}
+
+impl PartialEq for Quaternion {
+    fn eq(&self, rhs: &Quaternion) -> bool {
+        self.v == rhs.v && self.w == rhs.w
+    }
+}
+
+impl ApproxEq for Quaternion {
+    type Margin = F32Margin;
+
+    fn approx_eq<M: Into<Self::Margin>>(self, other: Self, margin: M) -> bool {
+        let margin = margin.into();
+        self.v.approx_eq(other.v, margin)
+            && self.w.approx_eq(other.w, margin)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Matrix4x4f,
+        Quaternion,
+        Vector3f,
+        gen_rotate,
+        gen_rotate_x,
+        gen_rotate_y,
+        gen_rotate_z,
+    };
+
+    const IDENTITY: Quaternion = Quaternion {v: Vector3f {x: 0.0, y: 0.0, z: 0.0}, w: 1.0};
+
+    fn assert_matrices_eq(a: &Matrix4x4f, b: &Matrix4x4f) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!(
+                    approx_eq!(f32, a.m[row][column], b.m[row][column], epsilon = 1e-5),
+                    "{:?} and {:?} differ",
+                    a,
+                    b,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_round_trip() {
+        let axes = [
+            Vector3f {x: 1.0, y: 0.0, z: 0.0},
+            Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            Vector3f {x: 1.0, y: 2.0, z: -3.0},
+        ];
+
+        for axis in axes.iter() {
+            for degrees in [0.0f32, 30.0, 120.0, 179.0, 270.0].iter() {
+                let m = gen_rotate(degrees.to_radians(), *axis).m;
+                let round_trip = Quaternion::from_matrix(&m).to_matrix();
+
+                for row in 0..4 {
+                    for column in 0..4 {
+                        assert!(
+                            approx_eq!(f32, round_trip.m[row][column], m.m[row][column], epsilon = 1e-5),
+                            "{:?} and {:?} differ turning {} degrees",
+                            round_trip,
+                            m,
+                            degrees,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn slerp_turns_at_a_constant_rate() {
+        let axis = Vector3f {x: 0.0, y: 0.0, z: 1.0};
+        let q1 = Quaternion::from_matrix(&gen_rotate(0.0, axis).m);
+        let q2 = Quaternion::from_matrix(&gen_rotate(0.5 * f32::consts::PI, axis).m);
+
+        let halfway = Quaternion::slerp(0.5, &q1, &q2).to_matrix();
+        let expected = gen_rotate(0.25 * f32::consts::PI, axis).m;
+        for row in 0..3 {
+            for column in 0..3 {
+                assert!(approx_eq!(f32, halfway.m[row][column], expected.m[row][column], epsilon = 1e-5));
+            }
+        }
+
+        let end = Quaternion::slerp(1.0, &q1, &q2);
+        assert!(approx_eq!(f32, end.dot(&q2).abs(), 1.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn axis_angle_matches_gen_rotate() {
+        let axis = Vector3f {x: 1.0, y: 2.0, z: -3.0};
+        for degrees in [0.0f32, 45.0, 180.0, 300.0].iter() {
+            let expected = gen_rotate(degrees.to_radians(), axis);
+            let transform = Quaternion::from_axis_angle(degrees.to_radians(), axis).to_transform();
+
+            assert_matrices_eq(&transform.m, &expected.m);
+            assert_matrices_eq(&transform.m_inv, &expected.m_inv);
+            assert!(approx_eq!(
+                Quaternion,
+                Quaternion::from_matrix(&expected.m).normalise(),
+                Quaternion::from_axis_angle(degrees.to_radians(), axis),
+                epsilon = 1e-5
+            ) || approx_eq!(
+                Quaternion,
+                -Quaternion::from_matrix(&expected.m).normalise(),
+                Quaternion::from_axis_angle(degrees.to_radians(), axis),
+                epsilon = 1e-5
+            ));
+        }
+    }
+
+    #[test]
+    fn composes_like_transforms() {
+        let a = Vector3f {x: 0.0, y: 1.0, z: 1.0};
+        let b = Vector3f {x: 1.0, y: 0.0, z: 0.0};
+        let q = Quaternion::from_axis_angle(0.7, a) * Quaternion::from_axis_angle(-1.9, b);
+
+        assert_matrices_eq(&q.to_matrix(), &(gen_rotate(0.7, a) * gen_rotate(-1.9, b)).m);
+        assert!(approx_eq!(f32, q.length(), 1.0, epsilon = 1e-6));
+        assert!(approx_eq!(Quaternion, q * q.conjugate(), IDENTITY, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn renormalising_stops_drift() {
+        let step = Quaternion::from_axis_angle(0.001, Vector3f {x: 1.0, y: 1.0, z: 0.0});
+        let mut q = IDENTITY;
+        for _ in 0..10000 {
+            q = (q * step).normalise();
+        }
+
+        assert!(approx_eq!(f32, q.length(), 1.0, epsilon = 1e-6));
+        assert!(approx_eq!(
+            Quaternion,
+            q,
+            Quaternion::from_axis_angle(10.0, Vector3f {x: 1.0, y: 1.0, z: 0.0}),
+            epsilon = 1e-3
+        ));
+    }
+
+    #[test]
+    fn euler_round_trip() {
+        let angles = [
+            (0.0, 0.0, 0.0),
+            (0.3, -0.5, 2.0),
+            (-2.5, 1.2, -0.1),
+            (1.0, 0.5 * f32::consts::PI, 0.0),
+            (-0.4, -0.5 * f32::consts::PI, 0.0),
+        ];
+
+        for &(x, y, z) in angles.iter() {
+            let q = Quaternion::from_euler(x, y, z);
+            let expected = gen_rotate_z(z) * gen_rotate_y(y) * gen_rotate_x(x);
+            assert_matrices_eq(&q.to_matrix(), &expected.m);
+
+            let (rx, ry, rz) = q.to_euler();
+            assert_matrices_eq(&Quaternion::from_euler(rx, ry, rz).to_matrix(), &expected.m);
+            assert!(approx_eq!(f32, ry, y, epsilon = 1e-3), "{} and {} differ", ry, y);
+        }
+
+        let (x, y, z) = Quaternion::from_euler(0.3, -0.5, 2.0).to_euler();
+        assert!(approx_eq!(f32, x, 0.3, epsilon = 1e-5));
+        assert!(approx_eq!(f32, y, -0.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, z, 2.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn serialises() {
+        let q = Quaternion::from_euler(0.1, 0.2, 0.3);
+        let json = serde_json::to_string(&q).unwrap();
+        let read: Quaternion = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, q);
+
+        let read: Quaternion = serde_json::from_str(r#"{"v": {"x": 0.0, "y": 0.0, "z": 0.0}, "w": 1.0}"#).unwrap();
+        assert_eq!(read, IDENTITY);
+    }
+}
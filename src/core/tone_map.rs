@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::core::Colour;
+
+
+// Squeezes the unbounded linear colours coming out of the renderer into the
+// [0, 1] range a display can show
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    Clamp,
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+// How the tone mapped colour is stored in an 8 bit image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding {
+    Srgb,
+    Gamma(f32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // In stops, so each +1.0 doubles the brightness
+    pub exposure: f32,
+    pub encoding: Encoding,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseToneMapError {
+    input: String,
+}
+
+impl fmt::Display for ParseToneMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\" is not a known tone mapping setting", self.input)
+    }
+}
+
+impl Error for ParseToneMapError {}
+
+impl ToneMapOperator {
+    pub fn apply(self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        match self {
+            ToneMapOperator::Clamp => value.min(1.0),
+            ToneMapOperator::Reinhard => value / (1.0 + value),
+            ToneMapOperator::Aces => {
+                let mapped = (value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14);
+                mapped.clamp(0.0, 1.0)
+            },
+        }
+    }
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = ParseToneMapError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            _ => Err(ParseToneMapError { input: input.to_string() }),
+        }
+    }
+}
+
+impl Encoding {
+    pub fn encode(self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Encoding::Srgb => {
+                if value <= 0.003_130_8 {
+                    12.92 * value
+                } else {
+                    1.055 * value.powf(1.0 / 2.4) - 0.055
+                }
+            },
+            Encoding::Gamma(gamma) => value.powf(1.0 / gamma),
+        }
+    }
+}
+
+// Either "srgb", or the gamma as a number, where 1.0 leaves the values linear
+impl FromStr for Encoding {
+    type Err = ParseToneMapError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if input == "srgb" {
+            return Ok(Encoding::Srgb);
+        }
+
+        match input.parse::<f32>() {
+            Ok(gamma) if gamma > 0.0 => Ok(Encoding::Gamma(gamma)),
+            _ => Err(ParseToneMapError { input: input.to_string() }),
+        }
+    }
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+            encoding: Encoding::Srgb,
+        }
+    }
+}
+
+impl ToneMapping {
+    // Exposed and tone mapped, but still linear
+    pub fn map(&self, colour: Colour) -> Colour {
+        let scale = 2.0f32.powf(self.exposure);
+        let operator = self.operator;
+        colour.map(|value| operator.apply(value * scale))
+    }
+
+    pub fn encode(&self, colour: Colour) -> Colour {
+        let encoding = self.encoding;
+        self.map(colour).map(|value| encoding.encode(value))
+    }
+
+    pub fn to_bytes(self, colour: Colour) -> [u8; 3] {
+        let encoded = self.encode(colour);
+        [
+            (255.99 * encoded.r) as u8,
+            (255.99 * encoded.g) as u8,
+            (255.99 * encoded.b) as u8,
+        ]
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use crate::core::{Colour, Encoding, ToneMapOperator, ToneMapping};
+
+    fn linear(operator: ToneMapOperator) -> ToneMapping {
+        ToneMapping {
+            operator,
+            exposure: 0.0,
+            encoding: Encoding::Gamma(1.0),
+        }
+    }
+
+    #[test]
+    fn bright_samples_do_not_overflow() {
+        let white = Colour { r: 1.0, g: 4.0, b: 1000.0 };
+
+        for operator in &[ToneMapOperator::Clamp, ToneMapOperator::Reinhard, ToneMapOperator::Aces] {
+            let bytes = ToneMapping { operator: *operator, ..ToneMapping::default() }.to_bytes(white);
+            assert!(bytes[0] <= bytes[1] && bytes[1] <= bytes[2], "{:?} {:?}", operator, bytes);
+        }
+        assert_eq!(ToneMapping::default().to_bytes(white), [255, 255, 255]);
+    }
+
+    #[test]
+    fn negative_values_are_black() {
+        let bytes = ToneMapping::default().to_bytes(Colour { r: -1.0, g: 0.0, b: -0.5 });
+        assert_eq!(bytes, [0, 0, 0]);
+    }
+
+    #[test]
+    fn operators() {
+        assert!(approx_eq!(f32, ToneMapOperator::Clamp.apply(0.25), 0.25));
+        assert!(approx_eq!(f32, ToneMapOperator::Clamp.apply(3.0), 1.0));
+        assert!(approx_eq!(f32, ToneMapOperator::Reinhard.apply(1.0), 0.5));
+        assert!(approx_eq!(f32, ToneMapOperator::Reinhard.apply(3.0), 0.75));
+        assert!(approx_eq!(f32, ToneMapOperator::Aces.apply(0.0), 0.0));
+        assert!(approx_eq!(f32, ToneMapOperator::Aces.apply(100.0), 1.0));
+        assert!(ToneMapOperator::Aces.apply(0.5) > ToneMapOperator::Aces.apply(0.4));
+    }
+
+    #[test]
+    fn exposure_in_stops() {
+        let tone_map = ToneMapping { exposure: 1.0, ..linear(ToneMapOperator::Clamp) };
+        let mapped = tone_map.map(Colour { r: 0.25, g: 0.5, b: 0.75 });
+
+        assert!(approx_eq!(f32, mapped.r, 0.5));
+        assert!(approx_eq!(f32, mapped.g, 1.0));
+        assert!(approx_eq!(f32, mapped.b, 1.0));
+    }
+
+    #[test]
+    fn encodings() {
+        assert!(approx_eq!(f32, Encoding::Gamma(1.0).encode(0.5), 0.5));
+        assert!(approx_eq!(f32, Encoding::Gamma(2.0).encode(0.25), 0.5));
+        assert!(approx_eq!(f32, Encoding::Srgb.encode(0.0), 0.0));
+        assert!(approx_eq!(f32, Encoding::Srgb.encode(1.0), 1.0, epsilon = 1e-6));
+        assert!(approx_eq!(f32, Encoding::Srgb.encode(0.002), 0.02584));
+        assert!(approx_eq!(f32, Encoding::Srgb.encode(0.5), 0.735_356_7, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!("aces".parse(), Ok(ToneMapOperator::Aces));
+        assert!("filmic".parse::<ToneMapOperator>().is_err());
+        assert_eq!("srgb".parse(), Ok(Encoding::Srgb));
+        assert_eq!("2.2".parse(), Ok(Encoding::Gamma(2.2)));
+        assert!("0".parse::<Encoding>().is_err());
+        assert!("bright".parse::<Encoding>().is_err());
+    }
+}
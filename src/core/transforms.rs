@@ -1,5 +1,7 @@
-use crate::core::matrix::Matrix4x4f as Matrix;
-use crate::core::vector::Vector3f;
+use crate::core::matrix::{Matrix4x4f as Matrix, indentity};
+use crate::core::point::Point3f;
+use crate::core::quaternion::Quaternion;
+use crate::core::vector::{Vector3f, cross};
 use std::ops::Mul;
 
 #[derive(Clone, Copy)]
@@ -8,6 +10,110 @@ pub struct Transform {
     pub m_inv: Matrix,
 }
 
+// A transform broken into translate * rotate * scale, so each part can be
+// interpolated on its own
+#[derive(Copy, Clone, Debug)]
+pub struct Decomposed {
+    pub translation: Vector3f,
+    pub rotation: Quaternion,
+    // What is left once the rotation is taken out. Usually only a scale, but
+    // it can hold shear.
+    pub scale: Matrix,
+}
+
+impl Transform {
+    // None when m is singular
+    pub fn from_matrix(m: Matrix) -> Option<Transform> {
+        m.inverse().map(|m_inv| Transform { m, m_inv })
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.m_inv,
+            m_inv: self.m,
+        }
+    }
+
+    // Finds the rotation by polar decomposition, as pbrt does. Any projection
+    // in the bottom row is dropped.
+    pub fn decompose(&self) -> Decomposed {
+        let m = self.m;
+        let translation = Vector3f {x: m.m[0][3], y: m.m[1][3], z: m.m[2][3]};
+
+        let mut linear = m;
+        for row in 0..3 {
+            linear.m[row][3] = 0.0;
+            linear.m[3][row] = 0.0;
+        }
+        linear.m[3][3] = 1.0;
+
+        // Averaging with the inverse transpose converges on the nearest
+        // orthonormal matrix
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let inverse_transpose = match rotation.transpose().inverse() {
+                Some(inverse_transpose) => inverse_transpose,
+                None => break,
+            };
+
+            let mut next = rotation;
+            let mut norm: f32 = 0.0;
+            for row in 0..3 {
+                let mut row_sum = 0.0;
+                for column in 0..3 {
+                    next.m[row][column] = 0.5 * (rotation.m[row][column] + inverse_transpose.m[row][column]);
+                    row_sum += (rotation.m[row][column] - next.m[row][column]).abs();
+                }
+                norm = norm.max(row_sum);
+            }
+
+            rotation = next;
+            if norm < 1e-4 {
+                break;
+            }
+        }
+
+        Decomposed {
+            translation,
+            rotation: Quaternion::from_matrix(&rotation),
+            scale: rotation.transpose() * linear,
+        }
+    }
+}
+
+impl Decomposed {
+    pub fn lerp(t: f32, a: &Decomposed, b: &Decomposed) -> Decomposed {
+        let mut scale = a.scale;
+        for row in 0..3 {
+            for column in 0..3 {
+                scale.m[row][column] = (1.0 - t) * a.scale.m[row][column] + t * b.scale.m[row][column];
+            }
+        }
+
+        Decomposed {
+            translation: (1.0 - t) * a.translation + t * b.translation,
+            rotation: Quaternion::slerp(t, &a.rotation, &b.rotation),
+            scale,
+        }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        let rotation = self.rotation.to_matrix();
+        let translate = gen_translate(self.translation);
+
+        // A scale that has passed through zero flattens the object, which
+        // then can't be hit, so the zero matrix stands in for its inverse
+        let scale_inv = self.scale
+            .inverse()
+            .unwrap_or(Matrix { m: [[0.0; 4]; 4] });
+
+        Transform {
+            m: translate.m * rotation * self.scale,
+            m_inv: scale_inv * rotation.transpose() * translate.m_inv,
+        }
+    }
+}
+
 pub fn gen_translate(delta: Vector3f) -> Transform {
     Transform {
         m: Matrix {
@@ -183,3 +289,205 @@ impl Mul for Transform {
         }
     }
 }
+
+// Takes world space into the space of a camera at from, looking down its -z
+// axis towards to, with up roughly along its +y axis
+pub fn gen_look_at(from: Point3f, to: Point3f, up: Vector3f) -> Transform {
+    let forward = (to - from).unit_vector();
+    let left = cross(&forward, &up.unit_vector()).unit_vector();
+    let true_up = cross(&left, &forward);
+
+    let orientation = Matrix {
+        m: [
+            [    left.x,     left.y,     left.z, 0.0],
+            [ true_up.x,  true_up.y,  true_up.z, 0.0],
+            [-forward.x, -forward.y, -forward.z, 0.0],
+            [       0.0,        0.0,        0.0, 1.0],
+        ]
+    };
+    let translate = gen_translate(-Vector3f::from(from));
+
+    Transform {
+        m: orientation * translate.m,
+        m_inv: translate.m_inv * orientation.transpose(),
+    }
+}
+
+// Projects camera space, looking down -z, so the view frustum of fov degrees
+// fills x and y from -1 to 1, and depth runs from 0 at near to 1 at far.
+// Points must be taken through Matrix4x4f::project for the divide by depth.
+pub fn gen_perspective(fov: f32, near: f32, far: f32) -> Transform {
+    let inv_tan = 1.0 / (fov.to_radians() / 2.0).tan();
+    let depth = far / (far - near);
+
+    let m = Matrix {
+        m: [
+            [inv_tan,     0.0,    0.0,           0.0],
+            [    0.0, inv_tan,    0.0,           0.0],
+            [    0.0,     0.0, -depth, -near * depth],
+            [    0.0,     0.0,   -1.0,           0.0],
+        ]
+    };
+    let m_inv = Matrix {
+        m: [
+            [1.0 / inv_tan,           0.0,                   0.0,  0.0],
+            [          0.0, 1.0 / inv_tan,                   0.0,  0.0],
+            [          0.0,           0.0,                   0.0, -1.0],
+            [          0.0,           0.0, -1.0 / (near * depth), 1.0 / near],
+        ]
+    };
+
+    Transform { m, m_inv }
+}
+
+pub fn gen_identity() -> Transform {
+    Transform {
+        m: indentity(),
+        m_inv: indentity(),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Matrix4x4f,
+        Point3f,
+        Transform,
+        Vector3f,
+        gen_look_at,
+        gen_perspective,
+        gen_rotate,
+        gen_scale,
+        gen_translate,
+        indentity,
+    };
+
+    fn assert_matrix_eq(a: &Matrix4x4f, b: &Matrix4x4f) {
+        for row in 0..4 {
+            for column in 0..4 {
+                assert!(
+                    approx_eq!(f32, a.m[row][column], b.m[row][column], epsilon = 1e-4),
+                    "{:?} and {:?} differ",
+                    a,
+                    b,
+                );
+            }
+        }
+    }
+
+    // Both halves should undo each other
+    fn assert_consistent(transform: &Transform) {
+        assert_matrix_eq(&(transform.m * transform.m_inv), &indentity());
+        assert_matrix_eq(&(transform.m_inv * transform.m), &indentity());
+    }
+
+    #[test]
+    fn from_matrix() {
+        let m = (gen_translate(Vector3f {x: 1.0, y: 2.0, z: 3.0}) * gen_scale(2.0, 4.0, 8.0)).m;
+        let transform = Transform::from_matrix(m).unwrap();
+
+        assert_consistent(&transform);
+        assert_eq!(transform.inverse().m, transform.m_inv);
+        assert!(Transform::from_matrix(gen_scale(1.0, 0.0, 1.0).m).is_none());
+    }
+
+    #[test]
+    fn decomposes_and_rebuilds() {
+        let transform = gen_translate(Vector3f {x: 1.0, y: -2.0, z: 3.0})
+            * gen_rotate(1.2, Vector3f {x: 1.0, y: 1.0, z: 0.5})
+            * gen_scale(2.0, 0.5, 3.0);
+        let decomposed = transform.decompose();
+
+        assert_eq!(decomposed.translation, Vector3f {x: 1.0, y: -2.0, z: 3.0});
+        assert!(approx_eq!(f32, decomposed.scale.m[0][0], 2.0, epsilon = 1e-4));
+        assert!(approx_eq!(f32, decomposed.scale.m[1][1], 0.5, epsilon = 1e-4));
+        assert!(approx_eq!(f32, decomposed.scale.m[2][2], 3.0, epsilon = 1e-4));
+        assert!(approx_eq!(f32, decomposed.scale.m[0][1], 0.0, epsilon = 1e-4));
+
+        let rebuilt = decomposed.to_transform();
+        assert_matrix_eq(&rebuilt.m, &transform.m);
+        assert_matrix_eq(&rebuilt.m_inv, &transform.m_inv);
+    }
+
+    // Scenario: The transformation matrix for the default orientation
+    //   Given from ← point(0, 0, 0)
+    //     And to ← point(0, 0, -1)
+    //     And up ← vector(0, 1, 0)
+    //   When t ← view_transform(from, to, up)
+    //   Then t = identity_matrix
+    // Scenario: A view transformation matrix looking in positive z direction
+    //   Then t = scaling(-1, 1, -1)
+    // Scenario: The view transformation moves the world
+    //   Given from ← point(0, 0, 8)
+    //   Then t = translation(0, 0, -8)
+    #[test]
+    fn look_at_orientation() {
+        let origin = Point3f {x: 0.0, y: 0.0, z: 0.0};
+        let up = Vector3f {x: 0.0, y: 1.0, z: 0.0};
+
+        let t = gen_look_at(origin, Point3f {x: 0.0, y: 0.0, z: -1.0}, up);
+        assert_matrix_eq(&t.m, &indentity());
+
+        let t = gen_look_at(origin, Point3f {x: 0.0, y: 0.0, z: 1.0}, up);
+        assert_matrix_eq(&t.m, &gen_scale(-1.0, 1.0, -1.0).m);
+
+        let t = gen_look_at(Point3f {x: 0.0, y: 0.0, z: 8.0}, origin, up);
+        assert_matrix_eq(&t.m, &gen_translate(Vector3f {x: 0.0, y: 0.0, z: -8.0}).m);
+    }
+
+    // Scenario: An arbitrary view transformation
+    //   Given from ← point(1, 3, 2)
+    //     And to ← point(4, -2, 8)
+    //     And up ← vector(1, 1, 0)
+    //   When t ← view_transform(from, to, up)
+    //   Then t is the following 4x4 matrix:
+    //       | -0.50709 | 0.50709 |  0.67612 | -2.36643 |
+    //       |  0.76772 | 0.60609 |  0.12122 | -2.82843 |
+    //       | -0.35857 | 0.59761 | -0.71714 |  0.00000 |
+    //       |  0.00000 | 0.00000 |  0.00000 |  1.00000 |
+    // The book leaves left unnormalised when up isn't perpendicular to the
+    // view, so here the first two rows are a little longer, keeping the
+    // transform rigid
+    #[test]
+    fn look_at_arbitrary() {
+        let t = gen_look_at(
+            Point3f {x: 1.0, y: 3.0, z: 2.0},
+            Point3f {x: 4.0, y: -2.0, z: 8.0},
+            Vector3f {x: 1.0, y: 1.0, z: 0.0},
+        );
+
+        assert_matrix_eq(&t.m, &Matrix4x4f { m:
+            [
+                [-0.51450, 0.51450,  0.68599, -2.40098],
+                [ 0.77892, 0.61494,  0.12299, -2.86972],
+                [-0.35857, 0.59761, -0.71714,  0.00000],
+                [ 0.00000, 0.00000,  0.00000,  1.00000],
+            ]
+        });
+        assert_consistent(&t);
+    }
+
+    #[test]
+    fn perspective() {
+        let t = gen_perspective(90.0, 1.0, 11.0);
+        assert_consistent(&t);
+
+        let near = t.m.project(Point3f {x: 0.5, y: -1.0, z: -1.0});
+        assert!(approx_eq!(f32, near.x, 0.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, near.y, -1.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, near.z, 0.0, epsilon = 1e-5));
+
+        // Twice as far away, so half the size on screen
+        let far = t.m.project(Point3f {x: 11.0, y: 5.5, z: -11.0});
+        assert!(approx_eq!(f32, far.x, 1.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, far.y, 0.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, far.z, 1.0, epsilon = 1e-5));
+
+        let back = t.m_inv.project(far);
+        assert!(approx_eq!(f32, back.z, -11.0, epsilon = 1e-3));
+    }
+}
@@ -83,6 +83,17 @@ impl Vector3f {
         }
     }
 
+    // Spread evenly over the surface of the unit sphere, so adding it to a
+    // unit normal picks directions with a cosine distribution about it
+    pub fn rnd_unit_vector() -> Self {
+        let mut rng = thread_rng();
+        let z = 1.0 - 2.0 * rng.gen::<f32>();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f32::consts::PI * rng.gen::<f32>();
+
+        Self { x: r * phi.cos(), y: r * phi.sin(), z }
+    }
+
     pub fn rnd_in_unit_disc() -> Self {
         let mut rng = thread_rng();
         let sub = Self { x: 1.0, y: 1.0, z: 0.0 };
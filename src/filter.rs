@@ -0,0 +1,199 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+
+// Reconstruction filters weight how much a sample contributes to each pixel
+// centre within radius of it. All of these are separable, so the 2D weight is
+// the product of the 1D weights along x and y.
+pub trait FilterTrait {
+    fn radius(&self) -> f32;
+    fn evaluate_1d(&self, x: f32) -> f32;
+
+    fn evaluate(&self, x: f32, y: f32) -> f32 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+}
+
+pub struct BoxFilter {
+    pub radius: f32,
+}
+
+pub struct TentFilter {
+    pub radius: f32,
+}
+
+pub struct GaussianFilter {
+    pub radius: f32,
+    // Falloff, larger values give a narrower peak
+    pub alpha: f32,
+}
+
+pub struct MitchellFilter {
+    pub radius: f32,
+    pub b: f32,
+    pub c: f32,
+}
+
+pub struct LanczosFilter {
+    pub radius: f32,
+}
+
+pub const FILTER_NAMES: [&str; 5] = ["box", "tent", "gaussian", "mitchell", "lanczos"];
+
+// Builds one of FILTER_NAMES, using the filter's usual radius when none is
+// given
+pub fn filter_from_name(name: &str, radius: Option<f32>) -> Option<Arc<dyn FilterTrait + Send + Sync>> {
+    let filter: Arc<dyn FilterTrait + Send + Sync> = match name {
+        "box" => Arc::new(BoxFilter { radius: radius.unwrap_or(0.5) }),
+        "tent" => Arc::new(TentFilter { radius: radius.unwrap_or(1.0) }),
+        "gaussian" => Arc::new(GaussianFilter { radius: radius.unwrap_or(1.5), alpha: 2.0 }),
+        "mitchell" => Arc::new(MitchellFilter { radius: radius.unwrap_or(2.0), b: 1.0 / 3.0, c: 1.0 / 3.0 }),
+        "lanczos" => Arc::new(LanczosFilter { radius: radius.unwrap_or(2.0) }),
+        _ => return None,
+    };
+    Some(filter)
+}
+
+impl FilterTrait for BoxFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        if x.abs() <= self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+impl FilterTrait for TentFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        (self.radius - x.abs()).max(0.0)
+    }
+}
+
+impl FilterTrait for GaussianFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        // Shifted down so the weight falls to zero at the radius
+        let edge = (-self.alpha * self.radius * self.radius).exp();
+        ((-self.alpha * x * x).exp() - edge).max(0.0)
+    }
+}
+
+impl FilterTrait for MitchellFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        // The cubic is defined over [-2, 2]
+        let x = (2.0 * x / self.radius).abs();
+        let (b, c) = (self.b, self.c);
+
+        let weight = if x > 2.0 {
+            0.0
+        } else if x > 1.0 {
+            (-b - 6.0 * c) * x * x * x
+                + (6.0 * b + 30.0 * c) * x * x
+                + (-12.0 * b - 48.0 * c) * x
+                + (8.0 * b + 24.0 * c)
+        } else {
+            (12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                + (6.0 - 2.0 * b)
+        };
+
+        weight / 6.0
+    }
+}
+
+impl FilterTrait for LanczosFilter {
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    fn evaluate_1d(&self, x: f32) -> f32 {
+        if x.abs() > self.radius {
+            return 0.0;
+        }
+        sinc(x) * sinc(x / self.radius)
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use crate::filter::{
+        FilterTrait,
+        BoxFilter,
+        TentFilter,
+        MitchellFilter,
+        FILTER_NAMES,
+        filter_from_name,
+    };
+
+    #[test]
+    fn filters_vanish_at_radius() {
+        for name in FILTER_NAMES.iter().filter(|name| **name != "box") {
+            let filter = filter_from_name(name, None).unwrap();
+            let radius = filter.radius();
+
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{}", name);
+            assert!(approx_eq!(f32, filter.evaluate_1d(radius), 0.0, epsilon = 1e-5), "{}", name);
+            assert!(approx_eq!(f32, filter.evaluate_1d(-radius), 0.0, epsilon = 1e-5), "{}", name);
+            assert!(approx_eq!(f32, filter.evaluate_1d(radius + 0.5), 0.0), "{}", name);
+            assert!(filter.evaluate_1d(0.0) > filter.evaluate_1d(0.25 * radius), "{}", name);
+        }
+    }
+
+    #[test]
+    fn filters_are_symmetric() {
+        for name in FILTER_NAMES.iter() {
+            let filter = filter_from_name(name, Some(1.5)).unwrap();
+            for x in &[0.1, 0.4, 0.9, 1.3] {
+                assert!(approx_eq!(f32, filter.evaluate_1d(*x), filter.evaluate_1d(-*x)), "{}", name);
+                assert!(approx_eq!(f32, filter.evaluate(*x, 0.2), filter.evaluate(-*x, -0.2)), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_filter() {
+        assert!(filter_from_name("sharpen", None).is_none());
+    }
+
+    #[test]
+    fn box_and_tent() {
+        let box_filter = BoxFilter { radius: 0.5 };
+        assert!(approx_eq!(f32, box_filter.evaluate(0.3, -0.4), 1.0));
+        assert!(approx_eq!(f32, box_filter.evaluate(0.6, 0.0), 0.0));
+
+        let tent = TentFilter { radius: 2.0 };
+        assert!(approx_eq!(f32, tent.evaluate_1d(0.5), 1.5));
+        assert!(approx_eq!(f32, tent.evaluate(1.0, 1.0), 1.0));
+    }
+
+    #[test]
+    fn mitchell_has_negative_lobes() {
+        let mitchell = MitchellFilter { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 };
+
+        // (6 - 2B) / 6 at the centre
+        assert!(approx_eq!(f32, mitchell.evaluate_1d(0.0), 8.0 / 9.0, epsilon = 1e-6));
+        assert!(mitchell.evaluate_1d(1.5) < 0.0);
+    }
+}
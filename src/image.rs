@@ -0,0 +1,358 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::core::{Colour, Encoding, ToneMapping};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm,
+    Png,
+    Hdr,
+}
+
+// A finished framebuffer of linear colours, stored row by row from the top
+// of the image down
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Colour>,
+}
+
+impl ImageFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<ImageFormat> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Image {
+        Image {
+            width,
+            height,
+            pixels: vec![Colour { r: 0.0, g: 0.0, b: 0.0 }; (width * height) as usize],
+        }
+    }
+
+    // Takes the renderer's coordinates, where y = 0 is the bottom row
+    pub fn set_pixel(&mut self, x: u32, y: u32, colour: Colour) {
+        let row = self.height - 1 - y;
+        self.pixels[(row * self.width + x) as usize] = colour;
+    }
+
+    // The tone mapping is only applied to the 8 bit formats, HDR output keeps
+    // the linear values
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat, tone_map: &ToneMapping) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format, tone_map)?;
+        writer.flush()
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W, format: ImageFormat, tone_map: &ToneMapping) -> io::Result<()> {
+        match format {
+            ImageFormat::Ppm => self.write_ppm(writer, tone_map),
+            ImageFormat::Png => self.write_png(writer, tone_map),
+            ImageFormat::Hdr => self.write_hdr(writer),
+        }
+    }
+
+    // Binary P6
+    pub fn write_ppm<W: Write>(&self, writer: &mut W, tone_map: &ToneMapping) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for pixel in &self.pixels {
+            bytes.extend_from_slice(&tone_map.to_bytes(*pixel));
+        }
+
+        writer.write_all(&bytes)
+    }
+
+    // 8 bit RGB, with the encoding recorded in an sRGB or gAMA chunk
+    pub fn write_png<W: Write>(&self, writer: &mut W, tone_map: &ToneMapping) -> io::Result<()> {
+        writer.write_all(&[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])?;
+
+        let mut header = vec![];
+        header.extend_from_slice(&self.width.to_be_bytes());
+        header.extend_from_slice(&self.height.to_be_bytes());
+        // Bit depth 8, colour type 2 (RGB), default compression, filter
+        // and interlace methods
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_png_chunk(writer, b"IHDR", &header)?;
+
+        match tone_map.encoding {
+            // Perceptual rendering intent
+            Encoding::Srgb => write_png_chunk(writer, b"sRGB", &[0])?,
+            Encoding::Gamma(gamma) => {
+                let gamma = (100_000.0 / gamma).round() as u32;
+                write_png_chunk(writer, b"gAMA", &gamma.to_be_bytes())?;
+            },
+        }
+
+        let mut scanlines = Vec::with_capacity(self.pixels.len() * 3 + self.height as usize);
+        for row in self.pixels.chunks(self.width.max(1) as usize) {
+            // Filter type 0, each row is stored as is
+            scanlines.push(0);
+            for pixel in row {
+                scanlines.extend_from_slice(&tone_map.to_bytes(*pixel));
+            }
+        }
+        write_png_chunk(writer, b"IDAT", &zlib_stored(&scanlines))?;
+
+        write_png_chunk(writer, b"IEND", &[])
+    }
+
+    // Radiance RGBE, keeping the full linear range of the render
+    pub fn write_hdr<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            self.height,
+            self.width,
+        )?;
+
+        for row in self.pixels.chunks(self.width.max(1) as usize) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(|pixel| to_rgbe(*pixel)).collect();
+
+            // Run length encoded scanlines are only defined for these widths
+            if self.width < 8 || self.width > 0x7fff {
+                for pixel in &rgbe {
+                    writer.write_all(pixel)?;
+                }
+                continue;
+            }
+
+            writer.write_all(&[2, 2, (self.width >> 8) as u8, (self.width & 0xff) as u8])?;
+            for component in 0..4 {
+                let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+                write_hdr_component(writer, &values)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn to_rgbe(colour: Colour) -> [u8; 4] {
+    let brightest = colour.r.max(colour.g).max(colour.b);
+    if brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // brightest = mantissa * 2^exponent, with the mantissa in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    let mut scale = 2.0f32.powi(-exponent);
+    if brightest * scale >= 1.0 {
+        exponent += 1;
+        scale *= 0.5;
+    }
+    let scale = scale * 256.0;
+
+    [
+        (colour.r.max(0.0) * scale) as u8,
+        (colour.g.max(0.0) * scale) as u8,
+        (colour.b.max(0.0) * scale) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+fn write_hdr_component<W: Write>(writer: &mut W, values: &[u8]) -> io::Result<()> {
+    let mut start = 0;
+    while start < values.len() {
+        let run_length = values[start..]
+            .iter()
+            .take(127)
+            .take_while(|value| **value == values[start])
+            .count();
+
+        if run_length > 2 {
+            writer.write_all(&[128 + run_length as u8, values[start]])?;
+            start += run_length;
+            continue;
+        }
+
+        // Gather values up to the next run worth encoding
+        let mut end = start;
+        while end < values.len() && end - start < 128 {
+            if end + 2 < values.len() && values[end] == values[end + 1] && values[end] == values[end + 2] {
+                break;
+            }
+            end += 1;
+        }
+
+        writer.write_all(&[(end - start) as u8])?;
+        writer.write_all(&values[start..end])?;
+        start = end;
+    }
+
+    Ok(())
+}
+
+fn write_png_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+
+    let mut crc = crc32_update(0xffff_ffff, kind);
+    crc = crc32_update(crc, data);
+    writer.write_all(&(crc ^ 0xffff_ffff).to_be_bytes())
+}
+
+// Wraps data in a zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK: usize = 0xffff;
+
+    let mut output = Vec::with_capacity(data.len() + data.len() / MAX_BLOCK * 5 + 11);
+    output.extend_from_slice(&[0x78, 0x01]);
+
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        output.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = if blocks.peek().is_none() { 1 } else { 0 };
+        let length = block.len() as u16;
+
+        output.push(last);
+        output.extend_from_slice(&length.to_le_bytes());
+        output.extend_from_slice(&(!length).to_le_bytes());
+        output.extend_from_slice(block);
+    }
+
+    output.extend_from_slice(&adler32(data).to_be_bytes());
+    output
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{Colour, ToneMapping};
+    use crate::image::{
+        Image,
+        ImageFormat,
+        adler32,
+        crc32_update,
+        to_rgbe,
+        write_hdr_component,
+        zlib_stored,
+    };
+
+    fn two_by_two() -> Image {
+        let mut image = Image::new(2, 2);
+        image.set_pixel(0, 1, Colour { r: 1.0, g: 0.0, b: 0.0 });
+        image.set_pixel(1, 0, Colour { r: 0.0, g: 0.0, b: 2.0 });
+        image
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(ImageFormat::from_path("out.png"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("renders/out.PPM"), Some(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_path("out.hdr"), Some(ImageFormat::Hdr));
+        assert_eq!(ImageFormat::from_path("out.jpg"), None);
+        assert_eq!(ImageFormat::from_path("out"), None);
+    }
+
+    #[test]
+    fn set_pixel_flips_rows() {
+        let image = two_by_two();
+
+        // Top left, and bottom right
+        assert_eq!(image.pixels[0].r, 1.0);
+        assert_eq!(image.pixels[3].b, 2.0);
+    }
+
+    #[test]
+    fn ppm_output() {
+        let mut output = vec![];
+        two_by_two().write(&mut output, ImageFormat::Ppm, &ToneMapping::default()).unwrap();
+
+        let mut expected = b"P6\n2 2\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn png_output() {
+        let mut output = vec![];
+        two_by_two().write(&mut output, ImageFormat::Png, &ToneMapping::default()).unwrap();
+
+        assert_eq!(&output[..8], &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n']);
+        assert_eq!(&output[12..16], b"IHDR");
+        assert_eq!(&output[16..24], &[0, 0, 0, 2, 0, 0, 0, 2]);
+        // An empty IEND chunk always has the same checksum
+        assert_eq!(&output[output.len() - 12..], &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(crc32_update(0xffff_ffff, b"123456789") ^ 0xffff_ffff, 0xcbf4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn zlib_splits_large_blocks() {
+        let data = vec![7; 70_000];
+        let stream = zlib_stored(&data);
+
+        assert_eq!(stream.len(), 2 + 5 + 65_535 + 5 + 4_465 + 4);
+        assert_eq!(stream[2], 0);
+        assert_eq!(stream[2 + 5 + 65_535], 1);
+    }
+
+    #[test]
+    fn rgbe_conversion() {
+        assert_eq!(to_rgbe(Colour { r: 1.0, g: 1.0, b: 1.0 }), [128, 128, 128, 129]);
+        assert_eq!(to_rgbe(Colour { r: 0.5, g: 0.25, b: 0.0 }), [128, 64, 0, 128]);
+        assert_eq!(to_rgbe(Colour { r: 0.0, g: 0.0, b: 0.0 }), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn hdr_run_length_encoding() {
+        let mut output = vec![];
+        write_hdr_component(&mut output, &[5, 5, 5, 5, 1, 2, 3, 3]).unwrap();
+
+        assert_eq!(output, vec![132, 5, 4, 1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn hdr_header() {
+        let mut output = vec![];
+        two_by_two().write(&mut output, ImageFormat::Hdr, &ToneMapping::default()).unwrap();
+
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n";
+        assert_eq!(&output[..header.len()], &header[..]);
+        // Too narrow to be run length encoded
+        assert_eq!(output.len(), header.len() + 4 * 4);
+    }
+}
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::bvh_tree::BVHTree;
+use crate::primative::{MaterialInteraction, Primative, TransformTrait, to_world};
+use crate::ray::Ray;
+use crate::shapes::base::{Interaction, ShapeTrait};
+
+
+// A group of primatives, and instances of other prototypes, built into its
+// own BVHTree once and then shared by every instance placed from it
+pub struct Prototype {
+    primatives: BVHTree,
+    instances: BVHTree<Instance>,
+}
+
+// A prototype placed in the world. Rays are taken into the prototype's space
+// the same way Primative::collide takes them into a shape's object space.
+#[derive(Clone)]
+pub struct Instance {
+    pub prototype: Arc<Prototype>,
+    pub transform: Arc<dyn TransformTrait + Send + Sync>,
+}
+
+impl Prototype {
+    pub fn new(primatives: Vec<Primative>, instances: Vec<Instance>, time_0: f32, time_1: f32) -> Prototype {
+        Prototype {
+            primatives: BVHTree::new(primatives, time_0, time_1),
+            instances: BVHTree::new(instances, time_0, time_1),
+        }
+    }
+
+    pub fn collide_material(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<MaterialInteraction<'_>> {
+        closest_material(&self.primatives, &self.instances, ray, t_min, t_max)
+    }
+
+    // None when anything inside is unbounded
+    pub fn bounds(&self) -> Option<AABB> {
+        match (self.primatives.is_empty(), self.instances.is_empty()) {
+            (true, true) => None,
+            (false, true) => self.primatives.bounds(),
+            (true, false) => self.instances.bounds(),
+            (false, false) => Some(AABB::join(&self.primatives.bounds()?, &self.instances.bounds()?)),
+        }
+    }
+}
+
+impl Instance {
+    pub fn collide_material(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<MaterialInteraction<'_>> {
+        let transform = self.transform.generate_transform(ray.time);
+
+        self.prototype
+            .collide_material(&(transform.m_inv * ray), t_min, t_max)
+            .map(|hit| MaterialInteraction {
+                material: hit.material,
+                interaction: to_world(&transform, &hit.interaction),
+            })
+    }
+}
+
+impl ShapeTrait for Instance {
+    fn collide(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
+        self.collide_material(ray, t_min, t_max).map(|hit| hit.interaction)
+    }
+
+    fn bounding_box(
+        &self,
+        time_0: f32,
+        time_1: f32,
+    ) -> Option<AABB> {
+        self.prototype
+            .bounds()
+            .map(|bounds| self.transform.motion_bounds(&bounds, time_0, time_1))
+    }
+}
+
+// The nearest hit among both the primatives and the instances, along with the
+// material of the primative that was hit
+pub fn closest_material<'a>(
+    primatives: &'a BVHTree,
+    instances: &'a BVHTree<Instance>,
+    ray: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<MaterialInteraction<'a>> {
+    let closest = primatives.traverse(ray, t_min, t_max, |_, primative, t_max| {
+        primative
+            .collide(ray, t_min, t_max)
+            .map(|interaction| (interaction.t, MaterialInteraction { material: &*primative.material, interaction }))
+    });
+
+    let t_max = closest.map_or(t_max, |hit| hit.interaction.t);
+    let instanced = instances.traverse(ray, t_min, t_max, |_, instance, t_max| {
+        instance
+            .collide_material(ray, t_min, t_max)
+            .map(|hit| (hit.interaction.t, hit))
+    });
+
+    instanced.or(closest)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::sync::Arc;
+
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Colour,
+        Point3f,
+        Vector3f,
+        gen_rotate_y,
+        gen_scale,
+        gen_translate,
+    };
+    use crate::instance::{Instance, Prototype};
+    use crate::material2::{Lambertian, Metal};
+    use crate::primative::{Primative, StaticTransform};
+    use crate::ray::Ray;
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::sphere2::Sphere;
+
+    // A red sphere with a smaller metal one sat on top of it
+    fn snowman() -> Arc<Prototype> {
+        let unit_sphere = Arc::new(Sphere {radius: 1.0});
+
+        Arc::new(Prototype::new(
+            vec![
+                Primative {
+                    shape: unit_sphere.clone(),
+                    transform: Arc::new(StaticTransform {
+                        transform: gen_translate(Vector3f {x: 0.0, y: 1.0, z: 0.0}),
+                    }),
+                    material: Arc::new(Lambertian::colour(Colour {r: 0.8, g: 0.1, b: 0.1})),
+                },
+                Primative {
+                    shape: unit_sphere,
+                    transform: Arc::new(StaticTransform {
+                        transform: gen_translate(Vector3f {x: 0.0, y: 2.5, z: 0.0}) * gen_scale(0.5, 0.5, 0.5),
+                    }),
+                    material: Arc::new(Metal {albedo: Colour {r: 0.8, g: 0.8, b: 0.8}, fuzz: 0.0}),
+                },
+            ],
+            vec![],
+            0.0,
+            1.0,
+        ))
+    }
+
+    fn placed(prototype: &Arc<Prototype>, x: f32, z: f32) -> Instance {
+        Instance {
+            prototype: prototype.clone(),
+            transform: Arc::new(StaticTransform {
+                transform: gen_translate(Vector3f {x, y: 0.0, z}) * gen_rotate_y(0.5 * f32::consts::PI),
+            }),
+        }
+    }
+
+    fn downwards(x: f32, z: f32) -> Ray {
+        Ray {
+            a: Point3f {x, y: 10.0, z},
+            b: Vector3f {x: 0.0, y: -1.0, z: 0.0},
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn instances_share_a_prototype() {
+        let prototype = snowman();
+        let instances: Vec<Instance> = (0..3).map(|i| placed(&prototype, 10.0 * i as f32, 0.0)).collect();
+
+        for instance in &instances {
+            let x = instance.transform.generate_transform(0.0).m * Point3f {x: 0.0, y: 0.0, z: 0.0};
+            let hit = instance.collide(&downwards(x.x, 0.0), 0.0, 100.0).expect("Expected to hit the top sphere");
+            assert!(approx_eq!(f32, hit.t, 7.0, epsilon = 1e-5));
+            assert!(approx_eq!(f32, hit.normal.y, 1.0, epsilon = 1e-5));
+        }
+
+        assert_eq!(Arc::strong_count(&prototype), 4);
+    }
+
+    #[test]
+    fn reports_the_material_hit() {
+        let instance = placed(&snowman(), 5.0, 0.0);
+
+        // Past the top sphere, down onto the side of the bottom one
+        let hit = instance.collide_material(&downwards(5.8, 0.0), 0.0, 100.0).unwrap();
+        assert!(approx_eq!(f32, hit.interaction.t, 8.4, epsilon = 1e-5));
+        let result = hit.material.scatter(&downwards(5.8, 0.0), &hit.interaction);
+        assert!(approx_eq!(f32, result.atten.g, 0.1));
+
+        let hit = instance.collide_material(&downwards(5.0, 0.0), 0.0, 100.0).unwrap();
+        let result = hit.material.scatter(&downwards(5.0, 0.0), &hit.interaction);
+        assert!(approx_eq!(f32, result.atten.g, 0.8));
+
+        assert!(instance.collide_material(&downwards(0.0, 0.0), 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn nested_instances() {
+        let prototype = snowman();
+        let row = Arc::new(Prototype::new(
+            vec![],
+            (0..4).map(|i| placed(&prototype, 3.0 * i as f32, 0.0)).collect(),
+            0.0,
+            1.0,
+        ));
+        let rows: Vec<Instance> = (0..4).map(|j| Instance {
+            prototype: row.clone(),
+            transform: Arc::new(StaticTransform {
+                transform: gen_translate(Vector3f {x: 0.0, y: 0.0, z: 3.0 * j as f32}),
+            }),
+        }).collect();
+
+        let hit = rows[2].collide(&downwards(6.0, 6.0), 0.0, 100.0).unwrap();
+        assert!(approx_eq!(f32, hit.t, 7.0, epsilon = 1e-5));
+        assert!(rows[2].collide(&downwards(6.0, 3.0), 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let instance = placed(&snowman(), 5.0, 2.0);
+        let bounds = instance.bounding_box(0.0, 1.0).unwrap();
+
+        assert!(approx_eq!(f32, bounds.minimum.x, 4.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.maximum.x, 6.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.minimum.y, 0.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.maximum.y, 3.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.minimum.z, 1.0, epsilon = 1e-5));
+
+        let empty = Instance {prototype: Arc::new(Prototype::new(vec![], vec![], 0.0, 1.0)), ..instance};
+        assert!(empty.bounding_box(0.0, 1.0).is_none());
+    }
+}
@@ -1,9 +1,21 @@
+pub mod accumulator;
 pub mod aabb;
+pub mod animated_transform;
+pub mod bvh_tree;
 pub mod core;
+pub mod filter;
+pub mod image;
+pub mod instance;
+pub mod light;
 pub mod camera;
 pub mod material;
+pub mod material2;
+pub mod obj;
+pub mod primative;
 pub mod ray;
 pub mod render;
+pub mod sample_stream;
 pub mod scene;
 pub mod vector;
-pub mod shapes;
\ No newline at end of file
+pub mod shapes;
+pub mod textures;
@@ -0,0 +1,185 @@
+use std::f32;
+
+use crate::core::{
+    Colour,
+    Point3f,
+    Vector3f,
+    dot_vv,
+};
+
+
+// Light from a source with no area, reaching a point along one direction only
+#[derive(Copy, Clone, Debug)]
+pub struct Illumination {
+    // Towards the light. For lights a finite distance away t = 1 reaches the
+    // light; for those infinitely far away it is a unit vector.
+    pub direction: Vector3f,
+    // How far along direction a shadow ray has to reach to get to the light
+    pub t_max: f32,
+    // Arriving at the point, before the cosine at the surface it lands on
+    pub radiance: Colour,
+}
+
+// Lights that can't be hit, like points and suns, so they are only ever found
+// by shadow rays from the surfaces they light
+pub trait LightTrait {
+    // None when the light gives nothing to p
+    fn illuminate(&self, p: &Point3f) -> Option<Illumination>;
+}
+
+// Shines equally in every direction, falling off with the square of distance
+pub struct PointLight {
+    pub position: Point3f,
+    pub intensity: Colour,
+}
+
+impl LightTrait for PointLight {
+    fn illuminate(&self, p: &Point3f) -> Option<Illumination> {
+        let direction = Vector3f::from(self.position - *p);
+
+        Some(Illumination {
+            direction,
+            t_max: 1.0,
+            radiance: self.intensity / direction.squared_length(),
+        })
+    }
+}
+
+// A point light shining only within cone_angle of its axis. It is at full
+// strength out to the falloff start, then fades smoothly to nothing at the
+// edge of the cone.
+pub struct SpotLight {
+    pub position: Point3f,
+    pub intensity: Colour,
+    axis: Vector3f,
+    cos_cone_angle: f32,
+    cos_falloff_start: f32,
+}
+
+impl SpotLight {
+    // The angles are from the axis, in degrees
+    pub fn new(
+        position: Point3f,
+        look_at: Point3f,
+        intensity: Colour,
+        cone_angle: f32,
+        falloff_start: f32,
+    ) -> SpotLight {
+        SpotLight {
+            position,
+            intensity,
+            axis: Vector3f::from(look_at - position).unit_vector(),
+            cos_cone_angle: cone_angle.to_radians().cos(),
+            cos_falloff_start: falloff_start.min(cone_angle).to_radians().cos(),
+        }
+    }
+
+    fn falloff(&self, cos_theta: f32) -> f32 {
+        if cos_theta <= self.cos_cone_angle {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+
+        let t = (cos_theta - self.cos_cone_angle) / (self.cos_falloff_start - self.cos_cone_angle);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl LightTrait for SpotLight {
+    fn illuminate(&self, p: &Point3f) -> Option<Illumination> {
+        let direction = Vector3f::from(self.position - *p);
+        let distance_squared = direction.squared_length();
+        let cos_theta = -dot_vv(&direction, &self.axis) / distance_squared.sqrt();
+
+        let falloff = self.falloff(cos_theta);
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        Some(Illumination {
+            direction,
+            t_max: 1.0,
+            radiance: falloff * self.intensity / distance_squared,
+        })
+    }
+}
+
+// A sun, infinitely far away, so its light arrives everywhere from the same
+// direction at the same strength
+pub struct DirectionalLight {
+    // The way the light travels, away from the sun
+    pub direction: Vector3f,
+    pub radiance: Colour,
+}
+
+impl LightTrait for DirectionalLight {
+    fn illuminate(&self, _p: &Point3f) -> Option<Illumination> {
+        Some(Illumination {
+            direction: -self.direction.unit_vector(),
+            t_max: f32::INFINITY,
+            radiance: self.radiance,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use crate::core::{Colour, Point3f, Vector3f};
+    use crate::light::{DirectionalLight, LightTrait, PointLight, SpotLight};
+
+    const WHITE: Colour = Colour {r: 1.0, g: 1.0, b: 1.0};
+
+    #[test]
+    fn point_light_falls_off_with_distance() {
+        let light = PointLight {position: Point3f {x: 0.0, y: 4.0, z: 0.0}, intensity: 8.0 * WHITE};
+
+        let near = light.illuminate(&Point3f {x: 0.0, y: 2.0, z: 0.0}).unwrap();
+        assert_eq!(near.radiance, 2.0 * WHITE);
+        assert_eq!(near.direction, Vector3f {x: 0.0, y: 2.0, z: 0.0});
+        assert_eq!(near.t_max, 1.0);
+
+        let far = light.illuminate(&Point3f {x: 0.0, y: 0.0, z: 0.0}).unwrap();
+        assert_eq!(far.radiance, 0.5 * WHITE);
+    }
+
+    #[test]
+    fn spot_light_cone() {
+        let light = SpotLight::new(
+            Point3f {x: 0.0, y: 1.0, z: 0.0},
+            Point3f {x: 0.0, y: 0.0, z: 0.0},
+            WHITE,
+            45.0,
+            30.0,
+        );
+        let at = |x: f32| light.illuminate(&Point3f {x, y: 0.0, z: 0.0});
+
+        // Inside the falloff start it is a plain point light
+        assert_eq!(at(0.0).unwrap().radiance, WHITE);
+        let inside = at(0.5).unwrap().radiance;
+        assert!(approx_eq!(f32, inside.r, 1.0 / 1.25, epsilon = 1e-6));
+
+        // 40 degrees out, part way through fading
+        let fading = at(40.0f32.to_radians().tan()).unwrap().radiance;
+        assert!(fading.r > 0.0 && fading.r < 1.0 / (1.0 + 40.0f32.to_radians().tan().powi(2)));
+
+        assert!(at(1.5).is_none());
+        assert!(light.illuminate(&Point3f {x: 0.0, y: 2.0, z: 0.0}).is_none());
+    }
+
+    #[test]
+    fn directional_light_is_everywhere() {
+        let light = DirectionalLight {direction: Vector3f {x: 0.0, y: -2.0, z: 0.0}, radiance: 3.0 * WHITE};
+
+        for p in [Point3f {x: 0.0, y: 0.0, z: 0.0}, Point3f {x: 100.0, y: -50.0, z: 7.0}].iter() {
+            let illumination = light.illuminate(p).unwrap();
+            assert_eq!(illumination.direction, Vector3f {x: 0.0, y: 1.0, z: 0.0});
+            assert_eq!(illumination.radiance, 3.0 * WHITE);
+            assert!(illumination.t_max.is_infinite());
+        }
+    }
+}
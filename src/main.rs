@@ -1,46 +1,55 @@
 use std::f32;
-use std::io::{self, Read};
-use std::sync::{Arc, mpsc};
-use std::thread;
+use std::io::{self, BufWriter, Write};
+use std::process;
+use std::sync::Arc;
 use std::time::Instant;
 
-use rand::thread_rng;
-use rand::Rng;
 use clap::{Arg, App};
 
 use indicatif::{HumanDuration, ProgressBar, ProgressStyle};
 use console::style;
 
 mod aabb;
+mod animated_transform;
 mod bvh_tree;
 mod camera;
 mod core;
+mod image;
+mod instance;
+mod light;
 mod material2;
 mod material;
+mod obj;
 mod primative;
 mod ray;
 mod render;
 mod scene2;
 mod scene;
+mod scene_description;
 mod scene_generator;
 mod shapes;
 mod vector;
 mod textures;
+mod tile_renderer;
 
 
-use crate::core::{Point3f, Normal3f, Colour};
+use crate::core::{Point3f, Normal3f, Colour, Encoding, ToneMapOperator, ToneMapping};
 use crate::material::{
     Material,
     MaterialHit,
     HitRecord
 };
 use crate::ray::Ray;
-use crate::render::RenderResult;
 use crate::shapes::base::Hitable;
 use crate::scene::HitList;
 
-use crate::scene2::{Scene, calculate_colour};
-use crate::scene_generator::three_sphere;
+use crate::bvh_tree::SplitStrategy;
+use crate::render::{RenderResult, SampleWriter, StreamHeader};
+use crate::image::{Image, ImageFormat};
+use crate::scene2::Integrator;
+use crate::scene_description::{load_scene, load_scene_file};
+use crate::scene_generator::{cornell_box, final_weekend, forest, three_sphere};
+use crate::tile_renderer::{RenderSettings, render};
 
 
 fn colour(ray : &Ray, world: &HitList, depth : i32) -> Colour {
@@ -69,16 +78,6 @@ fn colour(ray : &Ray, world: &HitList, depth : i32) -> Colour {
     (1.0 - t) * Colour { r: 1.0, g: 1.0, b: 1.0 } + t * Colour { r: 0.5, g: 0.7, b: 1.0 }
 }
 
-fn sample_range(
-    thread_number: u32,
-    total_threads : u32,
-    total_samples: u32
-) -> (u32, u32) {
-    let from = thread_number * total_samples / total_threads;
-    let to = (thread_number + 1) * total_samples / total_threads;
-    (from, to)
-}
-
 // fn main() {
 //     let matches = App::new("Ray Tracer")
 //        .version("0.1")
@@ -199,6 +198,46 @@ fn sample_range(
 //     );
 // }
 
+enum SampleStream<W: Write> {
+    Off,
+    Json(W),
+    Binary(SampleWriter<W>),
+}
+
+impl<W: Write> SampleStream<W> {
+    fn write(&mut self, samples: &[RenderResult]) -> io::Result<()> {
+        match self {
+            SampleStream::Off => Ok(()),
+            SampleStream::Json(writer) => {
+                for sample in samples {
+                    serde_json::to_writer(&mut *writer, sample)?;
+                    writer.write_all(b"\n")?;
+                }
+                Ok(())
+            },
+            SampleStream::Binary(writer) => {
+                for sample in samples {
+                    writer.write(sample)?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            SampleStream::Off => Ok(()),
+            SampleStream::Json(writer) => writer.flush(),
+            SampleStream::Binary(writer) => writer.flush(),
+        }
+    }
+}
+
+fn stream_failed(err: io::Error) -> ! {
+    eprintln!("{} could not write samples: {}", style("Error:").bold().red(), err);
+    process::exit(1);
+}
+
 fn main() {
     eprintln!("{} Initialising...", style("[0/2]").bold().dim());
     let matches = App::new("Ray Tracer")
@@ -230,17 +269,98 @@ fn main() {
                 .short("f")
                 .long("file")
                 .value_name("FILE")
-                .help("Load from file (not yet implemented)"))
+                .help("Load scene description from file, or - for stdin")
+                .takes_value(true))
+        .arg(Arg::with_name("scene")
+                .long("scene")
+                .default_value("three_sphere")
+                .possible_values(&["three_sphere", "final_weekend", "cornell_box", "forest"])
+                .value_name("NAME")
+                .help("Built in scene to render when no file is given")
+                .takes_value(true))
+        .arg(Arg::with_name("bvh")
+                .short("b")
+                .long("bvh")
+                .default_value("sah")
+                .possible_values(&["sah", "random"])
+                .value_name("STRATEGY")
+                .help("How the BVH splits primatives")
+                .takes_value(true))
+        .arg(Arg::with_name("integrator")
+                .long("integrator")
+                .default_value("path")
+                .possible_values(&["path", "bounce"])
+                .value_name("INTEGRATOR")
+                .help("Path trace sampling the lights directly, or only bounce rays until they find a light")
+                .takes_value(true))
+        .arg(Arg::with_name("tile_size")
+                .short("t")
+                .long("tile-size")
+                .default_value("16")
+                .value_name("INT")
+                .help("Width and height of the tiles handed to each render thread")
+                .takes_value(true))
+        .arg(Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Write the finished image to a .png, .ppm or .hdr file")
+                .takes_value(true))
+        .arg(Arg::with_name("stream")
+                .long("stream")
+                .help("Stream every sample to stdout, even when writing an image")
+                .takes_value(false))
+        .arg(Arg::with_name("json")
+                .long("json")
+                .help("Stream samples as JSON lines instead of the binary format")
+                .takes_value(false))
+        .arg(Arg::with_name("tone_map")
+                .long("tone-map")
+                .default_value("clamp")
+                .possible_values(&["clamp", "reinhard", "aces"])
+                .value_name("OPERATOR")
+                .help("How colours brighter than white are brought into range")
+                .takes_value(true))
+        .arg(Arg::with_name("exposure")
+                .long("exposure")
+                .default_value("0")
+                .value_name("STOPS")
+                .help("Brightens or darkens the image before tone mapping")
+                .validator(|value| value.parse::<f32>().map(|_| ()).map_err(|err| err.to_string()))
+                .takes_value(true))
+        .arg(Arg::with_name("gamma")
+                .long("gamma")
+                .default_value("srgb")
+                .value_name("srgb|FLOAT")
+                .help("Encoding of 8 bit output, either srgb or a gamma where 1.0 is linear")
+                .validator(|value| value.parse::<Encoding>().map(|_| ()).map_err(|err| err.to_string()))
+                .takes_value(true))
        .get_matches();
 
     let started = Instant::now();
 
-    let NTHREADS = num_cpus::get() as u32;
-    eprintln!("{} Running with {} processor(s)...", style("[1/2]").bold().dim(), NTHREADS);
+    let threads = num_cpus::get();
+    eprintln!("{} Running with {} processor(s)...", style("[1/2]").bold().dim(), threads);
 
     let n_x : u32 = matches.value_of("width").unwrap().parse::<u32>().unwrap();
     let n_y : u32 = matches.value_of("height").unwrap().parse::<u32>().unwrap();
 
+    let tone_map = ToneMapping {
+        operator: matches.value_of("tone_map").unwrap().parse::<ToneMapOperator>().unwrap(),
+        exposure: matches.value_of("exposure").unwrap().parse::<f32>().unwrap(),
+        encoding: matches.value_of("gamma").unwrap().parse::<Encoding>().unwrap(),
+    };
+
+    let output = matches.value_of("output").map(|path| {
+        match ImageFormat::from_path(path) {
+            Some(format) => (path, format),
+            None => {
+                eprintln!("{} cannot tell the image format of {}, expected .png, .ppm or .hdr", style("Error:").bold().red(), path);
+                process::exit(1);
+            },
+        }
+    });
+
     // eprintln!(
     //     "{} Parsing scene...",
     //     style("[1/2]").bold().dim(),
@@ -249,14 +369,61 @@ fn main() {
     // io::stdin().read_to_string(&mut buffer);
     // let input_scene: scene::Scene = serde_json::from_str(&buffer).unwrap();
 
-    eprintln!("{} Generating scene...", style("[1/2]").bold().dim());
-
-    let scene = three_sphere(0.0, 1.0 / 30.0);
+    let aspect = (n_x as f32) / (n_y as f32);
+
+    let strategy = if matches.value_of("bvh") == Some("random") {
+        SplitStrategy::RandomAxis
+    } else {
+        SplitStrategy::default()
+    };
+
+    let scene = match matches.value_of("file") {
+        Some(path) => {
+            eprintln!("{} Loading scene from {}...", style("[1/2]").bold().dim(), path);
+
+            let loaded = if path == "-" {
+                load_scene(io::stdin().lock(), aspect, strategy)
+            } else {
+                load_scene_file(path, aspect, strategy)
+            };
+
+            match loaded {
+                Ok(scene) => scene,
+                Err(err) => {
+                    eprintln!("{} {}", style("Error:").bold().red(), err);
+                    process::exit(1);
+                },
+            }
+        },
+        None => {
+            eprintln!("{} Generating scene...", style("[1/2]").bold().dim());
+            match matches.value_of("scene") {
+                Some("final_weekend") => final_weekend(0.0, 1.0, strategy),
+                Some("cornell_box") => cornell_box(0.0, 1.0, strategy),
+                Some("forest") => forest(0.0, 1.0, strategy),
+                _ => three_sphere(0.0, 1.0 / 30.0, strategy),
+            }
+        },
+    };
 
-    let _aspect = (n_x as f32) / (n_y as f32);
+    let statistics = scene.bvh_statistics();
+    eprintln!(
+        "{} BVH over {} primatives with {} nodes, depth {}, {:.2} primatives per leaf, SAH cost {:.2}, {} unbounded, {} instances, {} lights",
+        style("[1/2]").bold().dim(),
+        scene.primatives.len(),
+        statistics.node_count,
+        statistics.depth,
+        statistics.average_leaf_size,
+        statistics.sah_cost,
+        statistics.unbounded_count,
+        scene.instances.len(),
+        scene.light_count(),
+    );
 
     let aa_samples : u32 = matches.value_of("aa_samples").unwrap().parse::<u32>().unwrap();
 
+    let (time_0, time_1) = scene.time_range();
+
     // let arc_scene = Arc::new(input_scene);
     let arc_scene = Arc::new(scene);
 
@@ -331,59 +498,64 @@ fn main() {
     //     println!("{}", serialized);
     // }
 
-    let mut ray_results : Vec<RenderResult> = vec![];
-    for y_coord in (0..n_y).rev() {
-        for x_coord in 0..n_x {
-            let mut handles = vec![];
-            let (tx, rx) = mpsc::channel();
-
-            for thread in 0..NTHREADS {
-                let arc_scene_n = Arc::clone(&arc_scene);
-                let tx_n = mpsc::Sender::clone(&tx);
-
-                let handle = thread::spawn(move || {
-                    let mut rng = thread_rng();
-
-                    let (from, to) = sample_range(thread, NTHREADS, aa_samples);
-                    for _ in from..to {
-                        let rand_x : f32 = rng.gen::<f64>() as f32;
-                        let rand_y : f32 = rng.gen::<f64>() as f32;
-
-                        let x_coord_precise = rand_x + x_coord as f32;
-                        let y_coord_precise = rand_y + y_coord as f32;
-
-                        let u: f32 = x_coord_precise / n_x as f32;
-                        let v: f32 = y_coord_precise / n_y as f32;
-
-                        let ray = &arc_scene_n.camera.get_ray(u, v);
-
-                        let colour_result = calculate_colour(&arc_scene_n, ray, 0);
-                        let result = RenderResult {
-                            x_coord: x_coord_precise,
-                            y_coord: y_coord_precise,
-                            time: ray.time,
-                            colour: colour_result,
-                        };
-                        tx_n.send(result).unwrap();
-                    }
-                });
-                handles.push(handle);
-            }
+    let stream = output.is_none() || matches.is_present("stream");
+    let settings = RenderSettings {
+        width: n_x,
+        height: n_y,
+        samples: aa_samples,
+        tile_size: matches.value_of("tile_size").unwrap().parse::<u32>().unwrap(),
+        threads,
+        keep_samples: stream,
+        integrator: match matches.value_of("integrator") {
+            Some("bounce") => Integrator::Bounce,
+            _ => Integrator::Path,
+        },
+    };
+
+    let stdout = io::stdout();
+    let mut sample_stream = if !stream {
+        SampleStream::Off
+    } else if matches.is_present("json") {
+        SampleStream::Json(BufWriter::new(stdout.lock()))
+    } else {
+        let header = StreamHeader {
+            width: n_x,
+            height: n_y,
+            samples: aa_samples,
+            time_0,
+            time_1,
+        };
+        match SampleWriter::new(BufWriter::new(stdout.lock()), &header) {
+            Ok(writer) => SampleStream::Binary(writer),
+            Err(err) => stream_failed(err),
+        }
+    };
 
-            drop(tx);
+    let mut image = Image::new(n_x, n_y);
+    render(arc_scene, settings, |result| {
+        if let Err(err) = sample_stream.write(&result.samples) {
+            pb.finish_and_clear();
+            stream_failed(err);
+        }
 
-            for handle in handles {
-                handle.join().unwrap();
-            }
+        let tile = result.tile;
+        for (index, colour) in result.pixels.iter().enumerate() {
+            let index = index as u32;
+            image.set_pixel(tile.x0 + index % tile.width, tile.y0 + index / tile.width, *colour);
+        }
+        pb.inc((tile.pixel_count() * aa_samples).into());
+    });
 
-            let mut received_count = 0;
-            for received in rx.iter() {
-                received_count += 1;
-                ray_results.push(received);
-                let serialized = serde_json::to_string(&received).unwrap();
-                println!("{}", serialized);
-            }
-            pb.inc(received_count);
+    if let Err(err) = sample_stream.flush() {
+        pb.finish_and_clear();
+        stream_failed(err);
+    }
+
+    if let Some((path, format)) = output {
+        if let Err(err) = image.save(path, format, &tone_map) {
+            pb.finish_and_clear();
+            eprintln!("{} could not write {}: {}", style("Error:").bold().red(), path, err);
+            process::exit(1);
         }
     }
 
@@ -395,23 +567,3 @@ fn main() {
 
     // eprintln!()
 }
-
-#[cfg(test)]
-mod main_tests {
-    use rand::thread_rng;
-    use rand::Rng;
-
-    #[test]
-    fn test_coordinate_bounding() {
-        let mut rng = thread_rng();
-        let initial_cord = 2000;
-        for attempt in 0..100000 {
-            let init_coord_f32 = initial_cord as f32;
-            let rnd_f32 = rng.gen::<f64>() as f32;
-            let f32_coord = init_coord_f32 + rnd_f32;
-            let converted_back = f32_coord as usize;
-
-            assert_eq!(converted_back, initial_cord, "Attempt {} converting {:.60} + {:.60} (becomes {:.60}) into int", attempt, init_coord_f32, rnd_f32, f32_coord)
-        }
-    }
-}
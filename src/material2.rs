@@ -2,6 +2,7 @@ use rand::Rng;
 
 use serde::{Deserialize, Serialize};
 
+use std::f32;
 use std::sync::Arc;
 
 use crate::core::{
@@ -33,6 +34,25 @@ pub trait MaterialTrait {
     // fn generate_response(&self) -> MaterialResult;
     // fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> ScatterResult;
     fn scatter(&self, ray_in: &Ray, interaction: &Interaction) -> ScatterResult;
+
+    // Light given off at the interaction, whichever way it is seen from.
+    // Only lights give off any.
+    fn emitted(&self, _interaction: &Interaction) -> Colour {
+        Colour { r: 0.0, g: 0.0, b: 0.0 }
+    }
+
+    // Whether emitted gives off anything, so the scene samples it as a light
+    fn is_light(&self) -> bool {
+        false
+    }
+
+    // The pdf, by solid angle, of scatter picking direction. Its attenuation
+    // must be the BSDF times the cosine over this pdf, so the two can weight
+    // light sampled directly. Materials that only scatter in one direction,
+    // like mirrors and glass, give None and are never lit directly.
+    fn scattering_pdf(&self, _ray_in: &Ray, _interaction: &Interaction, _direction: &Vector3f) -> Option<f32> {
+        None
+    }
 }
 
 // pub struct Lambertian<'a> {
@@ -45,19 +65,31 @@ pub struct Lambertian {
 // impl MaterialTrait for Lambertian<'_> {
 impl MaterialTrait for Lambertian {
     // fn scatter(&self, ray_in: &Ray, hit: &HitRecord) -> ScatterResult {
+    // A cosine weighted direction about the normal, for which the
+    // attenuation is exactly the albedo
     fn scatter(&self, ray_in: &Ray, interaction: &Interaction) -> ScatterResult {
-        let target = interaction.p + interaction.normal + Vector3f::rnd_in_unit_sphere();
+        let normal = Vector3f::from(interaction.normal.unit_vector());
+        let mut direction = normal + Vector3f::rnd_unit_vector();
+        // The random vector can all but cancel the normal out
+        if direction.squared_length() < 1e-8 {
+            direction = normal;
+        }
 
         ScatterResult {
             hit : true,
             atten : self.albedo.value(interaction.u, interaction.v, interaction.p),
             ray_out : Ray {
                 a: interaction.p,
-                b: Vector3f::from(target - interaction.p),
+                b: direction,
                 time: ray_in.time,
             },
         }
     }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, interaction: &Interaction, direction: &Vector3f) -> Option<f32> {
+        let cosine = dot_vn(&direction.unit_vector(), &interaction.normal.unit_vector());
+        Some(cosine.max(0.0) / f32::consts::PI)
+    }
 }
 
 impl Lambertian {
@@ -158,6 +190,42 @@ impl MaterialTrait for Dielectric {
     }
 }
 
+// Gives off the colour of its texture, scaled by intensity, and scatters
+// nothing. The intensity lets a light be brighter than white.
+pub struct DiffuseLight {
+    pub emit: Arc<dyn Texture + Send + Sync>,
+    pub intensity: f32,
+}
+
+impl MaterialTrait for DiffuseLight {
+    fn scatter(&self, ray_in: &Ray, _interaction: &Interaction) -> ScatterResult {
+        ScatterResult {
+            hit: false,
+            atten: Colour { r: 0.0, g: 0.0, b: 0.0 },
+            ray_out: *ray_in,
+        }
+    }
+
+    fn emitted(&self, interaction: &Interaction) -> Colour {
+        self.intensity * self.emit.value(interaction.u, interaction.v, interaction.p)
+    }
+
+    fn is_light(&self) -> bool {
+        true
+    }
+}
+
+impl DiffuseLight {
+    pub fn colour(colour: Colour, intensity: f32) -> DiffuseLight {
+        DiffuseLight {
+            emit: Arc::new(SolidColour {
+                colour
+            }),
+            intensity,
+        }
+    }
+}
+
 // pub struct NormalMaterial {}
 
 // impl MaterialTrait for NormalMaterial {
@@ -0,0 +1,405 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::core::{Normal3f, Point2f, Point3f};
+use crate::material2::MaterialTrait;
+use crate::primative::{Primative, TransformTrait};
+use crate::shapes::triangle_mesh::{MeshData, MeshVertex, TriangleMesh};
+
+
+// Faces before the first g or o statement belong to this group
+pub const DEFAULT_GROUP: &str = "default";
+
+// The faces of one g or o group in a Wavefront OBJ file, with only the
+// vertices those faces use
+pub struct ObjGroup {
+    pub name: String,
+    pub mesh: MeshData,
+}
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(err) => write!(f, "could not read mesh: {}", err),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ObjError {
+    fn from(err: io::Error) -> Self {
+        ObjError::Io(err)
+    }
+}
+
+// A group's mesh along with where each of the file's vertices ended up in it
+struct GroupBuilder {
+    name: String,
+    mesh: MeshData,
+    positions: HashMap<usize, usize>,
+    normals: HashMap<usize, usize>,
+    uvs: HashMap<usize, usize>,
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<ObjGroup>, ObjError> {
+    parse_obj(BufReader::new(File::open(path)?))
+}
+
+// Reads positions, normals, texture coordinates, faces and groups. Faces with
+// more than three corners are split into a fan of triangles, and everything
+// else (materials, smoothing groups, lines) is ignored. Groups without any
+// faces are left out.
+pub fn parse_obj<R: BufRead>(reader: R) -> Result<Vec<ObjGroup>, ObjError> {
+    let mut positions: Vec<Point3f> = vec![];
+    let mut normals: Vec<Normal3f> = vec![];
+    let mut uvs: Vec<Point2f> = vec![];
+
+    let mut groups = vec![GroupBuilder::new(DEFAULT_GROUP)];
+    let mut current = 0;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = index + 1;
+        let parse_error = |message: String| ObjError::Parse { line: line_number, message };
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => {
+                let values = parse_floats(&arguments, 3, 4).map_err(parse_error)?;
+                positions.push(Point3f { x: values[0], y: values[1], z: values[2] });
+            },
+            "vn" => {
+                let values = parse_floats(&arguments, 3, 3).map_err(parse_error)?;
+                normals.push(Normal3f { x: values[0], y: values[1], z: values[2] });
+            },
+            "vt" => {
+                let values = parse_floats(&arguments, 1, 3).map_err(parse_error)?;
+                uvs.push(Point2f { x: values[0], y: values.get(1).cloned().unwrap_or(0.0) });
+            },
+            "f" => {
+                if arguments.len() < 3 {
+                    return Err(parse_error(format!("a face needs at least 3 vertices, found {}", arguments.len())));
+                }
+
+                let corners = arguments
+                    .iter()
+                    .map(|corner| parse_corner(corner, positions.len(), normals.len(), uvs.len()))
+                    .collect::<Result<Vec<MeshVertex>, String>>()
+                    .map_err(parse_error)?;
+
+                let group = &mut groups[current];
+                let corners: Vec<MeshVertex> = corners
+                    .iter()
+                    .map(|corner| group.add_vertex(corner, &positions, &normals, &uvs))
+                    .collect();
+
+                for fan in 1..corners.len() - 1 {
+                    group.mesh.triangles.push([corners[0], corners[fan], corners[fan + 1]]);
+                }
+            },
+            "g" | "o" => {
+                let name = if arguments.is_empty() {
+                    DEFAULT_GROUP.to_string()
+                } else {
+                    arguments.join(" ")
+                };
+
+                current = match groups.iter().position(|group| group.name == name) {
+                    Some(existing) => existing,
+                    None => {
+                        groups.push(GroupBuilder::new(&name));
+                        groups.len() - 1
+                    },
+                };
+            },
+            _ => (),
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .filter(|group| !group.mesh.triangles.is_empty())
+        .map(|group| ObjGroup { name: group.name, mesh: group.mesh })
+        .collect())
+}
+
+// Every group becomes a primative with its own mesh BVH, all sharing the
+// same material and transform
+pub fn obj_primatives(
+    groups: Vec<ObjGroup>,
+    material: Arc<dyn MaterialTrait + Send + Sync>,
+    transform: Arc<dyn TransformTrait + Send + Sync>,
+) -> Vec<Primative> {
+    groups
+        .into_iter()
+        .map(|group| Primative {
+            shape: Arc::new(TriangleMesh::new(group.mesh)),
+            material: material.clone(),
+            transform: transform.clone(),
+        })
+        .collect()
+}
+
+impl GroupBuilder {
+    fn new(name: &str) -> GroupBuilder {
+        GroupBuilder {
+            name: name.to_string(),
+            mesh: MeshData::default(),
+            positions: HashMap::new(),
+            normals: HashMap::new(),
+            uvs: HashMap::new(),
+        }
+    }
+
+    // Takes a corner indexing the whole file's buffers, returning it indexed
+    // into this group's
+    fn add_vertex(&mut self, corner: &MeshVertex, positions: &[Point3f], normals: &[Normal3f], uvs: &[Point2f]) -> MeshVertex {
+        let GroupBuilder { mesh, positions: position_map, normals: normal_map, uvs: uv_map, .. } = self;
+
+        MeshVertex {
+            position: *position_map.entry(corner.position).or_insert_with(|| {
+                mesh.positions.push(positions[corner.position]);
+                mesh.positions.len() - 1
+            }),
+            normal: corner.normal.map(|normal| *normal_map.entry(normal).or_insert_with(|| {
+                mesh.normals.push(normals[normal]);
+                mesh.normals.len() - 1
+            })),
+            uv: corner.uv.map(|uv| *uv_map.entry(uv).or_insert_with(|| {
+                mesh.uvs.push(uvs[uv]);
+                mesh.uvs.len() - 1
+            })),
+        }
+    }
+}
+
+fn parse_floats(arguments: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if arguments.len() < min || arguments.len() > max {
+        return Err(format!("expected between {} and {} values, found {}", min, max, arguments.len()));
+    }
+
+    arguments
+        .iter()
+        .map(|argument| argument.parse::<f32>().map_err(|_| format!("\"{}\" is not a number", argument)))
+        .collect()
+}
+
+// Corners are written v, v/vt, v//vn or v/vt/vn
+fn parse_corner(corner: &str, position_count: usize, normal_count: usize, uv_count: usize) -> Result<MeshVertex, String> {
+    let mut parts = corner.split('/');
+
+    let position = parse_index(parts.next().unwrap_or(""), position_count, "position")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(parse_index(part, uv_count, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(part) => Some(parse_index(part, normal_count, "normal")?),
+    };
+
+    if parts.next().is_some() {
+        return Err(format!("\"{}\" has too many indices", corner));
+    }
+
+    Ok(MeshVertex { position, normal, uv })
+}
+
+// OBJ indices start at 1, and negative indices count back from the most
+// recently defined element
+fn parse_index(part: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = part
+        .parse::<i64>()
+        .map_err(|_| format!("\"{}\" is not a valid {} index", part, kind))?;
+
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+
+    if resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} is out of range, only {} are defined", kind, index, count));
+    }
+
+    Ok(resolved as usize)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use float_cmp::approx_eq;
+
+    use crate::core::{Colour, Point3f, Vector3f, gen_translate};
+    use crate::material2::Lambertian;
+    use crate::obj::{ObjError, obj_primatives, parse_obj};
+    use crate::primative::StaticTransform;
+    use crate::ray::Ray;
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::triangle_mesh::MeshVertex;
+
+    const CUBE: &str = "
+# A unit cube split into two groups
+mtllib cube.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0 0 1
+v 1 0 1
+v 1 1 1
+v 0 1 1
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 -1
+vn 0 0 1
+
+g back
+usemtl red
+s off
+f 1/1/1 4/4/1 3/3/1 2/2/1
+
+g front
+f 5/1/2 6/2/2 7/3/2 8/4/2
+f -8 -7 -3
+";
+
+    #[test]
+    fn parses_groups() {
+        let groups = parse_obj(CUBE.as_bytes()).unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "back");
+        assert_eq!(groups[1].name, "front");
+
+        // Quads are split into two triangles
+        let back = &groups[0].mesh;
+        assert_eq!(back.triangles.len(), 2);
+        assert_eq!(back.positions.len(), 4);
+        assert_eq!(back.normals.len(), 1);
+        assert_eq!(back.uvs.len(), 4);
+        assert_eq!(back.triangles[1][0], back.triangles[0][0]);
+
+        // Only the vertices a group uses are copied into it
+        let front = &groups[1].mesh;
+        assert_eq!(front.triangles.len(), 3);
+        assert_eq!(front.positions.len(), 6);
+        assert_eq!(front.normals.len(), 1);
+        assert!(approx_eq!(f32, front.positions[0].z, 1.0));
+    }
+
+    #[test]
+    fn corner_formats() {
+        let groups = parse_obj("
+v 0 0 0
+v 1 0 0
+v 0 1 0
+vt 0.5 0.5
+vn 0 0 1
+f 1 2 3
+f 1/1 2/1 3/1
+f 1//1 2//1 3//1
+f -3/-1/-1 -2/-1/-1 -1/-1/-1
+".as_bytes()).unwrap();
+
+        let triangles = &groups[0].mesh.triangles;
+        assert_eq!(triangles[0][1], MeshVertex { position: 1, normal: None, uv: None });
+        assert_eq!(triangles[1][1], MeshVertex { position: 1, normal: None, uv: Some(0) });
+        assert_eq!(triangles[2][1], MeshVertex { position: 1, normal: Some(0), uv: None });
+        assert_eq!(triangles[3][1], MeshVertex { position: 1, normal: Some(0), uv: Some(0) });
+        assert_eq!(groups[0].name, "default");
+    }
+
+    #[test]
+    fn repeated_group_names_merge() {
+        let groups = parse_obj("
+v 0 0 0
+v 1 0 0
+v 0 1 0
+o a
+f 1 2 3
+o b
+f 1 2 3
+o a
+f 3 2 1
+".as_bytes()).unwrap();
+
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].mesh.triangles.len(), 2);
+        assert_eq!(groups[0].mesh.positions.len(), 3);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        match parse_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n".as_bytes()) {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("position index 3"), "{}", message);
+            },
+            _ => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for input in &["v 0 zero 0\n", "vn 0 1\n", "v 0 0 0\nf 1 1\n", "v 0 0 0\nf 1/1/1/1 1 1\n"] {
+            match parse_obj(input.as_bytes()) {
+                Err(ObjError::Parse { .. }) => (),
+                _ => panic!("Expected {:?} to be rejected", input),
+            }
+        }
+    }
+
+    #[test]
+    fn groups_become_primatives() {
+        let primatives = obj_primatives(
+            parse_obj(CUBE.as_bytes()).unwrap(),
+            Arc::new(Lambertian::colour(Colour { r: 0.5, g: 0.5, b: 0.5 })),
+            Arc::new(StaticTransform { transform: gen_translate(Vector3f { x: 0.0, y: 0.0, z: 4.0 }) }),
+        );
+        assert_eq!(primatives.len(), 2);
+
+        // Through the back face, which has been moved to z = 4
+        let ray = Ray {
+            a: Point3f { x: 0.5, y: 0.5, z: -1.0 },
+            b: Vector3f { x: 0.0, y: 0.0, z: 1.0 },
+            time: 0.0,
+        };
+        let interaction = primatives[0].collide(&ray, 0.0, 100.0).unwrap();
+        assert!(approx_eq!(f32, interaction.t, 5.0));
+        assert!(approx_eq!(f32, interaction.normal.z, -1.0));
+
+        let bounds = primatives[1].bounding_box(0.0, 0.0).unwrap();
+        assert!(bounds.minimum.z < 5.0 && bounds.maximum.z > 5.0);
+    }
+}
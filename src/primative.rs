@@ -4,8 +4,8 @@ use std::sync::Arc;
 use crate::aabb::AABB;
 use crate::ray::Ray;
 use crate::shapes::base::{Interaction, ShapeTrait};
-use crate::core::{Point3f, Transform};
-use crate::material2::{MaterialTrait, ScatterResult};
+use crate::core::{Normal3f, Point3f, Transform, Vector3f, dot_vn};
+use crate::material2::MaterialTrait;
 
 
 #[derive(Clone)]
@@ -15,70 +15,179 @@ pub struct Primative {
     pub transform: Arc<dyn TransformTrait + Send + Sync>,
 }
 
+#[derive(Copy, Clone)]
+pub struct PrimativeInteraction {
+    pub primative: usize,
+    pub interaction: Interaction,
+}
+
+// A hit carrying the material of whichever primative was struck, for hits
+// found inside instances where there is no scene level primative index
+#[derive(Copy, Clone)]
+pub struct MaterialInteraction<'a> {
+    pub material: &'a (dyn MaterialTrait + Send + Sync),
+    pub interaction: Interaction,
+}
+
+// A point picked on a light by Primative::sample_light
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+    // From the point being lit to the light, so t = 1 reaches it
+    pub direction: Vector3f,
+    // The pdf of picking this direction, by solid angle
+    pub pdf: f32,
+}
+
 impl Primative {
-    pub fn collide(self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
+    // Picks a point on the shape, by two uniform random numbers, to light
+    // reference with. Shapes that can't be sampled give None.
+    pub fn sample_light(&self, reference: &Point3f, time: f32, u: f32, v: f32) -> Option<LightSample> {
+        let sample = self.shape.sample(u, v)?;
+        let transform = self.transform.generate_transform(time);
+        let direction = Vector3f::from(transform.m * sample.p - *reference);
+
+        Some(LightSample {
+            direction,
+            pdf: self.solid_angle_pdf(&transform, &sample.normal, &direction),
+        })
+    }
+
+    // The pdf sample_light would have picked the direction of ray with, from
+    // its origin, or zero when the ray misses the shape between t_min and
+    // t_max
+    pub fn light_pdf(&self, ray: &Ray, t_min: f32, t_max: f32) -> f32 {
         let transform = self.transform.generate_transform(ray.time);
 
-        let object_to_world = transform.m;
-        let world_to_object = transform.m_inv;
-
-        let interaction_result = self.shape.collide(
-            &(world_to_object * ray),
-            t_min,
-            t_max,
-        );
-
-        match interaction_result {
-            Some(interaction) => {
-                Some(Interaction {
-                    t: interaction.t,
-                    p: object_to_world * interaction.p,
-                    u: interaction.u,
-                    v: interaction.v,
-                    normal: (world_to_object.transpose() * interaction.normal).unit_vector(),
-                })
-            },
-            None => None,
+        match self.shape.collide_sample(&(transform.m_inv * ray), t_min, t_max) {
+            Some((t, sample)) => self.solid_angle_pdf(&transform, &sample.normal, &(t * ray.b)),
+            None => 0.0,
+        }
+    }
+
+    // Turns the pdf by area, one over the shape's area once the transform
+    // has stretched it, into one by solid angle as seen along direction
+    fn solid_angle_pdf(&self, transform: &Transform, normal: &Normal3f, direction: &Vector3f) -> f32 {
+        let normal = normal.unit_vector();
+        let world_normal = transform.m_inv.transpose() * normal;
+        let area = self.shape.area() * transform.m.determinant().abs() * world_normal.length();
+
+        let distance_squared = direction.squared_length();
+        let cosine = dot_vn(direction, &world_normal.unit_vector()).abs() / distance_squared.sqrt();
+        if area <= 0.0 || cosine <= 0.0 {
+            return 0.0;
         }
+
+        distance_squared / (cosine * area)
+    }
+}
+
+// A primative is a shape placed in world space, so the scene's BVHTree can
+// treat it like any other shape
+impl ShapeTrait for Primative {
+    fn collide(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
+        let transform = self.transform.generate_transform(ray.time);
+
+        self.shape
+            .collide(&(transform.m_inv * ray), t_min, t_max)
+            .map(|interaction| to_world(&transform, &interaction))
+    }
+
+    fn collide_all(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<Interaction> {
+        let transform = self.transform.generate_transform(ray.time);
+
+        self.shape
+            .collide_all(&(transform.m_inv * ray), t_min, t_max)
+            .iter()
+            .map(|interaction| to_world(&transform, interaction))
+            .collect()
     }
 
-    pub fn bounding_box(
+    fn bounding_box(
         &self,
         time_0: f32,
         time_1: f32,
     ) -> Option<AABB> {
-        let transform = self.transform.generate_transform(time_0);
-        let object_to_world = transform.m;
-
-        match self.shape.bounding_box(time_0, time_1) {
-            Some(bounding) => {
-                let transformed_min = object_to_world * bounding.minimum;
-                let transformed_max = object_to_world * bounding.maximum;
-
-                Some(AABB {
-                    minimum: Point3f {
-                        x: transformed_min.x.min(transformed_max.x),
-                        y: transformed_min.y.min(transformed_max.y),
-                        z: transformed_min.z.min(transformed_max.z),
-                    },
-                    maximum: Point3f {
-                        x: transformed_min.x.max(transformed_max.x),
-                        y: transformed_min.y.max(transformed_max.y),
-                        z: transformed_min.z.max(transformed_max.z),
-                    },
-                })
-            },
-            None => None
-        }
+        self.shape
+            .bounding_box(time_0, time_1)
+            .map(|bounds| self.transform.motion_bounds(&bounds, time_0, time_1))
     }
+}
 
-    pub fn scatter(self, ray: &Ray, interaction: &Interaction) -> ScatterResult {
-        self.material.scatter(ray, interaction)
+// Takes an interaction found in object space back out through the transform.
+// t is unchanged, as the ray direction is transformed without normalising.
+pub fn to_world(transform: &Transform, interaction: &Interaction) -> Interaction {
+    Interaction {
+        t: interaction.t,
+        p: transform.m * interaction.p,
+        u: interaction.u,
+        v: interaction.v,
+        normal: (transform.m_inv.transpose() * interaction.normal).unit_vector(),
     }
 }
 
+// How many times motion_bounds looks at a transform over its time range
+pub const MOTION_SAMPLES: usize = 16;
+
 pub trait TransformTrait {
     fn generate_transform(&self, time_t: f32) -> Transform;
+
+    // The world space box swept out by an object space box between time_0
+    // and time_1
+    fn motion_bounds(&self, bounds: &AABB, time_0: f32, time_1: f32) -> AABB {
+        swept_bounds(|time| self.generate_transform(time), bounds, time_0, time_1)
+    }
+}
+
+// Without knowing how a transform moves, it is sampled at MOTION_SAMPLES
+// times and the box padded by half the furthest any corner moved between two
+// samples. As long as no corner turns more than half a circle between
+// samples, it stays within that of the straight line joining them, which is
+// inside the joined boxes.
+pub fn swept_bounds<F>(generate_transform: F, bounds: &AABB, time_0: f32, time_1: f32) -> AABB
+    where F: Fn(f32) -> Transform
+{
+    let corners = bounds.corners();
+    let mut swept = bounds.transform(&generate_transform(time_0));
+    if time_1 <= time_0 {
+        return swept;
+    }
+
+    let place = |transform: Transform| {
+        let mut placed = corners;
+        for corner in placed.iter_mut() {
+            *corner = transform.m * *corner;
+        }
+        placed
+    };
+
+    let mut previous = place(generate_transform(time_0));
+    let mut furthest: f32 = 0.0;
+    for i in 1..MOTION_SAMPLES {
+        let time = time_0 + (time_1 - time_0) * i as f32 / (MOTION_SAMPLES - 1) as f32;
+        let placed = place(generate_transform(time));
+
+        for (from, to) in previous.iter().zip(placed.iter()) {
+            furthest = furthest.max(Point3f::distance(from, to));
+            swept = AABB::join_point(&swept, to);
+        }
+        previous = placed;
+    }
+
+    swept.expand(0.5 * furthest)
+}
+
+pub struct StaticTransform {
+    pub transform: Transform,
+}
+
+impl TransformTrait for StaticTransform {
+    fn generate_transform(&self, _time_t: f32) -> Transform {
+        self.transform
+    }
+
+    fn motion_bounds(&self, bounds: &AABB, _time_0: f32, _time_1: f32) -> AABB {
+        bounds.transform(&self.transform)
+    }
 }
 
 
@@ -86,9 +195,11 @@ pub trait TransformTrait {
 #[cfg(test)]
 mod sphere_tests {
     use std::sync::Arc;
+    use crate::aabb::AABB;
     use crate::shapes::base::{Interaction, ShapeTrait};
-    use crate::primative::{Primative, TransformTrait};
+    use crate::primative::{Primative, StaticTransform, TransformTrait};
     use crate::shapes::sphere2::Sphere;
+    use crate::shapes::triangle_mesh::{MeshData, MeshVertex, TriangleMesh};
     use crate::core::{
         Vector3f,
         Point3f,
@@ -98,7 +209,7 @@ mod sphere_tests {
         gen_translate,
         gen_scale,
         // gen_rotate_x,
-        // gen_rotate_y,
+        gen_rotate_y,
         // gen_rotate_z,
         gen_rotate,
     };
@@ -171,7 +282,7 @@ mod sphere_tests {
             b: Vector3f {x: -2.0, y: 0.0, z: 0.0},
             time: 0.0,
         };
-        let interaction = primative.collide(ray, 0.0, 10.0).unwrap();
+        let interaction = primative.collide(&ray, 0.0, 10.0).unwrap();
 
         assert_eq!(interaction.t, 0.5);
         assert_eq!(interaction.p, Point3f {x: 1.0, y: 0.0, z: 0.0});
@@ -205,7 +316,7 @@ mod sphere_tests {
                     b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
                     time: 0.0,
                 };
-                let interaction_result = primative.collide(ray, 0.0, 10.0);
+                let interaction_result = primative.collide(&ray, 0.0, 10.0);
 
                 if x * x + y * y > 1.0 {
                     // miss case
@@ -218,7 +329,7 @@ mod sphere_tests {
 
                     let expected = Point3f {x: x, y: y, z: z_point};
                     assert!(
-                        approx_eq!(Point3f, interaction.p, expected, ulps = 4),
+                        approx_eq!(Point3f, interaction.p, expected, epsilon = 1e-5, ulps = 4),
                         "Point comparison failed - returned {:?} expected {:?} (z squared: {})",
                         interaction.p,
                         expected,
@@ -227,7 +338,7 @@ mod sphere_tests {
 
                     let expected = Normal3f {x: x, y: y, z: z_point};
                     assert!(
-                        approx_eq!(Normal3f, interaction.normal, expected),
+                        approx_eq!(Normal3f, interaction.normal, expected, epsilon = 1e-5, ulps = 4),
                         "Normal comparison failed - returned {:?} expected {:?}",
                         interaction.normal,
                         expected,
@@ -264,7 +375,7 @@ mod sphere_tests {
                     b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
                     time: 0.0,
                 };
-                let interaction_result = primative.collide(ray, 0.0, 10.0);
+                let interaction_result = primative.collide(&ray, 0.0, 10.0);
 
                 // 1.0 - x * x - y * y
                 if (x * x) / 4.0 + (y * y) / 4.0 > 1.0 {
@@ -279,7 +390,7 @@ mod sphere_tests {
                     // "Expected x:{}, y:{}, z:{} (z squared:{}) for point", x, y, z_point, z_squared
                     let expected = Point3f {x: x, y: y, z: z_point};
                     assert!(
-                        approx_eq!(Point3f,  interaction.p, expected, ulps = 4),
+                        approx_eq!(Point3f,  interaction.p, expected, epsilon = 1e-5, ulps = 4),
                         "Point comparison failed - returned {:?} expected {:?} (z squared: {})",
                         interaction.p,
                         expected,
@@ -291,7 +402,9 @@ mod sphere_tests {
                         approx_eq!(
                             Normal3f,
                             interaction.normal.unit_vector(),
-                            expected_normal.unit_vector()
+                            expected_normal.unit_vector(),
+                            epsilon = 1e-5,
+                            ulps = 4
                         ),
                         "Normal comparison failed - returned {:?} expected {:?}",
                         interaction.normal.unit_vector(),
@@ -329,7 +442,7 @@ mod sphere_tests {
                     b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
                     time: 0.0,
                 };
-                let interaction_result = primative.collide(ray, 0.0, 10.0);
+                let interaction_result = primative.collide(&ray, 0.0, 10.0);
 
                 // 1.0 - x * x - y * y
                 let a_sqred = 4.0_f32.powi(2);
@@ -345,7 +458,7 @@ mod sphere_tests {
 
                     let expected = Point3f {x: x, y: y, z: z_point};
                     assert!(
-                        approx_eq!(Point3f,  interaction.p, expected, ulps = 4),
+                        approx_eq!(Point3f,  interaction.p, expected, epsilon = 1e-5, ulps = 4),
                         "Point comparison failed - returned {:?} expected {:?} (z squared: {})",
                         interaction.p,
                         expected,
@@ -357,7 +470,9 @@ mod sphere_tests {
                         approx_eq!(
                             Normal3f,
                             interaction.normal.unit_vector(),
-                            expected_normal.unit_vector()
+                            expected_normal.unit_vector(),
+                            epsilon = 1e-5,
+                            ulps = 4
                         ),
                         "Normal comparison failed - returned {:?} expected {:?}",
                         interaction.normal.unit_vector(),
@@ -382,4 +497,161 @@ mod sphere_tests {
     // fn test_moved_irregularly_scaled_sphere_normals() {
     //     unimplemented!();
     // }
+
+    // Points spread over the unit sphere, from pole to pole
+    fn surface_samples() -> Vec<Point3f> {
+        let mut samples = vec![];
+        for i in 0..=24 {
+            let theta = std::f32::consts::PI * i as f32 / 24.0;
+            for j in 0..48 {
+                let phi = 2.0 * std::f32::consts::PI * j as f32 / 48.0;
+                samples.push(Point3f {
+                    x: theta.sin() * phi.cos(),
+                    y: theta.cos(),
+                    z: theta.sin() * phi.sin(),
+                });
+            }
+        }
+        samples
+    }
+
+    fn contains(bounds: &AABB, p: Point3f) -> bool {
+        let e = 1e-4;
+        p.x >= bounds.minimum.x - e && p.x <= bounds.maximum.x + e
+            && p.y >= bounds.minimum.y - e && p.y <= bounds.maximum.y + e
+            && p.z >= bounds.minimum.z - e && p.z <= bounds.maximum.z + e
+    }
+
+    // A stretched sphere swung around the y axis over time, well away from it
+    struct Spinning {}
+    impl TransformTrait for Spinning {
+        fn generate_transform(&self, time_t: f32) -> Transform {
+            gen_rotate_y(time_t * std::f32::consts::PI)
+                * gen_translate(Vector3f {x: 4.0, y: 0.0, z: 0.0})
+                * gen_scale(2.0, 0.5, 1.0)
+        }
+    }
+
+    #[test]
+    fn rotated_bounds_contain_surface() {
+        for degrees in [15.0f32, 45.0, 70.0, 135.0].iter() {
+            let transform = SRTTransform::init(
+                Vector3f {x: 1.0, y: -2.0, z: 3.0},
+                3.0,
+                1.0,
+                0.5,
+                degrees.to_radians(),
+                Vector3f {x: 1.0, y: 1.0, z: 0.0},
+            );
+            let primative = Primative {
+                shape: Arc::new(Sphere {radius: 1.0}),
+                transform: Arc::new(transform),
+                material: Arc::new(DummyMaterial {}),
+            };
+            let bounds = primative.bounding_box(0.0, 1.0).unwrap();
+            let m = primative.transform.generate_transform(0.0).m;
+
+            for p in surface_samples() {
+                let p = m * p;
+                assert!(contains(&bounds, p), "{:?} outside the bounds at {} degrees", p, degrees);
+            }
+        }
+    }
+
+    #[test]
+    fn animated_bounds_contain_surface() {
+        let primative = Primative {
+            shape: Arc::new(Sphere {radius: 1.0}),
+            transform: Arc::new(Spinning {}),
+            material: Arc::new(DummyMaterial {}),
+        };
+        let bounds = primative.bounding_box(0.0, 1.0).unwrap();
+
+        for step in 0..=97 {
+            let m = primative.transform.generate_transform(step as f32 / 97.0).m;
+            for p in surface_samples() {
+                let p = m * p;
+                assert!(contains(&bounds, p), "{:?} outside the bounds at step {}", p, step);
+            }
+        }
+
+        // Half a turn from +x round to -x, passing through +z or -z
+        assert!(bounds.minimum.x < -5.9 && bounds.maximum.x > 5.9);
+        assert!(bounds.maximum.z - bounds.minimum.z > 5.0);
+
+        // Without motion, only the start is covered
+        let still = primative.bounding_box(0.0, 0.0).unwrap();
+        assert!(still.minimum.x > 1.9);
+    }
+
+    #[test]
+    fn scaled_light_pdf() {
+        let primative = |x, y, z| Primative {
+            shape: Arc::new(Sphere {radius: 1.0}),
+            transform: Arc::new(SRTTransform::init(
+                Vector3f {x: 0.0, y: 0.0, z: 0.0},
+                x,
+                y,
+                z,
+                0.0,
+                Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            )),
+            material: Arc::new(DummyMaterial {}),
+        };
+
+        // The pole of a sphere grown to radius 2, facing straight back 8 away
+        let light = primative(2.0, 2.0, 2.0);
+        let reference = Point3f {x: 0.0, y: 0.0, z: 10.0};
+        let sample = light.sample_light(&reference, 0.0, 0.0, 0.0).unwrap();
+        assert!(approx_eq!(Vector3f, sample.direction, Vector3f {x: 0.0, y: 0.0, z: -8.0}, epsilon = 1e-5));
+        assert!(approx_eq!(f32, sample.pdf, 4.0 / std::f32::consts::PI, epsilon = 1e-5));
+
+        let ray = Ray {a: reference, b: Vector3f {x: 0.0, y: 0.0, z: -1.0}, time: 0.0};
+        assert!(approx_eq!(f32, light.light_pdf(&ray, 0.0, 100.0), sample.pdf, epsilon = 1e-5));
+        assert_eq!(light.light_pdf(&ray, 0.0, 7.0), 0.0);
+        let away = Ray {a: reference, b: Vector3f {x: 0.0, y: 0.0, z: 1.0}, time: 0.0};
+        assert_eq!(light.light_pdf(&away, 0.0, 100.0), 0.0);
+
+        // Stretched along x, the tip's surface isn't stretched at all
+        let light = primative(2.0, 1.0, 1.0);
+        let reference = Point3f {x: 10.0, y: 0.0, z: 0.0};
+        let sample = light.sample_light(&reference, 0.0, 0.5, 0.0).unwrap();
+        assert!(approx_eq!(Vector3f, sample.direction, Vector3f {x: -8.0, y: 0.0, z: 0.0}, epsilon = 1e-5));
+        assert!(approx_eq!(f32, sample.pdf, 16.0 / std::f32::consts::PI, epsilon = 1e-4));
+    }
+
+    #[test]
+    fn smooth_mesh_light_pdf() {
+        let vertex = |position, normal| MeshVertex {position, normal: Some(normal), uv: None};
+        // A unit square facing +z, with vertex normals leaning well away
+        let data = MeshData {
+            positions: vec![
+                Point3f {x: 0.0, y: 0.0, z: 0.0},
+                Point3f {x: 1.0, y: 0.0, z: 0.0},
+                Point3f {x: 1.0, y: 1.0, z: 0.0},
+                Point3f {x: 0.0, y: 1.0, z: 0.0},
+            ],
+            normals: vec![
+                Normal3f {x: 1.0, y: 0.0, z: 0.2},
+                Normal3f {x: -1.0, y: 0.0, z: 0.2},
+            ],
+            uvs: vec![],
+            triangles: vec![
+                [vertex(0, 0), vertex(1, 1), vertex(2, 1)],
+                [vertex(0, 0), vertex(2, 1), vertex(3, 0)],
+            ],
+        };
+        let light = Primative {
+            shape: Arc::new(TriangleMesh::new(data)),
+            transform: Arc::new(StaticTransform {transform: gen_translate(Vector3f {x: 0.0, y: 0.0, z: -2.0})}),
+            material: Arc::new(DummyMaterial {}),
+        };
+
+        let reference = Point3f {x: 2.0, y: 0.5, z: 1.0};
+        for &(u, v) in [(0.1, 0.3), (0.5, 0.5), (0.9, 0.8)].iter() {
+            let sample = light.sample_light(&reference, 0.0, u, v).unwrap();
+            let ray = Ray {a: reference, b: sample.direction, time: 0.0};
+            assert!(approx_eq!(f32, light.light_pdf(&ray, 0.0, 2.0), sample.pdf, epsilon = 1e-4));
+        }
+    }
 }
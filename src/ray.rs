@@ -22,6 +22,35 @@ impl Ray {
     pub fn point_at_parameter(&self, point : f32) -> Point3f { &self.a + &(point * &self.b) }
 }
 
+// A ray with its reciprocal direction precomputed, so that slab tests
+// against many bounding boxes only multiply
+#[derive(Copy, Clone)]
+pub struct InverseRay {
+    pub origin: Point3f,
+    pub inv_direction: Vector3f,
+    pub direction_is_negative: [bool; 3],
+}
+
+impl From<&Ray> for InverseRay {
+    fn from(ray: &Ray) -> Self {
+        let inv_direction = Vector3f {
+            x: 1.0 / ray.b.x,
+            y: 1.0 / ray.b.y,
+            z: 1.0 / ray.b.z,
+        };
+
+        InverseRay {
+            origin: ray.a,
+            inv_direction,
+            direction_is_negative: [
+                inv_direction.x < 0.0,
+                inv_direction.y < 0.0,
+                inv_direction.z < 0.0,
+            ],
+        }
+    }
+}
+
 impl Mul<Ray> for Matrix4x4f {
     type Output = Ray;
 
@@ -1,12 +1,73 @@
+use std::io::{self, Write};
+
 use serde::{Deserialize, Serialize};
 
 use crate::core::Colour;
 
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+// Binary sample streams start with the magic and version, followed by the
+// rest of the StreamHeader, then one fixed size record per RenderResult. All
+// values are little endian.
+pub const STREAM_MAGIC: [u8; 4] = *b"RRTS";
+pub const STREAM_VERSION: u32 = 1;
+
+pub const HEADER_SIZE: usize = 28;
+pub const RECORD_SIZE: usize = 24;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct RenderResult {
     pub x_coord: f32,
     pub y_coord: f32,
     pub time: f32,
     pub colour: Colour,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamHeader {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub time_0: f32,
+    pub time_1: f32,
+}
+
+pub struct SampleWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> SampleWriter<W> {
+    pub fn new(mut writer: W, header: &StreamHeader) -> io::Result<SampleWriter<W>> {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&STREAM_MAGIC);
+        bytes[4..8].copy_from_slice(&STREAM_VERSION.to_le_bytes());
+        bytes[8..12].copy_from_slice(&header.width.to_le_bytes());
+        bytes[12..16].copy_from_slice(&header.height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&header.samples.to_le_bytes());
+        bytes[20..24].copy_from_slice(&header.time_0.to_le_bytes());
+        bytes[24..28].copy_from_slice(&header.time_1.to_le_bytes());
+        writer.write_all(&bytes)?;
+
+        Ok(SampleWriter { writer })
+    }
+
+    pub fn write(&mut self, result: &RenderResult) -> io::Result<()> {
+        let values = [
+            result.x_coord,
+            result.y_coord,
+            result.time,
+            result.colour.r,
+            result.colour.g,
+            result.colour.b,
+        ];
+
+        let mut bytes = [0; RECORD_SIZE];
+        for (chunk, value) in bytes.chunks_mut(4).zip(values.iter()) {
+            chunk.copy_from_slice(&value.to_le_bytes());
+        }
+        self.writer.write_all(&bytes)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
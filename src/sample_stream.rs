@@ -0,0 +1,289 @@
+use std::io::{self, BufRead, Read};
+
+use crate::core::Colour;
+use crate::render::{HEADER_SIZE, RECORD_SIZE, RenderResult, STREAM_MAGIC, STREAM_VERSION, StreamHeader};
+
+
+// Reads back the binary format SampleWriter writes, as described in render
+pub struct SampleReader<R: Read> {
+    reader: R,
+    pub header: StreamHeader,
+}
+
+// The samples coming into the assembler, along with the size of the image
+// they make up. Binary streams carry the size in their header, while JSON
+// lines rely on being told it.
+pub struct SampleStream<'a> {
+    pub width: usize,
+    pub height: usize,
+    pub samples: Box<dyn Iterator<Item = io::Result<RenderResult>> + 'a>,
+}
+
+impl<'a> SampleStream<'a> {
+    pub fn binary<R: Read + 'a>(input: R) -> io::Result<SampleStream<'a>> {
+        let reader = SampleReader::new(input)?;
+
+        Ok(SampleStream {
+            width: reader.header.width as usize,
+            height: reader.header.height as usize,
+            samples: Box::new(reader),
+        })
+    }
+
+    pub fn json<R: BufRead + 'a>(input: R, width: usize, height: usize) -> SampleStream<'a> {
+        let lines = input
+            .lines()
+            .map(|line| line.and_then(|line| Ok(serde_json::from_str(&line)?)));
+
+        SampleStream {
+            width,
+            height,
+            samples: Box::new(lines),
+        }
+    }
+}
+
+impl<R: Read> SampleReader<R> {
+    pub fn new(mut reader: R) -> io::Result<SampleReader<R>> {
+        let mut bytes = [0; HEADER_SIZE];
+        reader.read_exact(&mut bytes)?;
+
+        if bytes[0..4] != STREAM_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary sample stream"));
+        }
+
+        let version = read_u32(&bytes[4..8]);
+        if version != STREAM_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("sample stream version {} is not supported, expected {}", version, STREAM_VERSION),
+            ));
+        }
+
+        let header = StreamHeader {
+            width: read_u32(&bytes[8..12]),
+            height: read_u32(&bytes[12..16]),
+            samples: read_u32(&bytes[16..20]),
+            time_0: read_f32(&bytes[20..24]),
+            time_1: read_f32(&bytes[24..28]),
+        };
+
+        Ok(SampleReader { reader, header })
+    }
+
+    // None at the end of the stream, and an error if it ends part way
+    // through a record
+    pub fn read(&mut self) -> io::Result<Option<RenderResult>> {
+        let mut bytes = [0; RECORD_SIZE];
+        let mut filled = 0;
+        while filled < RECORD_SIZE {
+            match self.reader.read(&mut bytes[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "sample stream ends part way through a record")),
+                Ok(count) => filled += count,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {},
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Some(RenderResult {
+            x_coord: read_f32(&bytes[0..4]),
+            y_coord: read_f32(&bytes[4..8]),
+            time: read_f32(&bytes[8..12]),
+            colour: Colour {
+                r: read_f32(&bytes[12..16]),
+                g: read_f32(&bytes[16..20]),
+                b: read_f32(&bytes[20..24]),
+            },
+        }))
+    }
+}
+
+impl<R: Read> Iterator for SampleReader<R> {
+    type Item = io::Result<RenderResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(bytes);
+    u32::from_le_bytes(value)
+}
+
+fn read_f32(bytes: &[u8]) -> f32 {
+    f32::from_bits(read_u32(bytes))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::accumulator::Accumulator;
+    use crate::core::Colour;
+    use crate::render::{RenderResult, SampleWriter, StreamHeader};
+    use crate::sample_stream::{SampleReader, SampleStream};
+
+    fn header() -> StreamHeader {
+        StreamHeader {
+            width: 200,
+            height: 100,
+            samples: 16,
+            time_0: 0.0,
+            time_1: 1.0 / 30.0,
+        }
+    }
+
+    fn results() -> Vec<RenderResult> {
+        vec![
+            RenderResult {
+                x_coord: 0.25,
+                y_coord: 99.999_99,
+                time: 0.01,
+                colour: Colour { r: 0.5, g: 1.5, b: 0.0 },
+            },
+            RenderResult {
+                x_coord: 199.5,
+                y_coord: 0.0,
+                time: 0.033,
+                colour: Colour { r: -0.0, g: 1e-20, b: 1e20 },
+            },
+        ]
+    }
+
+    fn encode(header: &StreamHeader, results: &[RenderResult]) -> Vec<u8> {
+        let mut output = vec![];
+        let mut writer = SampleWriter::new(&mut output, header).unwrap();
+        for result in results {
+            writer.write(result).unwrap();
+        }
+        output
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let encoded = encode(&header(), &results());
+        assert_eq!(encoded.len(), 28 + 2 * 24);
+
+        let reader = SampleReader::new(&encoded[..]).unwrap();
+        assert_eq!(reader.header, header());
+
+        let decoded = reader.collect::<Result<Vec<RenderResult>, _>>().unwrap();
+        assert_eq!(decoded, results());
+    }
+
+    #[test]
+    fn binary_matches_json() {
+        let json: Vec<String> = results()
+            .iter()
+            .map(|result| serde_json::to_string(result).unwrap())
+            .collect();
+        let from_json: Vec<RenderResult> = json
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let encoded = encode(&header(), &from_json);
+        let from_binary = SampleReader::new(&encoded[..])
+            .unwrap()
+            .collect::<Result<Vec<RenderResult>, _>>()
+            .unwrap();
+
+        assert_eq!(from_binary, from_json);
+    }
+
+    #[test]
+    fn rejects_other_data() {
+        let json = serde_json::to_string(&results()[0]).unwrap();
+        let padded = format!("{:<40}", json);
+
+        match SampleReader::new(padded.as_bytes()) {
+            Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("Expected JSON to be rejected"),
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut encoded = encode(&header(), &[]);
+        encoded[4] = 2;
+
+        match SampleReader::new(&encoded[..]) {
+            Err(err) => assert!(err.to_string().contains("version 2"), "{}", err),
+            Ok(_) => panic!("Expected version 2 to be rejected"),
+        }
+    }
+
+    #[test]
+    fn truncated_record() {
+        let encoded = encode(&header(), &results());
+        let mut reader = SampleReader::new(&encoded[..encoded.len() - 3]).unwrap();
+
+        assert!(reader.read().unwrap().is_some());
+        assert_eq!(reader.read().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    fn sample(x_coord: f32, y_coord: f32, value: f32) -> RenderResult {
+        RenderResult {
+            x_coord,
+            y_coord,
+            time: 0.0,
+            colour: Colour { r: value, g: value, b: value },
+        }
+    }
+
+    fn assemble(stream: SampleStream) -> Accumulator {
+        let mut accumulator = Accumulator::new(stream.width as u32, stream.height as u32);
+        for sample in stream.samples {
+            accumulator.add(&sample.unwrap());
+        }
+        accumulator
+    }
+
+    #[test]
+    fn assembles_a_written_stream() {
+        let header = StreamHeader { width: 3, height: 2, samples: 2, time_0: 0.0, time_1: 0.0 };
+        let samples = [
+            sample(0.2, 0.3, 1.0),
+            sample(2.5, 1.5, 4.0),
+            sample(0.7, 0.9, 3.0),
+            sample(2.1, 1.8, 2.0),
+            sample(1.5, 0.5, 0.5),
+        ];
+        let encoded = encode(&header, &samples);
+
+        let stream = SampleStream::binary(&encoded[..]).unwrap();
+        assert_eq!((stream.width, stream.height), (3, 2));
+        let accumulator = assemble(stream);
+
+        assert_eq!(accumulator.sample_count, 5);
+        assert_eq!(accumulator.pixel(0, 0).count, 2);
+        assert_eq!(accumulator.pixel(0, 0).mean, Colour { r: 2.0, g: 2.0, b: 2.0 });
+        assert_eq!(accumulator.pixel(2, 1).mean, Colour { r: 3.0, g: 3.0, b: 3.0 });
+        assert_eq!(accumulator.pixel(1, 0).mean, Colour { r: 0.5, g: 0.5, b: 0.5 });
+        assert_eq!(accumulator.pixel(1, 1).count, 0);
+
+        // The same samples as JSON lines merge into the same pixels
+        let lines: String = samples
+            .iter()
+            .map(|sample| serde_json::to_string(sample).unwrap() + "\n")
+            .collect();
+        let from_json = assemble(SampleStream::json(lines.as_bytes(), 3, 2));
+        assert_eq!(from_json.mean_image().pixels, accumulator.mean_image().pixels);
+    }
+
+    #[test]
+    fn stream_stops_at_bad_records() {
+        let header = StreamHeader { width: 1, height: 1, samples: 1, time_0: 0.0, time_1: 0.0 };
+        let encoded = encode(&header, &[sample(0.5, 0.5, 1.0)]);
+
+        let mut samples = SampleStream::binary(&encoded[..encoded.len() - 1]).unwrap().samples;
+        assert_eq!(samples.next().unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut samples = SampleStream::json(&b"{\"x_coord\": 0.5}\n"[..], 1, 1).samples;
+        assert_eq!(samples.next().unwrap().unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,98 +1,239 @@
-use crate::aabb::AABB;
+use std::sync::Arc;
+
+use rand::{Rng, thread_rng};
+use serde::{Deserialize, Serialize};
+
 use crate::ray::Ray;
-use crate::shapes::base::{Interaction, ShapeTrait};
-use crate::primative::Primative;
-use crate::core::{Point3f, Normal3f, Colour};
+use crate::primative::{MaterialInteraction, Primative};
+use crate::instance::{Instance, closest_material};
+use crate::light::LightTrait;
+use crate::core::Colour;
 use crate::camera::Camera;
-use crate::bvh_tree::BVHTree;
+use crate::bvh_tree::{BVHTree, BVHStatistics, SplitStrategy};
 
 
 const T_MAX: f32 = 1000000.0;
 
-pub struct Scene {
-    pub primatives: Vec<Primative>,
-    pub camera: Camera,
-}
+// How long a path is followed for, matching calculate_colour's depth
+const MAX_BOUNCES: i32 = 50;
 
-struct PrimativeInteraction {
-    primative: usize,
-    interaction: Interaction,
+// How close to either end of a shadow ray, as a fraction of its length, a hit
+// is taken to be the surface being lit or the light itself, and not anything
+// in between
+const SHADOW_EPSILON: f32 = 1e-4;
+
+// Which of the two ways of following rays round the scene to render with
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Integrator {
+    // calculate_colour, which only finds lights by bouncing into them
+    Bounce,
+    // path_trace, which also samples the lights directly
+    Path,
 }
 
-impl Scene {
-    fn find_interaction(&self, ray: &Ray) -> Option<PrimativeInteraction> {
-        let mut t_max = T_MAX;
-
-        // let mut nearest_hit = 
-        // let mut closest: Option<Primative>;
-        // let mut ray_hit: Option<Ray>;
-
-        // let mut closest: Option<PrimativeInteraction> = None;
-        // for (index, primative) in self.primatives.iter().enumerate() {
-        //     let interaction_option = primative.collide(ray, 0.0, t_max);
-
-        //     match interaction_option {
-        //         Some(interaction) => {
-        //             closest = Some(PrimativeInteraction{
-        //                 primative: index,
-        //                 interaction: *interaction,
-        //             });
-        //             t_max = interaction.t;
-        //         },
-        //         None => (),
-        //     }
-        // }
-
-        return (&self.primatives).iter().enumerate().fold(None, |previous, (index, primative)| {
-            // let index = enum_prim_tuple[0];
-            // let primative = enum_prim_tuple[1];
-            let prima_clone = primative.clone();
-
-            let interaction_option = prima_clone.collide(ray, 0.0, t_max);
-            match interaction_option {
-                Some(interaction) => {
-                    t_max = interaction.t;
-                    Some(PrimativeInteraction{
-                        primative: index,
-                        interaction,
-                    })
-                },
-                None => previous,
-            }
-        });
+impl Integrator {
+    pub fn colour(&self, scene: &Scene, ray: &Ray) -> Colour {
+        match self {
+            Integrator::Bounce => calculate_colour(scene, ray, 0),
+            Integrator::Path => path_trace(scene, ray),
+        }
     }
+}
 
-    // fn find_boundingboxes(&self, time_0: f32, time_1: f32) -> Option<AABB> {
-    //     (&self.primatives).iter().fold(|previous, primative| {
+// What a ray sees when it leaves the scene without hitting anything
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Background {
+    Black,
+    Solid { colour: Colour },
+    // Blends from bottom, looking straight down, to top, looking straight up
+    Gradient { bottom: Colour, top: Colour },
+}
+
+impl Default for Background {
+    // The sky the scenes have always been lit by
+    fn default() -> Self {
+        Background::Gradient {
+            bottom: Colour { r: 1.0, g: 1.0, b: 1.0 },
+            top: Colour { r: 0.5, g: 0.7, b: 1.0 },
+        }
+    }
+}
 
-    // }
+impl Background {
+    pub fn colour(&self, ray: &Ray) -> Colour {
+        match self {
+            Background::Black => Colour { r: 0.0, g: 0.0, b: 0.0 },
+            Background::Solid { colour } => *colour,
+            Background::Gradient { bottom, top } => {
+                let unit_dir = ray.direction().unit_vector();
+                let t : f32 = 0.5 * (unit_dir.y + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            },
+        }
+    }
 }
 
-pub struct SceneAABBDivision {
+pub struct Scene {
     pub primatives: Vec<Primative>,
+    pub instances: Vec<Instance>,
     pub camera: Camera,
-    pub bvh_trees: Vec<BVHTree>,
+    pub background: Background,
+    // Points, spots and suns, which can't be hit so only path_trace sees them
+    pub analytic_lights: Vec<Arc<dyn LightTrait + Send + Sync>>,
+    // The primatives whose material gives off light and whose shape can be
+    // sampled. Lights inside instances are only found by bouncing into them.
+    area_lights: Vec<Primative>,
+    time_0: f32,
+    time_1: f32,
+    // Also holds the primatives without bounds, like infinite planes, which
+    // it keeps apart from the tree and tests against every ray
+    bvh_tree: BVHTree,
+    instance_tree: BVHTree<Instance>,
 }
 
-impl SceneAABBDivision {
-    fn find_interaction(&self, ray: &Ray) -> Option<PrimativeInteraction> {
-        let mut t_max = T_MAX;
-
-        return (&self.primatives).iter().enumerate().fold(None, |previous, (index, primative)| {
-            let prima_clone = primative.clone();
-
-            let interaction_option = prima_clone.collide(ray, 0.0, t_max);
-            match interaction_option {
-                Some(interaction) => {
-                    t_max = interaction.t;
-                    Some(PrimativeInteraction{
-                        primative: index,
-                        interaction,
-                    })
-                },
-                None => previous,
+impl Scene {
+    pub fn new(
+        primatives: Vec<Primative>,
+        camera: Camera,
+        time_0: f32,
+        time_1: f32,
+        strategy: SplitStrategy,
+    ) -> Scene {
+        Scene::with_instances(primatives, vec![], camera, time_0, time_1, strategy)
+    }
+
+    pub fn with_instances(
+        primatives: Vec<Primative>,
+        instances: Vec<Instance>,
+        camera: Camera,
+        time_0: f32,
+        time_1: f32,
+        strategy: SplitStrategy,
+    ) -> Scene {
+        let bvh_tree = BVHTree::with_strategy(primatives.clone(), time_0, time_1, strategy);
+        let instance_tree = BVHTree::with_strategy(instances.clone(), time_0, time_1, strategy);
+        let area_lights = primatives
+            .iter()
+            .filter(|primative| primative.material.is_light() && primative.shape.area() > 0.0)
+            .cloned()
+            .collect();
+
+        Scene {
+            primatives,
+            instances,
+            camera,
+            background: Background::default(),
+            analytic_lights: vec![],
+            area_lights,
+            time_0,
+            time_1,
+            bvh_tree,
+            instance_tree,
+        }
+    }
+
+    pub fn time_range(&self) -> (f32, f32) {
+        (self.time_0, self.time_1)
+    }
+
+    pub fn bvh_statistics(&self) -> BVHStatistics {
+        self.bvh_tree.statistics()
+    }
+
+    pub fn light_count(&self) -> usize {
+        self.area_lights.len() + self.analytic_lights.len()
+    }
+
+    fn find_interaction(&self, ray: &Ray) -> Option<MaterialInteraction<'_>> {
+        closest_material(&self.bvh_tree, &self.instance_tree, ray, 0.0, T_MAX)
+    }
+
+    // The pdf of sampling the lights picking ray's direction, which must
+    // reach the light it hits at t
+    fn light_pdf(&self, ray: &Ray, t: f32) -> f32 {
+        if self.area_lights.is_empty() {
+            return 0.0;
+        }
+
+        let t_max = t * (1.0 + SHADOW_EPSILON);
+        let total: f32 = self.area_lights
+            .iter()
+            .map(|light| light.light_pdf(ray, 0.0, t_max))
+            .sum();
+
+        total / self.area_lights.len() as f32
+    }
+
+    // Light reaching interaction straight from a randomly picked light,
+    // weighted against the chance scattering would have found it
+    fn sample_lights(&self, ray: &Ray, interaction: &MaterialInteraction) -> Colour {
+        let black = Colour { r: 0.0, g: 0.0, b: 0.0 };
+        if self.area_lights.is_empty() {
+            return black;
+        }
+
+        let mut rng = thread_rng();
+        let light = &self.area_lights[rng.gen_range(0, self.area_lights.len())];
+        let p = interaction.interaction.p;
+        let sample = match light.sample_light(&p, ray.time, rng.gen(), rng.gen()) {
+            Some(sample) if sample.pdf > 0.0 => sample,
+            _ => return black,
+        };
+        let light_pdf = sample.pdf / self.area_lights.len() as f32;
+
+        let scattering_pdf = interaction.material
+            .scattering_pdf(ray, &interaction.interaction, &sample.direction)
+            .unwrap_or(0.0);
+        if scattering_pdf <= 0.0 {
+            return black;
+        }
+
+        // Whatever the shadow ray hits first has to be at the sampled point
+        let shadow = Ray {
+            a: p,
+            b: sample.direction,
+            time: ray.time,
+        };
+        match closest_material(&self.bvh_tree, &self.instance_tree, &shadow, SHADOW_EPSILON, 1.0 + SHADOW_EPSILON) {
+            Some(hit) if hit.interaction.t >= 1.0 - SHADOW_EPSILON => {
+                hit.material.emitted(&hit.interaction)
+                    * (scattering_pdf * power_heuristic(light_pdf, scattering_pdf) / light_pdf)
+            },
+            _ => black,
+        }
+    }
+
+    // Light reaching interaction from every point, spot and sun it can see.
+    // Nothing else could find them, so there is nothing to weight against.
+    fn sample_analytic_lights(&self, ray: &Ray, interaction: &MaterialInteraction) -> Colour {
+        let p = interaction.interaction.p;
+        let mut total = Colour { r: 0.0, g: 0.0, b: 0.0 };
+
+        for light in &self.analytic_lights {
+            let illumination = match light.illuminate(&p) {
+                Some(illumination) => illumination,
+                None => continue,
+            };
+            let scattering_pdf = interaction.material
+                .scattering_pdf(ray, &interaction.interaction, &illumination.direction)
+                .unwrap_or(0.0);
+            if scattering_pdf <= 0.0 {
+                continue;
             }
-        });
+
+            let shadow = Ray {
+                a: p,
+                b: illumination.direction,
+                time: ray.time,
+            };
+            let t_max = (illumination.t_max * (1.0 - SHADOW_EPSILON)).min(T_MAX);
+            if closest_material(&self.bvh_tree, &self.instance_tree, &shadow, SHADOW_EPSILON, t_max).is_none() {
+                total += scattering_pdf * illumination.radiance;
+            }
+        }
+
+        total
     }
 }
 
@@ -116,19 +257,204 @@ pub fn calculate_colour(scene: &Scene, ray: &Ray, depth: i32) -> Colour {
             //     b: normalised_normal.z,
             // } + Colour {r: 1.0, g: 1.0, b: 1.0})
 
-            let prima_clone = scene.primatives[interaction.primative].clone();
-            let scatter_result = prima_clone.scatter(ray, &interaction.interaction);
+            let emitted = interaction.material.emitted(&interaction.interaction);
+            let scatter_result = interaction.material.scatter(ray, &interaction.interaction);
 
             if !scatter_result.hit {
-                return Colour { r: 0.0, g: 0.0, b: 0.0 };
+                return emitted;
             }
 
-            scatter_result.atten * calculate_colour(scene, &scatter_result.ray_out, depth + 1)
-        },
-        None => {
-            let unit_dir = ray.direction().unit_vector();
-            let t : f32 = 0.5 * (unit_dir.y + 1.0);
-            (1.0 - t) * Colour { r: 1.0, g: 1.0, b: 1.0 } + t * Colour { r: 0.5, g: 0.7, b: 1.0 }
+            emitted + scatter_result.atten * calculate_colour(scene, &scatter_result.ray_out, depth + 1)
         },
+        None => scene.background.colour(ray),
+    }
+}
+
+// Follows the path a ray takes round the scene like calculate_colour, but at
+// each diffuse bounce also samples a light directly with a shadow ray. Light
+// found either way is weighted by the power heuristic, so small lights are
+// mostly found by sampling them, and large lights and shiny surfaces mostly
+// by scattering.
+pub fn path_trace(scene: &Scene, ray: &Ray) -> Colour {
+    let mut colour = Colour { r: 0.0, g: 0.0, b: 0.0 };
+    let mut throughput = Colour { r: 1.0, g: 1.0, b: 1.0 };
+    let mut ray = *ray;
+    // The pdf the last bounce scattered ray with, or None for the camera ray
+    // and mirror like bounces, which the lights are never sampled from
+    let mut scattering_pdf: Option<f32> = None;
+
+    for _ in 0..MAX_BOUNCES {
+        let interaction = match scene.find_interaction(&ray) {
+            Some(interaction) => interaction,
+            None => {
+                colour += throughput * scene.background.colour(&ray);
+                break;
+            },
+        };
+
+        let emitted = interaction.material.emitted(&interaction.interaction);
+        let weight = match scattering_pdf {
+            Some(pdf) => power_heuristic(pdf, scene.light_pdf(&ray, interaction.interaction.t)),
+            None => 1.0,
+        };
+        colour += weight * throughput * emitted;
+
+        let scatter_result = interaction.material.scatter(&ray, &interaction.interaction);
+        if !scatter_result.hit {
+            break;
+        }
+
+        scattering_pdf = interaction.material
+            .scattering_pdf(&ray, &interaction.interaction, &scatter_result.ray_out.b);
+        throughput = throughput * scatter_result.atten;
+        if scattering_pdf.is_some() {
+            colour += throughput * scene.sample_lights(&ray, &interaction);
+            colour += throughput * scene.sample_analytic_lights(&ray, &interaction);
+        }
+        ray = scatter_result.ray_out;
+    }
+
+    colour
+}
+
+// Veach's power heuristic with a power of two, the weight for a sample from
+// the strategy with pdf f when the other would have picked it with pdf g
+pub fn power_heuristic(f: f32, g: f32) -> f32 {
+    let (f, g) = (f * f, g * g);
+    if f + g > 0.0 {
+        f / (f + g)
+    } else {
+        0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::sync::Arc;
+
+    use float_cmp::approx_eq;
+
+    use crate::bvh_tree::SplitStrategy;
+    use crate::camera::Camera;
+    use crate::core::{Colour, Point3f, Vector3f, gen_translate};
+    use crate::light::{DirectionalLight, LightTrait, PointLight};
+    use crate::material2::{DiffuseLight, Lambertian};
+    use crate::primative::{Primative, StaticTransform};
+    use crate::ray::Ray;
+    use crate::scene2::{Background, Scene, calculate_colour, path_trace, power_heuristic};
+    use crate::shapes::rect::XZRect;
+
+    // A grey floor with the given primatives over it, in the dark
+    fn floor_with(mut primatives: Vec<Primative>) -> Scene {
+        primatives.push(Primative {
+            shape: Arc::new(XZRect {x0: -10.0, x1: 10.0, z0: -10.0, z1: 10.0, k: 0.0, flip_normal: false}),
+            transform: placed(),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5})),
+        });
+        let camera = Camera::create(
+            Point3f {x: 0.0, y: 1.0, z: 5.0},
+            Point3f {x: 0.0, y: 0.0, z: 0.0},
+            Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+
+        let mut scene = Scene::new(primatives, camera, 0.0, 1.0, SplitStrategy::default());
+        scene.background = Background::Black;
+        scene
+    }
+
+    fn placed() -> Arc<StaticTransform> {
+        Arc::new(StaticTransform {transform: gen_translate(Vector3f {x: 0.0, y: 0.0, z: 0.0})})
+    }
+
+    fn lit_floor() -> Scene {
+        floor_with(vec![
+            Primative {
+                shape: Arc::new(XZRect {x0: -1.0, x1: 1.0, z0: -1.0, z1: 1.0, k: 2.0, flip_normal: true}),
+                transform: placed(),
+                material: Arc::new(DiffuseLight::colour(Colour {r: 1.0, g: 1.0, b: 1.0}, 4.0)),
+            },
+        ])
+    }
+
+    #[test]
+    fn power_heuristic_weights() {
+        assert_eq!(power_heuristic(1.0, 1.0), 0.5);
+        assert!(approx_eq!(f32, power_heuristic(3.0, 1.0), 0.9));
+        assert_eq!(power_heuristic(2.0, 0.0), 1.0);
+        assert_eq!(power_heuristic(0.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn finds_the_lights() {
+        let scene = lit_floor();
+        assert_eq!(scene.light_count(), 1);
+
+        // Seen directly, the light is counted in full
+        let up = Ray {a: Point3f {x: 0.0, y: 1.0, z: 0.0}, b: Vector3f {x: 0.0, y: 1.0, z: 0.0}, time: 0.0};
+        assert_eq!(path_trace(&scene, &up), Colour {r: 4.0, g: 4.0, b: 4.0});
+    }
+
+    #[test]
+    fn agrees_with_bouncing() {
+        let scene = lit_floor();
+        let down = Ray {a: Point3f {x: 0.0, y: 1.0, z: 0.0}, b: Vector3f {x: 0.0, y: -1.0, z: 0.0}, time: 0.0};
+
+        let samples = 20000;
+        let (mut bounced, mut traced) = (0.0, 0.0);
+        for _ in 0..samples {
+            bounced += calculate_colour(&scene, &down, 0).r;
+            traced += path_trace(&scene, &down).r;
+        }
+        let (bounced, traced) = (bounced / samples as f32, traced / samples as f32);
+
+        // The light covers 0.239 of the cosine weighted hemisphere above the
+        // point under it, so the floor there is 0.5 * 4 * 0.239
+        assert!(approx_eq!(f32, traced, 0.478, epsilon = 0.02), "{}", traced);
+        assert!((bounced - traced).abs() < 0.1 * traced, "{} and {} differ", bounced, traced);
+    }
+
+    #[test]
+    fn analytic_lights_cast_shadows() {
+        let down = Ray {a: Point3f {x: 0.0, y: 1.0, z: 0.0}, b: Vector3f {x: 0.0, y: -1.0, z: 0.0}, time: 0.0};
+        let bulb: Arc<dyn LightTrait + Send + Sync> = Arc::new(PointLight {
+            position: Point3f {x: 0.0, y: 2.0, z: 0.0},
+            intensity: Colour {r: 4.0, g: 4.0, b: 4.0},
+        });
+        let sun: Arc<dyn LightTrait + Send + Sync> = Arc::new(DirectionalLight {
+            direction: Vector3f {x: 0.0, y: -1.0, z: -1.0},
+            radiance: Colour {r: 1.0, g: 1.0, b: 1.0},
+        });
+
+        // 4 / 2^2 arriving straight down, reflected by 0.5 / pi
+        let mut scene = floor_with(vec![]);
+        scene.analytic_lights = vec![bulb.clone()];
+        assert_eq!(scene.light_count(), 1);
+        let colour = path_trace(&scene, &down);
+        assert!(approx_eq!(f32, colour.r, 0.5 / f32::consts::PI, epsilon = 1e-6), "{:?}", colour);
+
+        // The sun comes in at 45 degrees
+        scene.analytic_lights = vec![bulb.clone(), sun.clone()];
+        let colour = path_trace(&scene, &down);
+        assert!(approx_eq!(f32, colour.r, (1.0 + f32::consts::FRAC_1_SQRT_2) * 0.5 / f32::consts::PI, epsilon = 1e-6));
+
+        // A shade over the point hides the bulb, but not the sun
+        let mut scene = floor_with(vec![
+            Primative {
+                shape: Arc::new(XZRect {x0: -0.5, x1: 0.5, z0: -0.5, z1: 0.5, k: 1.5, flip_normal: false}),
+                transform: placed(),
+                material: Arc::new(Lambertian::colour(Colour {r: 0.0, g: 0.0, b: 0.0})),
+            },
+        ]);
+        scene.analytic_lights = vec![bulb, sun];
+        let colour = path_trace(&scene, &down);
+        assert!(approx_eq!(f32, colour.r, f32::consts::FRAC_1_SQRT_2 * 0.5 / f32::consts::PI, epsilon = 1e-6));
     }
 }
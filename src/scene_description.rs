@@ -0,0 +1,1442 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::f32;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::animated_transform::{AnimatedTransform, Easing, Keyframe};
+use crate::bvh_tree::SplitStrategy;
+use crate::camera::Camera;
+use crate::core::{
+    Colour,
+    Point3f,
+    Quaternion,
+    Transform,
+    Vector3f,
+    Matrix4x4f,
+    gen_identity,
+    gen_look_at,
+    gen_translate,
+    gen_scale,
+    gen_rotate_x,
+    gen_rotate_y,
+    gen_rotate_z,
+    gen_rotate,
+};
+use crate::instance::{Instance, Prototype};
+use crate::light::{DirectionalLight, LightTrait, PointLight, SpotLight};
+use crate::material2::{
+    MaterialTrait,
+    Lambertian,
+    Metal,
+    Dielectric,
+    DiffuseLight,
+};
+use crate::obj::{ObjError, ObjGroup, load_obj, obj_primatives};
+use crate::primative::{Primative, StaticTransform, TransformTrait};
+use crate::scene2::{Background, Scene};
+use crate::shapes::base::ShapeTrait;
+use crate::shapes::cone::Cone;
+use crate::shapes::csg::{Csg, CsgChild, CsgOperation};
+use crate::shapes::cuboid::Cuboid;
+use crate::shapes::cylinder2::Cylinder;
+use crate::shapes::disk::Disk;
+use crate::shapes::hyperboloid::Hyperboloid;
+use crate::shapes::paraboloid::Paraboloid;
+use crate::shapes::plane::Plane;
+use crate::shapes::rect::{XYRect, XZRect, YZRect};
+use crate::shapes::sdf::{self, SDFShape, SharedSDF};
+use crate::shapes::sphere2::{PartialSphere, Sphere};
+use crate::shapes::triangle_mesh::TriangleMesh;
+use crate::textures::base::Texture;
+use crate::textures::solid_colour::SolidColour;
+
+
+// A scene as it is written to disk. Textures and materials are declared once
+// by name and shared between primatives; a material or texture can also be
+// written inline wherever a name is accepted.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SceneDescription {
+    pub camera: CameraDescription,
+    #[serde(default)]
+    pub textures: HashMap<String, TextureDescription>,
+    #[serde(default)]
+    pub materials: HashMap<String, MaterialDescription>,
+    pub primatives: Vec<PrimativeDescription>,
+    // Groups of primatives built once and placed any number of times by the
+    // instances, which share the prototype's BVHTree
+    #[serde(default)]
+    pub prototypes: HashMap<String, PrototypeDescription>,
+    #[serde(default)]
+    pub instances: Vec<InstanceDescription>,
+    // The sky gradient when not given
+    #[serde(default)]
+    pub background: Background,
+    // Points, spots and suns, which have no shape so sit apart from the
+    // primatives
+    #[serde(default)]
+    pub lights: Vec<LightDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrototypeDescription {
+    #[serde(default)]
+    pub primatives: Vec<PrimativeDescription>,
+    // Prototypes can place other prototypes, as long as none contain themselves
+    #[serde(default)]
+    pub instances: Vec<InstanceDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstanceDescription {
+    pub prototype: String,
+    #[serde(default)]
+    pub transform: Vec<TransformDescription>,
+    #[serde(default)]
+    pub keyframes: Vec<KeyframeDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CameraDescription {
+    pub look_from: Point3f,
+    pub look_at: Point3f,
+    #[serde(default = "default_up")]
+    pub up: Vector3f,
+    pub vfov: f32,
+    // Taken from the image dimensions when not given
+    #[serde(default)]
+    pub aspect: Option<f32>,
+    #[serde(default)]
+    pub aperture: f32,
+    // Defaults to the distance between look_from and look_at
+    #[serde(default)]
+    pub focus_dist: Option<f32>,
+    #[serde(default)]
+    pub time_0: f32,
+    #[serde(default)]
+    pub time_1: f32,
+    // Moves the camera on from where look_from and look_at put it
+    #[serde(default)]
+    pub transform: Vec<TransformDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrimativeDescription {
+    pub shape: ShapeDescription,
+    pub material: MaterialReference,
+    // Applied in order, so [scale, rotate, translate] scales first
+    #[serde(default)]
+    pub transform: Vec<TransformDescription>,
+    // Moves the primative over the camera's exposure, after the transform
+    #[serde(default)]
+    pub keyframes: Vec<KeyframeDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KeyframeDescription {
+    pub time: f32,
+    #[serde(default)]
+    pub transform: Vec<TransformDescription>,
+    // How it moves from this keyframe to the next
+    #[serde(default)]
+    pub easing: Easing,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShapeDescription {
+    Sphere { radius: f32 },
+    // The infinite xz plane, facing up the y axis
+    Plane,
+    // The quadrics below are all around the z axis, with phi_max in radians
+    PartialSphere {
+        radius: f32,
+        z_min: f32,
+        z_max: f32,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+    },
+    Cylinder {
+        radius: f32,
+        z_min: f32,
+        z_max: f32,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+        #[serde(default)]
+        capped: bool,
+    },
+    Disk {
+        #[serde(default)]
+        height: f32,
+        radius: f32,
+        #[serde(default)]
+        inner_radius: f32,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+    },
+    Cone {
+        height: f32,
+        radius: f32,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+    },
+    Paraboloid {
+        radius: f32,
+        #[serde(default)]
+        z_min: f32,
+        z_max: f32,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+    },
+    Hyperboloid {
+        p1: Point3f,
+        p2: Point3f,
+        #[serde(default = "full_sweep")]
+        phi_max: f32,
+    },
+    // Rectangles lying in the plane where the remaining axis equals k, facing
+    // along that axis unless flip_normal is set
+    XyRect {
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+        k: f32,
+        #[serde(default)]
+        flip_normal: bool,
+    },
+    XzRect {
+        x0: f32,
+        x1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        #[serde(default)]
+        flip_normal: bool,
+    },
+    YzRect {
+        y0: f32,
+        y1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+        #[serde(default)]
+        flip_normal: bool,
+    },
+    #[serde(rename = "box")]
+    Cuboid {
+        minimum: Point3f,
+        maximum: Point3f,
+    },
+    // Two closed shapes combined into one, each with its own transforms
+    Csg {
+        operation: CsgOperation,
+        left: Box<CsgChildDescription>,
+        right: Box<CsgChildDescription>,
+    },
+    // A signed distance function sphere traced within the box from minimum
+    // to maximum, which must hold the whole surface
+    Sdf {
+        sdf: SDFDescription,
+        minimum: Point3f,
+        maximum: Point3f,
+        #[serde(default = "default_max_steps")]
+        max_steps: u32,
+        #[serde(default = "default_epsilon")]
+        epsilon: f32,
+        // Below 1 for distance functions that overestimate, such as twist
+        #[serde(default = "default_step_scale")]
+        step_scale: f32,
+    },
+    // A Wavefront OBJ file, relative to the scene file, or to the working
+    // directory for scenes that aren't read from a file. Every group becomes
+    // its own primative unless a single group is picked.
+    Mesh {
+        path: String,
+        #[serde(default)]
+        group: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CsgChildDescription {
+    pub shape: ShapeDescription,
+    #[serde(default)]
+    pub transform: Vec<TransformDescription>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SDFDescription {
+    Sphere { radius: f32 },
+    RoundedBox {
+        half_extents: Vector3f,
+        #[serde(default)]
+        radius: f32,
+    },
+    Torus { major_radius: f32, minor_radius: f32 },
+    Capsule { a: Point3f, b: Point3f, radius: f32 },
+    Mandelbulb {
+        #[serde(default = "default_mandelbulb_power")]
+        power: f32,
+        #[serde(default = "default_mandelbulb_iterations")]
+        iterations: u32,
+    },
+    Union { a: Box<SDFDescription>, b: Box<SDFDescription> },
+    SmoothUnion { a: Box<SDFDescription>, b: Box<SDFDescription>, k: f32 },
+    Intersection { a: Box<SDFDescription>, b: Box<SDFDescription> },
+    Subtraction { a: Box<SDFDescription>, b: Box<SDFDescription> },
+    SmoothSubtraction { a: Box<SDFDescription>, b: Box<SDFDescription>, k: f32 },
+    Repeat { sdf: Box<SDFDescription>, period: Vector3f },
+    Twist { sdf: Box<SDFDescription>, rate: f32 },
+    Translate { sdf: Box<SDFDescription>, offset: Vector3f },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MaterialDescription {
+    Lambertian { albedo: TextureReference },
+    Metal { albedo: Colour, fuzz: f32 },
+    Dielectric { ref_idx: f32 },
+    DiffuseLight {
+        emit: TextureReference,
+        #[serde(default = "default_intensity")]
+        intensity: f32,
+    },
+}
+
+// A light's brightness is its colour scaled by its intensity. The spot's
+// angles are in degrees from its axis.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LightDescription {
+    Point {
+        position: Point3f,
+        #[serde(default = "default_light_colour")]
+        colour: Colour,
+        intensity: f32,
+    },
+    Spot {
+        position: Point3f,
+        look_at: Point3f,
+        #[serde(default = "default_light_colour")]
+        colour: Colour,
+        intensity: f32,
+        cone_angle: f32,
+        // Hard edged at the cone angle when not given
+        falloff_start: Option<f32>,
+    },
+    Directional {
+        // The way the light travels
+        direction: Vector3f,
+        #[serde(default = "default_light_colour")]
+        colour: Colour,
+        intensity: f32,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum MaterialReference {
+    Named(String),
+    Inline(MaterialDescription),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextureDescription {
+    SolidColour { colour: Colour },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum TextureReference {
+    Colour(Colour),
+    Named(String),
+    Inline(TextureDescription),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformDescription {
+    Translate { delta: Vector3f },
+    Scale { x: f32, y: f32, z: f32 },
+    RotateX { theta: f32 },
+    RotateY { theta: f32 },
+    RotateZ { theta: f32 },
+    Rotate { theta: f32, axis: Vector3f },
+    // Turns about x, then y, then z
+    Euler { x: f32, y: f32, z: f32 },
+    // Normalised before use, so it needn't be exactly unit length, but it
+    // can't be zero
+    Quaternion { rotation: Quaternion },
+    // Given row by row, with the translation down the last column
+    Matrix { m: [[f32; 4]; 4] },
+}
+
+// Quaternions shorter than this have no direction to normalise to
+const QUATERNION_EPSILON: f32 = 1e-6;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    UnknownTexture { name: String },
+    UnknownMaterial { name: String },
+    Mesh { path: String, error: ObjError },
+    UnknownGroup { path: String, name: String },
+    CsgMesh { path: String },
+    UnknownPrototype { name: String },
+    PrototypeCycle { name: String },
+    SingularMatrix { m: [[f32; 4]; 4] },
+    ZeroQuaternion { rotation: Quaternion },
+    KeyframeTime { time: f32 },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io(err) => write!(f, "could not read scene: {}", err),
+            SceneError::Parse(err) => write!(f, "could not parse scene: {}", err),
+            SceneError::UnknownTexture { name } => write!(f, "texture \"{}\" is not defined in the scene", name),
+            SceneError::UnknownMaterial { name } => write!(f, "material \"{}\" is not defined in the scene", name),
+            SceneError::Mesh { path, error } => write!(f, "could not load mesh {}: {}", path, error),
+            SceneError::UnknownGroup { path, name } => write!(f, "mesh {} has no group \"{}\"", path, name),
+            SceneError::CsgMesh { path } => write!(f, "mesh {} must have a single group to be used in csg", path),
+            SceneError::UnknownPrototype { name } => write!(f, "prototype \"{}\" is not defined in the scene", name),
+            SceneError::PrototypeCycle { name } => write!(f, "prototype \"{}\" contains an instance of itself", name),
+            SceneError::SingularMatrix { m } => write!(f, "matrix {:?} has no inverse", m),
+            SceneError::ZeroQuaternion { rotation } => write!(f, "quaternion {:?} is too short to be a rotation", rotation),
+            SceneError::KeyframeTime { time } => write!(f, "keyframe time {} is not a finite number", time),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(err) => Some(err),
+            SceneError::Parse(err) => Some(err),
+            SceneError::Mesh { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(err: io::Error) -> Self {
+        SceneError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(err: serde_json::Error) -> Self {
+        SceneError::Parse(err)
+    }
+}
+
+type SharedTexture = Arc<dyn Texture + Send + Sync>;
+type SharedMaterial = Arc<dyn MaterialTrait + Send + Sync>;
+
+fn default_up() -> Vector3f {
+    Vector3f {x: 0.0, y: 1.0, z: 0.0}
+}
+
+fn full_sweep() -> f32 {
+    2.0 * f32::consts::PI
+}
+
+fn default_max_steps() -> u32 {
+    sdf::DEFAULT_MAX_STEPS
+}
+
+fn default_epsilon() -> f32 {
+    sdf::DEFAULT_EPSILON
+}
+
+fn default_step_scale() -> f32 {
+    1.0
+}
+
+fn default_mandelbulb_power() -> f32 {
+    8.0
+}
+
+fn default_mandelbulb_iterations() -> u32 {
+    10
+}
+
+fn default_intensity() -> f32 {
+    1.0
+}
+
+fn default_light_colour() -> Colour {
+    Colour {r: 1.0, g: 1.0, b: 1.0}
+}
+
+pub fn load_scene<R: Read>(mut reader: R, aspect: f32, strategy: SplitStrategy) -> Result<Scene, SceneError> {
+    let mut buffer = String::new();
+    reader.read_to_string(&mut buffer)?;
+
+    SceneDescription::parse(&buffer)?.build_with_strategy(aspect, strategy)
+}
+
+// Loads the scene at path, finding the meshes it uses from the directory the
+// file is in so it renders the same wherever it is run from
+pub fn load_scene_file<P: AsRef<Path>>(path: P, aspect: f32, strategy: SplitStrategy) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let mut buffer = String::new();
+    File::open(path)?.read_to_string(&mut buffer)?;
+
+    let mut description = SceneDescription::parse(&buffer)?;
+    if let Some(directory) = path.parent() {
+        description.resolve_paths(directory);
+    }
+    description.build_with_strategy(aspect, strategy)
+}
+
+impl SceneDescription {
+    pub fn parse(input: &str) -> Result<SceneDescription, SceneError> {
+        Ok(serde_json::from_str(input)?)
+    }
+
+    // Joins relative mesh paths onto directory. Absolute paths are left as
+    // they are.
+    pub fn resolve_paths(&mut self, directory: &Path) {
+        let prototype_primatives = self.prototypes
+            .values_mut()
+            .flat_map(|prototype| prototype.primatives.iter_mut());
+
+        for primative in self.primatives.iter_mut().chain(prototype_primatives) {
+            primative.shape.resolve_paths(directory);
+        }
+    }
+
+    pub fn build(&self, aspect: f32) -> Result<Scene, SceneError> {
+        self.build_with_strategy(aspect, SplitStrategy::default())
+    }
+
+    // Both of the scene's trees are split with strategy
+    pub fn build_with_strategy(&self, aspect: f32, strategy: SplitStrategy) -> Result<Scene, SceneError> {
+        let mut textures: HashMap<String, SharedTexture> = HashMap::new();
+        for (name, texture) in &self.textures {
+            textures.insert(name.clone(), texture.build());
+        }
+
+        let mut materials: HashMap<String, SharedMaterial> = HashMap::new();
+        for (name, material) in &self.materials {
+            materials.insert(name.clone(), material.build(&textures)?);
+        }
+
+        let primatives = self.primatives
+            .iter()
+            .map(|primative| primative.build(&materials, &textures))
+            .collect::<Result<Vec<Vec<Primative>>, SceneError>>()?
+            .concat();
+
+        let mut prototypes = Prototypes {
+            descriptions: &self.prototypes,
+            built: HashMap::new(),
+            building: vec![],
+            materials: &materials,
+            textures: &textures,
+            time_0: self.camera.time_0,
+            time_1: self.camera.time_1,
+        };
+        let instances = prototypes.instances(&self.instances)?;
+
+        let mut scene = Scene::with_instances(
+            primatives,
+            instances,
+            self.camera.build(aspect)?,
+            self.camera.time_0,
+            self.camera.time_1,
+            strategy,
+        );
+        scene.background = self.background;
+        scene.analytic_lights = self.lights.iter().map(LightDescription::build).collect();
+        Ok(scene)
+    }
+}
+
+// Builds each prototype the first time an instance asks for it, so every
+// instance of a name shares the one Arc
+struct Prototypes<'a> {
+    descriptions: &'a HashMap<String, PrototypeDescription>,
+    built: HashMap<String, Arc<Prototype>>,
+    // The chain of prototypes currently being built, to catch cycles
+    building: Vec<String>,
+    materials: &'a HashMap<String, SharedMaterial>,
+    textures: &'a HashMap<String, SharedTexture>,
+    time_0: f32,
+    time_1: f32,
+}
+
+impl<'a> Prototypes<'a> {
+    fn instances(&mut self, descriptions: &[InstanceDescription]) -> Result<Vec<Instance>, SceneError> {
+        descriptions
+            .iter()
+            .map(|instance| Ok(Instance {
+                prototype: self.get(&instance.prototype)?,
+                transform: build_motion(&instance.transform, &instance.keyframes)?,
+            }))
+            .collect()
+    }
+
+    fn get(&mut self, name: &str) -> Result<Arc<Prototype>, SceneError> {
+        if let Some(prototype) = self.built.get(name) {
+            return Ok(prototype.clone());
+        }
+        if self.building.iter().any(|building| building == name) {
+            return Err(SceneError::PrototypeCycle { name: name.to_string() });
+        }
+
+        let description = self.descriptions
+            .get(name)
+            .ok_or_else(|| SceneError::UnknownPrototype { name: name.to_string() })?;
+
+        self.building.push(name.to_string());
+        let primatives = description.primatives
+            .iter()
+            .map(|primative| primative.build(self.materials, self.textures))
+            .collect::<Result<Vec<Vec<Primative>>, SceneError>>()?
+            .concat();
+        let instances = self.instances(&description.instances)?;
+        self.building.pop();
+
+        let prototype = Arc::new(Prototype::new(primatives, instances, self.time_0, self.time_1));
+        self.built.insert(name.to_string(), prototype.clone());
+        Ok(prototype)
+    }
+}
+
+impl CameraDescription {
+    pub fn build(&self, aspect: f32) -> Result<Camera, SceneError> {
+        let placed = gen_look_at(self.look_from, self.look_at, self.up).inverse();
+
+        Ok(Camera::from_transform(
+            &(build_transform(&self.transform)? * placed),
+            self.vfov,
+            self.aspect.unwrap_or(aspect),
+            self.aperture,
+            self.focus_dist.unwrap_or_else(|| Point3f::distance(&self.look_from, &self.look_at)),
+            self.time_0,
+            self.time_1,
+        ))
+    }
+}
+
+impl PrimativeDescription {
+    fn build(
+        &self,
+        materials: &HashMap<String, SharedMaterial>,
+        textures: &HashMap<String, SharedTexture>,
+    ) -> Result<Vec<Primative>, SceneError> {
+        let material = match &self.material {
+            MaterialReference::Named(name) => materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SceneError::UnknownMaterial { name: name.clone() })?,
+            MaterialReference::Inline(description) => description.build(textures)?,
+        };
+
+        self.shape.build(material, build_motion(&self.transform, &self.keyframes)?)
+    }
+}
+
+// Static unless there are keyframes, each of which moves on from the place
+// the transform steps put it. Keyframes can't be put in order unless every
+// time is a number.
+fn build_motion(
+    steps: &[TransformDescription],
+    keyframes: &[KeyframeDescription],
+) -> Result<Arc<dyn TransformTrait + Send + Sync>, SceneError> {
+    let transform = build_transform(steps)?;
+    if keyframes.is_empty() {
+        return Ok(Arc::new(StaticTransform { transform }));
+    }
+
+    Ok(Arc::new(AnimatedTransform::from_keyframes(
+        keyframes
+            .iter()
+            .map(|keyframe| {
+                if !keyframe.time.is_finite() {
+                    return Err(SceneError::KeyframeTime { time: keyframe.time });
+                }
+
+                Ok(Keyframe {
+                    time: keyframe.time,
+                    transform: build_transform(&keyframe.transform)? * transform,
+                    easing: keyframe.easing,
+                })
+            })
+            .collect::<Result<Vec<Keyframe>, SceneError>>()?,
+    )))
+}
+
+fn build_transform(steps: &[TransformDescription]) -> Result<Transform, SceneError> {
+    steps
+        .iter()
+        .try_fold(gen_identity(), |combined, step| Ok(step.build()? * combined))
+}
+
+impl ShapeDescription {
+    // Most shapes make a single primative, but a mesh makes one for each
+    // group in the file
+    pub fn build(
+        &self,
+        material: SharedMaterial,
+        transform: Arc<dyn TransformTrait + Send + Sync>,
+    ) -> Result<Vec<Primative>, SceneError> {
+        if let ShapeDescription::Mesh { path, group } = self {
+            return Ok(obj_primatives(load_groups(path, group.as_ref())?, material, transform));
+        }
+
+        Ok(vec![Primative { shape: self.build_shape()?, material, transform }])
+    }
+
+    fn resolve_paths(&mut self, directory: &Path) {
+        match self {
+            ShapeDescription::Mesh { path, .. } => {
+                *path = directory.join(path.as_str()).to_string_lossy().into_owned();
+            },
+            ShapeDescription::Csg { left, right, .. } => {
+                left.shape.resolve_paths(directory);
+                right.shape.resolve_paths(directory);
+            },
+            _ => {},
+        }
+    }
+
+    // The shape alone, as used inside csg. A mesh is only accepted there when
+    // it comes down to a single group.
+    fn build_shape(&self) -> Result<Arc<dyn ShapeTrait + Send + Sync>, SceneError> {
+        Ok(match self {
+            ShapeDescription::Sphere { radius } => Arc::new(Sphere { radius: *radius }),
+            ShapeDescription::Plane => Arc::new(Plane {}),
+            ShapeDescription::PartialSphere { radius, z_min, z_max, phi_max } => Arc::new(PartialSphere {
+                radius: *radius,
+                z_min: *z_min,
+                z_max: *z_max,
+                phi_max: *phi_max,
+            }),
+            ShapeDescription::Cylinder { radius, z_min, z_max, phi_max, capped } => Arc::new(Cylinder {
+                radius: *radius,
+                z_min: *z_min,
+                z_max: *z_max,
+                phi_max: *phi_max,
+                capped: *capped,
+            }),
+            ShapeDescription::Disk { height, radius, inner_radius, phi_max } => Arc::new(Disk {
+                height: *height,
+                radius: *radius,
+                inner_radius: *inner_radius,
+                phi_max: *phi_max,
+            }),
+            ShapeDescription::Cone { height, radius, phi_max } => Arc::new(Cone {
+                height: *height,
+                radius: *radius,
+                phi_max: *phi_max,
+            }),
+            ShapeDescription::Paraboloid { radius, z_min, z_max, phi_max } => Arc::new(Paraboloid {
+                radius: *radius,
+                z_min: *z_min,
+                z_max: *z_max,
+                phi_max: *phi_max,
+            }),
+            ShapeDescription::Hyperboloid { p1, p2, phi_max } => Arc::new(Hyperboloid {
+                p1: *p1,
+                p2: *p2,
+                phi_max: *phi_max,
+            }),
+            ShapeDescription::XyRect { x0, x1, y0, y1, k, flip_normal } => Arc::new(XYRect {
+                x0: *x0,
+                x1: *x1,
+                y0: *y0,
+                y1: *y1,
+                k: *k,
+                flip_normal: *flip_normal,
+            }),
+            ShapeDescription::XzRect { x0, x1, z0, z1, k, flip_normal } => Arc::new(XZRect {
+                x0: *x0,
+                x1: *x1,
+                z0: *z0,
+                z1: *z1,
+                k: *k,
+                flip_normal: *flip_normal,
+            }),
+            ShapeDescription::YzRect { y0, y1, z0, z1, k, flip_normal } => Arc::new(YZRect {
+                y0: *y0,
+                y1: *y1,
+                z0: *z0,
+                z1: *z1,
+                k: *k,
+                flip_normal: *flip_normal,
+            }),
+            ShapeDescription::Cuboid { minimum, maximum } => Arc::new(Cuboid::new(*minimum, *maximum)),
+            ShapeDescription::Csg { operation, left, right } => Arc::new(Csg {
+                operation: *operation,
+                left: left.build()?,
+                right: right.build()?,
+            }),
+            ShapeDescription::Sdf { sdf, minimum, maximum, max_steps, epsilon, step_scale } => Arc::new(SDFShape {
+                sdf: sdf.build(),
+                bounds: AABB { minimum: *minimum, maximum: *maximum },
+                max_steps: *max_steps,
+                epsilon: *epsilon,
+                step_scale: *step_scale,
+            }),
+            ShapeDescription::Mesh { path, group } => {
+                let mut groups = load_groups(path, group.as_ref())?;
+                if groups.len() != 1 {
+                    return Err(SceneError::CsgMesh { path: path.clone() });
+                }
+
+                Arc::new(TriangleMesh::new(groups.remove(0).mesh))
+            },
+        })
+    }
+}
+
+impl CsgChildDescription {
+    fn build(&self) -> Result<CsgChild, SceneError> {
+        Ok(CsgChild {
+            shape: self.shape.build_shape()?,
+            transform: build_transform(&self.transform)?,
+        })
+    }
+}
+
+fn load_groups(path: &str, group: Option<&String>) -> Result<Vec<ObjGroup>, SceneError> {
+    let groups = load_obj(path).map_err(|error| SceneError::Mesh { path: path.to_string(), error })?;
+
+    match group {
+        Some(name) => groups
+            .into_iter()
+            .find(|group| &group.name == name)
+            .map(|group| vec![group])
+            .ok_or_else(|| SceneError::UnknownGroup { path: path.to_string(), name: name.clone() }),
+        None => Ok(groups),
+    }
+}
+
+impl SDFDescription {
+    fn build(&self) -> SharedSDF {
+        match self {
+            SDFDescription::Sphere { radius } => Arc::new(sdf::Sphere { radius: *radius }),
+            SDFDescription::RoundedBox { half_extents, radius } => Arc::new(sdf::RoundedBox {
+                half_extents: *half_extents,
+                radius: *radius,
+            }),
+            SDFDescription::Torus { major_radius, minor_radius } => Arc::new(sdf::Torus {
+                major_radius: *major_radius,
+                minor_radius: *minor_radius,
+            }),
+            SDFDescription::Capsule { a, b, radius } => Arc::new(sdf::Capsule {
+                a: *a,
+                b: *b,
+                radius: *radius,
+            }),
+            SDFDescription::Mandelbulb { power, iterations } => Arc::new(sdf::Mandelbulb {
+                power: *power,
+                iterations: *iterations,
+            }),
+            SDFDescription::Union { a, b } => Arc::new(sdf::Union { a: a.build(), b: b.build() }),
+            SDFDescription::SmoothUnion { a, b, k } => Arc::new(sdf::SmoothUnion { a: a.build(), b: b.build(), k: *k }),
+            SDFDescription::Intersection { a, b } => Arc::new(sdf::Intersection { a: a.build(), b: b.build() }),
+            SDFDescription::Subtraction { a, b } => Arc::new(sdf::Subtraction { a: a.build(), b: b.build() }),
+            SDFDescription::SmoothSubtraction { a, b, k } => Arc::new(sdf::SmoothSubtraction { a: a.build(), b: b.build(), k: *k }),
+            SDFDescription::Repeat { sdf, period } => Arc::new(sdf::Repeat { sdf: sdf.build(), period: *period }),
+            SDFDescription::Twist { sdf, rate } => Arc::new(sdf::Twist { sdf: sdf.build(), rate: *rate }),
+            SDFDescription::Translate { sdf, offset } => Arc::new(sdf::Translate { sdf: sdf.build(), offset: *offset }),
+        }
+    }
+}
+
+impl MaterialDescription {
+    fn build(&self, textures: &HashMap<String, SharedTexture>) -> Result<SharedMaterial, SceneError> {
+        Ok(match self {
+            MaterialDescription::Lambertian { albedo } => Arc::new(Lambertian {
+                albedo: albedo.build(textures)?,
+            }),
+            MaterialDescription::Metal { albedo, fuzz } => Arc::new(Metal {
+                albedo: *albedo,
+                fuzz: *fuzz,
+            }),
+            MaterialDescription::Dielectric { ref_idx } => Arc::new(Dielectric {
+                ref_idx: *ref_idx,
+            }),
+            MaterialDescription::DiffuseLight { emit, intensity } => Arc::new(DiffuseLight {
+                emit: emit.build(textures)?,
+                intensity: *intensity,
+            }),
+        })
+    }
+}
+
+impl LightDescription {
+    fn build(&self) -> Arc<dyn LightTrait + Send + Sync> {
+        match self {
+            LightDescription::Point { position, colour, intensity } => Arc::new(PointLight {
+                position: *position,
+                intensity: *intensity * *colour,
+            }),
+            LightDescription::Spot { position, look_at, colour, intensity, cone_angle, falloff_start } => Arc::new(SpotLight::new(
+                *position,
+                *look_at,
+                *intensity * *colour,
+                *cone_angle,
+                falloff_start.unwrap_or(*cone_angle),
+            )),
+            LightDescription::Directional { direction, colour, intensity } => Arc::new(DirectionalLight {
+                direction: *direction,
+                radiance: *intensity * *colour,
+            }),
+        }
+    }
+}
+
+impl TextureDescription {
+    fn build(&self) -> SharedTexture {
+        match self {
+            TextureDescription::SolidColour { colour } => Arc::new(SolidColour { colour: *colour }),
+        }
+    }
+}
+
+impl TextureReference {
+    fn build(&self, textures: &HashMap<String, SharedTexture>) -> Result<SharedTexture, SceneError> {
+        match self {
+            TextureReference::Colour(colour) => Ok(Arc::new(SolidColour { colour: *colour })),
+            TextureReference::Named(name) => textures
+                .get(name)
+                .cloned()
+                .ok_or_else(|| SceneError::UnknownTexture { name: name.clone() }),
+            TextureReference::Inline(description) => Ok(description.build()),
+        }
+    }
+}
+
+impl TransformDescription {
+    pub fn build(&self) -> Result<Transform, SceneError> {
+        Ok(match self {
+            TransformDescription::Translate { delta } => gen_translate(*delta),
+            TransformDescription::Scale { x, y, z } => gen_scale(*x, *y, *z),
+            TransformDescription::RotateX { theta } => gen_rotate_x(*theta),
+            TransformDescription::RotateY { theta } => gen_rotate_y(*theta),
+            TransformDescription::RotateZ { theta } => gen_rotate_z(*theta),
+            TransformDescription::Rotate { theta, axis } => gen_rotate(*theta, *axis),
+            TransformDescription::Euler { x, y, z } => Quaternion::from_euler(*x, *y, *z).to_transform(),
+            TransformDescription::Quaternion { rotation } => {
+                if rotation.length() < QUATERNION_EPSILON {
+                    return Err(SceneError::ZeroQuaternion { rotation: *rotation });
+                }
+                rotation.normalise().to_transform()
+            }
+            TransformDescription::Matrix { m } => Transform::from_matrix(Matrix4x4f { m: *m })
+                .ok_or(SceneError::SingularMatrix { m: *m })?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use float_cmp::approx_eq;
+
+    use crate::animated_transform::Easing;
+    use crate::bvh_tree::SplitStrategy;
+    use crate::core::{Colour, Point3f, Vector3f};
+    use crate::ray::Ray;
+    use crate::scene2::{Background, calculate_colour, path_trace};
+    use crate::shapes::base::ShapeTrait;
+
+    use crate::scene_description::{
+        KeyframeDescription,
+        SceneDescription,
+        SceneError,
+        ShapeDescription,
+        TransformDescription,
+        build_motion,
+        build_transform,
+        load_scene_file,
+    };
+
+    const THREE_SPHERE: &str = include_str!("../examples/scenes/three_sphere.json");
+    const SDF: &str = include_str!("../examples/scenes/sdf.json");
+    const LIGHTS: &str = include_str!("../examples/scenes/lights.json");
+
+    #[test]
+    fn loads_example_scene() {
+        let description = SceneDescription::parse(THREE_SPHERE).unwrap();
+        let scene = description.build(2.0).unwrap();
+
+        assert_eq!(scene.primatives.len(), 3);
+        assert!(scene.instances.is_empty());
+    }
+
+    #[test]
+    fn loads_sdf_scene() {
+        let description = SceneDescription::parse(SDF).unwrap();
+
+        match &description.primatives[1].shape {
+            ShapeDescription::Sdf { max_steps, step_scale, .. } => {
+                assert_eq!(*max_steps, 256);
+                assert_eq!(*step_scale, 1.0);
+            },
+            _ => panic!("Expected an sdf"),
+        }
+        assert_eq!(description.build(2.0).unwrap().primatives.len(), 4);
+    }
+
+    #[test]
+    fn cylinder_defaults() {
+        let description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "primatives": [
+                {"shape": {"type": "cylinder", "radius": 1.0, "z_min": 0.0, "z_max": 2.0}, "material": {"type": "dielectric", "ref_idx": 1.5}}
+            ]
+        }"#).unwrap();
+
+        match description.primatives[0].shape {
+            ShapeDescription::Cylinder { phi_max, capped, .. } => {
+                assert_eq!(phi_max, 2.0 * f32::consts::PI);
+                assert!(!capped);
+            },
+            _ => panic!("Expected a cylinder"),
+        }
+        assert!(description.build(2.0).is_ok());
+    }
+
+    #[test]
+    fn quadric_defaults() {
+        let description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "primatives": [
+                {"shape": {"type": "partial_sphere", "radius": 1.0, "z_min": -0.5, "z_max": 0.5}, "material": "glass"},
+                {"shape": {"type": "disk", "radius": 1.0}, "material": "glass"},
+                {"shape": {"type": "cone", "height": 2.0, "radius": 1.0, "phi_max": 3.0}, "material": "glass"},
+                {"shape": {"type": "paraboloid", "radius": 1.0, "z_max": 2.0}, "material": "glass"},
+                {"shape": {"type": "hyperboloid", "p1": {"x": 1.0, "y": -1.0, "z": -1.0}, "p2": {"x": 1.0, "y": 1.0, "z": 1.0}}, "material": "glass"}
+            ],
+            "materials": {
+                "glass": {"type": "dielectric", "ref_idx": 1.5}
+            }
+        }"#).unwrap();
+
+        match description.primatives[1].shape {
+            ShapeDescription::Disk { height, inner_radius, phi_max, .. } => {
+                assert_eq!(height, 0.0);
+                assert_eq!(inner_radius, 0.0);
+                assert_eq!(phi_max, 2.0 * f32::consts::PI);
+            },
+            _ => panic!("Expected a disk"),
+        }
+        match description.primatives[2].shape {
+            ShapeDescription::Cone { phi_max, .. } => assert_eq!(phi_max, 3.0),
+            _ => panic!("Expected a cone"),
+        }
+        assert_eq!(description.build(2.0).unwrap().primatives.len(), 5);
+    }
+
+    #[test]
+    fn rects_and_boxes() {
+        let description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "primatives": [
+                {"shape": {"type": "xy_rect", "x0": 0.0, "x1": 1.0, "y0": 0.0, "y1": 1.0, "k": 0.0}, "material": "white"},
+                {"shape": {"type": "xz_rect", "x0": 0.0, "x1": 1.0, "z0": 0.0, "z1": 1.0, "k": 1.0, "flip_normal": true}, "material": "white"},
+                {"shape": {"type": "yz_rect", "y0": 0.0, "y1": 1.0, "z0": 0.0, "z1": 1.0, "k": 0.0}, "material": "white"},
+                {"shape": {"type": "box", "minimum": {"x": 0.0, "y": 0.0, "z": 0.0}, "maximum": {"x": 1.0, "y": 2.0, "z": 1.0}}, "material": "white"}
+            ],
+            "materials": {
+                "white": {"type": "lambertian", "albedo": {"r": 0.73, "g": 0.73, "b": 0.73}}
+            }
+        }"#).unwrap();
+
+        match description.primatives[0].shape {
+            ShapeDescription::XyRect { flip_normal, .. } => assert!(!flip_normal),
+            _ => panic!("Expected an xy rectangle"),
+        }
+        match description.primatives[3].shape {
+            ShapeDescription::Cuboid { maximum, .. } => assert_eq!(maximum.y, 2.0),
+            _ => panic!("Expected a box"),
+        }
+        assert_eq!(description.build(2.0).unwrap().primatives.len(), 4);
+    }
+
+    #[test]
+    fn plane_shape() {
+        let description = mesh_scene(r#"{"type": "plane"}"#);
+
+        assert!(matches!(description.primatives[0].shape, ShapeDescription::Plane));
+        assert_eq!(description.build(2.0).unwrap().primatives.len(), 1);
+    }
+
+    #[test]
+    fn unknown_shape_type() {
+        let result = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "primatives": [
+                {"shape": {"type": "teapot"}, "material": {"type": "dielectric", "ref_idx": 1.5}}
+            ]
+        }"#);
+
+        match result {
+            Err(SceneError::Parse(err)) => assert!(err.to_string().contains("teapot"), "{}", err),
+            _ => panic!("Expected a parse error"),
+        }
+    }
+
+    #[test]
+    fn missing_material() {
+        let description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "materials": {
+                "glass": {"type": "dielectric", "ref_idx": 1.5}
+            },
+            "primatives": [
+                {"shape": {"type": "sphere", "radius": 1.0}, "material": "glass"},
+                {"shape": {"type": "sphere", "radius": 1.0}, "material": "chrome"}
+            ]
+        }"#).unwrap();
+
+        match description.build(2.0) {
+            Err(SceneError::UnknownMaterial { name }) => assert_eq!(name, "chrome"),
+            _ => panic!("Expected an unknown material error"),
+        }
+    }
+
+    #[test]
+    fn missing_texture() {
+        let description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "materials": {
+                "matte": {"type": "lambertian", "albedo": "checker"}
+            },
+            "primatives": []
+        }"#).unwrap();
+
+        match description.build(2.0) {
+            Err(SceneError::UnknownTexture { name }) => assert_eq!(name, "checker"),
+            _ => panic!("Expected an unknown texture error"),
+        }
+    }
+
+    fn mesh_scene(shape: &str) -> SceneDescription {
+        SceneDescription::parse(&format!(r#"{{
+            "camera": {{"look_from": {{"x": 0.0, "y": 0.0, "z": 5.0}}, "look_at": {{"x": 0.0, "y": 0.0, "z": 0.0}}, "vfov": 90.0}},
+            "primatives": [
+                {{"shape": {}, "material": {{"type": "dielectric", "ref_idx": 1.5}}}}
+            ]
+        }}"#, shape)).unwrap()
+    }
+
+    #[test]
+    fn mesh_groups_become_primatives() {
+        let scene = mesh_scene(r#"{"type": "mesh", "path": "examples/meshes/cube.obj"}"#).build(2.0).unwrap();
+        assert_eq!(scene.primatives.len(), 2);
+
+        let scene = mesh_scene(r#"{"type": "mesh", "path": "examples/meshes/cube.obj", "group": "top"}"#).build(2.0).unwrap();
+        assert_eq!(scene.primatives.len(), 1);
+    }
+
+    #[test]
+    fn missing_mesh() {
+        match mesh_scene(r#"{"type": "mesh", "path": "examples/meshes/teapot.obj"}"#).build(2.0) {
+            Err(SceneError::Mesh { path, .. }) => assert_eq!(path, "examples/meshes/teapot.obj"),
+            _ => panic!("Expected a mesh error"),
+        }
+
+        match mesh_scene(r#"{"type": "mesh", "path": "examples/meshes/cube.obj", "group": "bottom"}"#).build(2.0) {
+            Err(SceneError::UnknownGroup { name, .. }) => assert_eq!(name, "bottom"),
+            _ => panic!("Expected an unknown group error"),
+        }
+    }
+
+    #[test]
+    fn mesh_paths_follow_the_scene_file() {
+        let scene = load_scene_file("examples/scenes/mesh.json", 1.5, SplitStrategy::default()).unwrap();
+        assert_eq!(scene.primatives.len(), 3);
+
+        let mut description = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "primatives": [
+                {"shape": {"type": "mesh", "path": "cube.obj"}, "material": "matte"},
+                {"shape": {"type": "mesh", "path": "/meshes/cube.obj"}, "material": "matte"}
+            ],
+            "prototypes": {
+                "lens": {"primatives": [{"shape": {
+                    "type": "csg",
+                    "operation": "union",
+                    "left": {"shape": {"type": "sphere", "radius": 1.0}},
+                    "right": {"shape": {"type": "mesh", "path": "../cube.obj"}}
+                }, "material": "matte"}]}
+            }
+        }"#).unwrap();
+        description.resolve_paths(Path::new("scenes"));
+
+        let path = |shape: &ShapeDescription| match shape {
+            ShapeDescription::Mesh { path, .. } => path.clone(),
+            ShapeDescription::Csg { right, .. } => match &right.shape {
+                ShapeDescription::Mesh { path, .. } => path.clone(),
+                _ => panic!("Expected a mesh"),
+            },
+            _ => panic!("Expected a mesh"),
+        };
+        assert_eq!(Path::new(&path(&description.primatives[0].shape)), Path::new("scenes/cube.obj"));
+        assert_eq!(path(&description.primatives[1].shape), "/meshes/cube.obj");
+        assert_eq!(
+            Path::new(&path(&description.prototypes["lens"].primatives[0].shape)),
+            Path::new("scenes/../cube.obj"),
+        );
+    }
+
+    #[test]
+    fn csg_shapes() {
+        let scene = mesh_scene(r#"{
+            "type": "csg",
+            "operation": "difference",
+            "left": {"shape": {"type": "box", "minimum": {"x": -1.0, "y": -1.0, "z": -1.0}, "maximum": {"x": 1.0, "y": 1.0, "z": 1.0}}},
+            "right": {
+                "shape": {"type": "csg", "operation": "intersection", "left": {"shape": {"type": "sphere", "radius": 1.0}}, "right": {"shape": {"type": "mesh", "path": "examples/meshes/cube.obj", "group": "top"}}},
+                "transform": [{"type": "translate", "delta": {"x": 0.0, "y": 1.0, "z": 0.0}}]
+            }
+        }"#).build(2.0).unwrap();
+        assert_eq!(scene.primatives.len(), 1);
+
+        match mesh_scene(r#"{
+            "type": "csg",
+            "operation": "union",
+            "left": {"shape": {"type": "sphere", "radius": 1.0}},
+            "right": {"shape": {"type": "mesh", "path": "examples/meshes/cube.obj"}}
+        }"#).build(2.0) {
+            Err(SceneError::CsgMesh { path }) => assert_eq!(path, "examples/meshes/cube.obj"),
+            _ => panic!("Expected a csg mesh error"),
+        }
+    }
+
+    fn prototype_scene(instances: &str) -> SceneDescription {
+        SceneDescription::parse(&format!(r#"{{
+            "camera": {{"look_from": {{"x": 0.0, "y": 0.0, "z": 5.0}}, "look_at": {{"x": 0.0, "y": 0.0, "z": 0.0}}, "vfov": 90.0}},
+            "primatives": [],
+            "prototypes": {{
+                "tree": {{
+                    "primatives": [
+                        {{"shape": {{"type": "sphere", "radius": 1.0}}, "material": {{"type": "lambertian", "albedo": {{"r": 0.1, "g": 0.6, "b": 0.1}}}}}}
+                    ]
+                }},
+                "grove": {{
+                    "instances": [
+                        {{"prototype": "tree"}},
+                        {{"prototype": "tree", "transform": [{{"type": "translate", "delta": {{"x": 3.0, "y": 0.0, "z": 0.0}}}}]}}
+                    ]
+                }},
+                "loop": {{"instances": [{{"prototype": "loop"}}]}}
+            }},
+            "instances": {}
+        }}"#, instances)).unwrap()
+    }
+
+    #[test]
+    fn instances_share_prototypes() {
+        let scene = prototype_scene(r#"[
+            {"prototype": "grove"},
+            {"prototype": "grove", "transform": [{"type": "translate", "delta": {"x": 0.0, "y": 0.0, "z": 3.0}}]},
+            {"prototype": "tree", "transform": [{"type": "scale", "x": 2.0, "y": 2.0, "z": 2.0}]}
+        ]"#).build(2.0).unwrap();
+
+        assert!(scene.primatives.is_empty());
+        assert_eq!(scene.instances.len(), 3);
+        assert!(Arc::ptr_eq(&scene.instances[0].prototype, &scene.instances[1].prototype));
+    }
+
+    #[test]
+    fn missing_and_recursive_prototypes() {
+        match prototype_scene(r#"[{"prototype": "forest"}]"#).build(2.0) {
+            Err(SceneError::UnknownPrototype { name }) => assert_eq!(name, "forest"),
+            _ => panic!("Expected an unknown prototype error"),
+        }
+
+        match prototype_scene(r#"[{"prototype": "loop"}]"#).build(2.0) {
+            Err(SceneError::PrototypeCycle { name }) => assert_eq!(name, "loop"),
+            _ => panic!("Expected a prototype cycle error"),
+        }
+    }
+
+    #[test]
+    fn keyframed_primatives() {
+        let scene = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0, "time_1": 1.0},
+            "primatives": [
+                {
+                    "shape": {"type": "sphere", "radius": 1.0},
+                    "material": {"type": "lambertian", "albedo": {"r": 0.5, "g": 0.5, "b": 0.5}},
+                    "transform": [{"type": "scale", "x": 0.5, "y": 0.5, "z": 0.5}],
+                    "keyframes": [
+                        {"time": 0.0},
+                        {"time": 1.0, "transform": [{"type": "translate", "delta": {"x": 2.0, "y": 0.0, "z": 0.0}}], "easing": "ease_in_out"}
+                    ]
+                }
+            ]
+        }"#).unwrap().build(2.0).unwrap();
+
+        let transform = scene.primatives[0].transform.generate_transform(1.0);
+        assert_eq!(transform.m * Point3f {x: 1.0, y: 0.0, z: 0.0}, Point3f {x: 2.5, y: 0.0, z: 0.0});
+
+        let bounds = scene.primatives[0].bounding_box(0.0, 1.0).unwrap();
+        assert!(bounds.minimum.x <= -0.5 && bounds.maximum.x >= 2.5);
+    }
+
+    #[test]
+    fn keyframe_times_must_be_numbers() {
+        let keyframe = |time: f32| KeyframeDescription { time, transform: vec![], easing: Easing::default() };
+
+        assert!(build_motion(&[], &[]).is_ok());
+        assert!(build_motion(&[], &[keyframe(1.0), keyframe(0.0)]).is_ok());
+        for time in &[f32::NAN, f32::INFINITY] {
+            match build_motion(&[], &[keyframe(0.0), keyframe(*time)]) {
+                Err(SceneError::KeyframeTime { .. }) => (),
+                _ => panic!("Expected a keyframe time error"),
+            }
+        }
+    }
+
+    #[test]
+    fn matrix_transforms() {
+        let scene = mesh_scene(r#"{"type": "sphere", "radius": 1.0}"#);
+        let mut description = scene.clone();
+        description.primatives[0].transform = serde_json::from_str(r#"[
+            {"type": "matrix", "m": [[2.0, 0.0, 0.0, 1.0], [0.0, 2.0, 0.0, 0.0], [0.0, 0.0, 2.0, 0.0], [0.0, 0.0, 0.0, 1.0]]},
+            {"type": "translate", "delta": {"x": 0.0, "y": 1.0, "z": 0.0}}
+        ]"#).unwrap();
+
+        let built = description.build(2.0).unwrap();
+        let transform = built.primatives[0].transform.generate_transform(0.0);
+        assert_eq!(transform.m * Point3f {x: 1.0, y: 1.0, z: 1.0}, Point3f {x: 3.0, y: 3.0, z: 2.0});
+        assert_eq!(transform.m_inv * Point3f {x: 3.0, y: 3.0, z: 2.0}, Point3f {x: 1.0, y: 1.0, z: 1.0});
+
+        let mut singular = scene;
+        singular.primatives[0].transform = serde_json::from_str(r#"[
+            {"type": "matrix", "m": [[1.0, 0.0, 0.0, 0.0], [0.0, 0.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]}
+        ]"#).unwrap();
+        match singular.build(2.0) {
+            Err(SceneError::SingularMatrix { m }) => assert_eq!(m[1][1], 0.0),
+            _ => panic!("Expected a singular matrix error"),
+        }
+    }
+
+    #[test]
+    fn camera_transform() {
+        let scene = SceneDescription::parse(r#"{
+            "camera": {
+                "look_from": {"x": 0.0, "y": 0.0, "z": 0.0},
+                "look_at": {"x": 0.0, "y": 0.0, "z": -1.0},
+                "vfov": 90.0,
+                "transform": [
+                    {"type": "rotate_y", "theta": 1.5707964},
+                    {"type": "translate", "delta": {"x": 0.0, "y": 2.0, "z": 0.0}}
+                ]
+            },
+            "primatives": []
+        }"#).unwrap().build(2.0).unwrap();
+
+        // Turned a quarter to the left, so it looks down -x
+        let ray = scene.camera.get_ray(0.5, 0.5);
+        assert!(approx_eq!(f32, ray.a.y, 2.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, ray.b.unit_vector().x, -1.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn quaternion_transforms() {
+        let rotations: Vec<TransformDescription> = serde_json::from_str(r#"[
+            {"type": "euler", "x": 0.0, "y": 1.5707964, "z": 0.0},
+            {"type": "quaternion", "rotation": {"v": {"x": 0.0, "y": 0.0, "z": 2.0}, "w": 2.0}}
+        ]"#).unwrap();
+
+        // A quarter turn about y, then a quarter turn about z
+        let transform = build_transform(&rotations).unwrap();
+        let p = transform.m * Point3f {x: 1.0, y: 0.0, z: 0.0};
+        assert!(approx_eq!(Point3f, p, Point3f {x: 0.0, y: 0.0, z: -1.0}, epsilon = 1e-6));
+        let p = transform.m * Point3f {x: 0.0, y: 1.0, z: 0.0};
+        assert!(approx_eq!(Point3f, p, Point3f {x: -1.0, y: 0.0, z: 0.0}, epsilon = 1e-6));
+
+        let zero: Vec<TransformDescription> = serde_json::from_str(r#"[
+            {"type": "quaternion", "rotation": {"v": {"x": 0.0, "y": 0.0, "z": 0.0}, "w": 0.0}}
+        ]"#).unwrap();
+        match build_transform(&zero) {
+            Err(SceneError::ZeroQuaternion { rotation }) => assert_eq!(rotation.w, 0.0),
+            _ => panic!("Expected a zero quaternion error"),
+        }
+    }
+
+    #[test]
+    fn lights_and_background() {
+        let scene = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 0.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "background": {"type": "black"},
+            "primatives": [
+                {"shape": {"type": "sphere", "radius": 1.0}, "material": {"type": "diffuse_light", "emit": {"r": 1.0, "g": 0.5, "b": 0.25}, "intensity": 4.0}}
+            ]
+        }"#).unwrap().build(2.0).unwrap();
+
+        let ray = |x| Ray {
+            a: Point3f {x, y: 0.0, z: 5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: -1.0},
+            time: 0.0,
+        };
+        assert_eq!(calculate_colour(&scene, &ray(0.0), 0), Colour {r: 4.0, g: 2.0, b: 1.0});
+        assert_eq!(calculate_colour(&scene, &ray(2.0), 0), Colour {r: 0.0, g: 0.0, b: 0.0});
+
+        // Left out, it is the sky
+        let scene = mesh_scene(r#"{"type": "sphere", "radius": 1.0}"#).build(2.0).unwrap();
+        assert_eq!(scene.background, Background::default());
+        let up = Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: 5.0},
+            b: Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            time: 0.0,
+        };
+        assert_eq!(calculate_colour(&scene, &up, 0), Colour {r: 0.5, g: 0.7, b: 1.0});
+    }
+
+    #[test]
+    fn analytic_lights() {
+        let scene = SceneDescription::parse(r#"{
+            "camera": {"look_from": {"x": 0.0, "y": 1.0, "z": 5.0}, "look_at": {"x": 0.0, "y": 0.0, "z": 0.0}, "vfov": 90.0},
+            "background": {"type": "black"},
+            "primatives": [
+                {"shape": {"type": "xz_rect", "x0": -5.0, "x1": 5.0, "z0": -5.0, "z1": 5.0, "k": 0.0}, "material": {"type": "lambertian", "albedo": {"r": 0.5, "g": 0.5, "b": 0.5}}}
+            ],
+            "lights": [
+                {"type": "point", "position": {"x": 0.0, "y": 2.0, "z": 0.0}, "intensity": 4.0},
+                {"type": "spot", "position": {"x": 0.0, "y": 2.0, "z": 0.0}, "look_at": {"x": 0.0, "y": 3.0, "z": 0.0}, "intensity": 100.0, "cone_angle": 30.0},
+                {"type": "directional", "direction": {"x": 0.0, "y": 1.0, "z": 0.0}, "colour": {"r": 1.0, "g": 0.0, "b": 0.0}, "intensity": 5.0}
+            ]
+        }"#).unwrap().build(2.0).unwrap();
+        assert_eq!(scene.light_count(), 3);
+
+        // Only the point light reaches the floor: the spot points up and the
+        // sun shines from below
+        let down = Ray {
+            a: Point3f {x: 0.0, y: 1.0, z: 0.0},
+            b: Vector3f {x: 0.0, y: -1.0, z: 0.0},
+            time: 0.0,
+        };
+        let colour = path_trace(&scene, &down);
+        assert!(approx_eq!(f32, colour.r, 0.5 / f32::consts::PI, epsilon = 1e-6));
+        assert!(approx_eq!(f32, colour.g, 0.5 / f32::consts::PI, epsilon = 1e-6));
+
+        let scene = SceneDescription::parse(LIGHTS).unwrap().build(1.5).unwrap();
+        assert_eq!(scene.light_count(), 3);
+    }
+}
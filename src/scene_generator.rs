@@ -1,7 +1,16 @@
 // use std::rc::Rc;
 use std::sync::Arc;
 
+use rand::thread_rng;
+use rand::Rng;
+
+use crate::shapes::cuboid::Cuboid;
+use crate::shapes::plane::Plane;
+use crate::shapes::rect::{XYRect, XZRect, YZRect};
 use crate::shapes::sphere2::Sphere;
+use crate::animated_transform::AnimatedTransform;
+use crate::bvh_tree::SplitStrategy;
+use crate::instance::{Instance, Prototype};
 use crate::primative::{Primative, TransformTrait};
 use crate::{camera::Camera, core::{
     Vector3f,
@@ -15,12 +24,14 @@ use crate::{camera::Camera, core::{
     gen_rotate_y,
     gen_rotate_z,
     gen_rotate,
-}, scene2::Scene};
+}, scene2::{Background, Scene}};
 
 use crate::material2::{
-    // MaterialTrait,
+    MaterialTrait,
     Metal,
     Lambertian,
+    Dielectric,
+    DiffuseLight,
     // NormalMaterial,
 };
 
@@ -60,7 +71,7 @@ impl TransformTrait for SRTTransform {
 }
 
 
-pub fn three_sphere(time_0: f32, time_1: f32) -> Scene {
+pub fn three_sphere(time_0: f32, time_1: f32, strategy: SplitStrategy) -> Scene {
     let metal_material = Arc::new(Metal {
         albedo: Colour {r: 0.8, g: 0.8, b: 0.8},
         fuzz: 0.0,
@@ -68,8 +79,8 @@ pub fn three_sphere(time_0: f32, time_1: f32) -> Scene {
 
     let solid_lamb = Arc::new(Lambertian::colour(Colour {r: 0.8, g: 0.1, b: 0.1}));
 
-    Scene {
-        primatives: vec![
+    Scene::new(
+        vec![
             Primative {
                 shape: Arc::new(Sphere {radius: 1.0}),
                 transform: Arc::new(SRTTransform::init(
@@ -107,7 +118,7 @@ pub fn three_sphere(time_0: f32, time_1: f32) -> Scene {
                 material: solid_lamb.clone(),
             },
         ],
-        camera: Camera::create(
+        Camera::create(
             Point3f {x: 1.5, y: 2.5, z: 5.0},
             Point3f {x: 0.0, y: 0.0, z: 0.0},
             Vector3f {x: 0.0, y:1.0, z:0.0},
@@ -118,8 +129,299 @@ pub fn three_sphere(time_0: f32, time_1: f32) -> Scene {
             time_0,
             time_1,
         ),
-        // bvh_trees: vec![],
+        time_0,
+        time_1,
+        strategy,
+    )
+}
+
+fn placed_sphere(centre: Vector3f, radius: f32) -> Arc<SRTTransform> {
+    Arc::new(SRTTransform::init(
+        centre,
+        radius,
+        radius,
+        radius,
+        0.0,
+        Vector3f {x: 0.0, y: 1.0, z: 0.0},
+    ))
+}
+
+pub fn final_weekend(time_0: f32, time_1: f32, strategy: SplitStrategy) -> Scene {
+    let mut rng = thread_rng();
+    let unit_sphere = Arc::new(Sphere {radius: 1.0});
+
+    let mut primatives = vec![
+        Primative {
+            shape: Arc::new(Plane {}),
+            transform: unplaced(),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.5, g: 0.5, b: 0.5})),
+        },
+        Primative {
+            shape: unit_sphere.clone(),
+            transform: placed_sphere(Vector3f {x: 0.0, y: 1.0, z: 0.0}, 1.0),
+            material: Arc::new(Dielectric {ref_idx: 1.5}),
+        },
+        Primative {
+            shape: unit_sphere.clone(),
+            transform: placed_sphere(Vector3f {x: -4.0, y: 1.0, z: 0.0}, 1.0),
+            material: Arc::new(Lambertian::colour(Colour {r: 0.4, g: 0.2, b: 0.1})),
+        },
+        Primative {
+            shape: unit_sphere.clone(),
+            transform: placed_sphere(Vector3f {x: 4.0, y: 1.0, z: 0.0}, 1.0),
+            material: Arc::new(Metal {
+                albedo: Colour {r: 0.7, g: 0.6, b: 0.5},
+                fuzz: 0.0,
+            }),
+        },
+    ];
+
+    let distance_filter = Point3f { x: 4.0, y: 0.2, z: 0.0 };
+    let glass = Arc::new(Dielectric {ref_idx: 1.5});
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let chosen_mat = rng.gen::<f32>();
+            let centre = Point3f {
+                x: a as f32 + 0.9 * rng.gen::<f32>(),
+                y: 0.2,
+                z: b as f32 + 0.9 * rng.gen::<f32>(),
+            };
+
+            if (centre - distance_filter).length() <= 0.9 {
+                continue;
+            }
+
+            // The diffuse spheres bounce up during the exposure
+            if chosen_mat < 0.8 {
+                let start = gen_translate(Vector3f::from(centre)) * gen_scale(0.2, 0.2, 0.2);
+                let rise = gen_translate(Vector3f {x: 0.0, y: 0.5 * rng.gen::<f32>(), z: 0.0});
+
+                primatives.push(Primative {
+                    shape: unit_sphere.clone(),
+                    transform: Arc::new(AnimatedTransform::new(time_0, start, time_1, rise * start)),
+                    material: Arc::new(Lambertian::colour(Colour {
+                        r: rng.gen::<f32>() * rng.gen::<f32>(),
+                        g: rng.gen::<f32>() * rng.gen::<f32>(),
+                        b: rng.gen::<f32>() * rng.gen::<f32>(),
+                    })),
+                });
+                continue;
+            }
+
+            let material: Arc<dyn MaterialTrait + Send + Sync> = match chosen_mat {
+                x if x < 0.95 => Arc::new(Metal {
+                    albedo: Colour {
+                        r: 0.5 * (1.0 + rng.gen::<f32>()),
+                        g: 0.5 * (1.0 + rng.gen::<f32>()),
+                        b: 0.5 * (1.0 + rng.gen::<f32>()),
+                    },
+                    fuzz: 0.5 * rng.gen::<f32>(),
+                }),
+                _ => glass.clone(),
+            };
+
+            primatives.push(Primative {
+                shape: unit_sphere.clone(),
+                transform: placed_sphere(Vector3f::from(centre), 0.2),
+                material,
+            });
+        }
     }
+
+    Scene::new(
+        primatives,
+        Camera::create(
+            Point3f {x: 13.0, y: 2.0, z: 3.0},
+            Point3f {x: 0.0, y: 0.0, z: 0.0},
+            Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            20.0,
+            2.0,
+            0.1,
+            10.0,
+            time_0,
+            time_1,
+        ),
+        time_0,
+        time_1,
+        strategy,
+    )
+}
+
+fn unplaced() -> Arc<SRTTransform> {
+    placed_box(Vector3f {x: 0.0, y: 0.0, z: 0.0}, 0.0)
+}
+
+// Turned about its corner at the origin, then moved into place
+fn placed_box(delta: Vector3f, degrees: f32) -> Arc<SRTTransform> {
+    Arc::new(SRTTransform::init(
+        delta,
+        1.0,
+        1.0,
+        1.0,
+        degrees.to_radians(),
+        Vector3f {x: 0.0, y: 1.0, z: 0.0},
+    ))
+}
+
+// The standard 555 unit Cornell box, with every wall facing into the room,
+// lit only by the panel in the ceiling
+pub fn cornell_box(time_0: f32, time_1: f32, strategy: SplitStrategy) -> Scene {
+    let red = Arc::new(Lambertian::colour(Colour {r: 0.65, g: 0.05, b: 0.05}));
+    let white = Arc::new(Lambertian::colour(Colour {r: 0.73, g: 0.73, b: 0.73}));
+    let green = Arc::new(Lambertian::colour(Colour {r: 0.12, g: 0.45, b: 0.15}));
+    let light = Arc::new(DiffuseLight::colour(Colour {r: 1.0, g: 1.0, b: 1.0}, 15.0));
+
+    let primatives = vec![
+        Primative {
+            shape: Arc::new(YZRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, flip_normal: true}),
+            transform: unplaced(),
+            material: green,
+        },
+        Primative {
+            shape: Arc::new(YZRect {y0: 0.0, y1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, flip_normal: false}),
+            transform: unplaced(),
+            material: red,
+        },
+        Primative {
+            shape: Arc::new(XZRect {x0: 213.0, x1: 343.0, z0: 227.0, z1: 332.0, k: 554.0, flip_normal: true}),
+            transform: unplaced(),
+            material: light,
+        },
+        Primative {
+            shape: Arc::new(XZRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 0.0, flip_normal: false}),
+            transform: unplaced(),
+            material: white.clone(),
+        },
+        Primative {
+            shape: Arc::new(XZRect {x0: 0.0, x1: 555.0, z0: 0.0, z1: 555.0, k: 555.0, flip_normal: true}),
+            transform: unplaced(),
+            material: white.clone(),
+        },
+        Primative {
+            shape: Arc::new(XYRect {x0: 0.0, x1: 555.0, y0: 0.0, y1: 555.0, k: 555.0, flip_normal: true}),
+            transform: unplaced(),
+            material: white.clone(),
+        },
+        Primative {
+            shape: Arc::new(Cuboid::new(
+                Point3f {x: 0.0, y: 0.0, z: 0.0},
+                Point3f {x: 165.0, y: 330.0, z: 165.0},
+            )),
+            transform: placed_box(Vector3f {x: 265.0, y: 0.0, z: 295.0}, 15.0),
+            material: white.clone(),
+        },
+        Primative {
+            shape: Arc::new(Cuboid::new(
+                Point3f {x: 0.0, y: 0.0, z: 0.0},
+                Point3f {x: 165.0, y: 165.0, z: 165.0},
+            )),
+            transform: placed_box(Vector3f {x: 130.0, y: 0.0, z: 65.0}, -18.0),
+            material: white,
+        },
+    ];
+
+    let mut scene = Scene::new(
+        primatives,
+        Camera::create(
+            Point3f {x: 278.0, y: 278.0, z: -800.0},
+            Point3f {x: 278.0, y: 278.0, z: 0.0},
+            Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            40.0,
+            1.0,
+            0.0,
+            10.0,
+            time_0,
+            time_1,
+        ),
+        time_0,
+        time_1,
+        strategy,
+    );
+    scene.background = Background::Black;
+    scene
+}
+
+// A hundred by hundred grid of trees, every one an instance of the same
+// prototype so the whole forest costs the memory of a single tree
+pub fn forest(time_0: f32, time_1: f32, strategy: SplitStrategy) -> Scene {
+    let mut rng = thread_rng();
+    let unit_sphere = Arc::new(Sphere {radius: 1.0});
+    let bark = Arc::new(Lambertian::colour(Colour {r: 0.35, g: 0.2, b: 0.1}));
+    let leaves = Arc::new(Lambertian::colour(Colour {r: 0.15, g: 0.45, b: 0.1}));
+
+    let tree = Arc::new(Prototype::new(
+        vec![
+            Primative {
+                shape: Arc::new(Cuboid::new(
+                    Point3f {x: -0.1, y: 0.0, z: -0.1},
+                    Point3f {x: 0.1, y: 1.2, z: 0.1},
+                )),
+                transform: unplaced(),
+                material: bark,
+            },
+            Primative {
+                shape: unit_sphere.clone(),
+                transform: placed_sphere(Vector3f {x: 0.0, y: 1.4, z: 0.0}, 0.6),
+                material: leaves.clone(),
+            },
+            Primative {
+                shape: unit_sphere,
+                transform: placed_sphere(Vector3f {x: 0.0, y: 2.0, z: 0.0}, 0.4),
+                material: leaves,
+            },
+        ],
+        vec![],
+        time_0,
+        time_1,
+    ));
+
+    let mut instances = vec![];
+    for a in -50..50 {
+        for b in -50..50 {
+            let size = 0.7 + 0.6 * rng.gen::<f32>();
+            instances.push(Instance {
+                prototype: tree.clone(),
+                transform: Arc::new(SRTTransform::init(
+                    Vector3f {
+                        x: 2.0 * a as f32 + 1.5 * rng.gen::<f32>(),
+                        y: 0.0,
+                        z: 2.0 * b as f32 + 1.5 * rng.gen::<f32>(),
+                    },
+                    size,
+                    size,
+                    size,
+                    rng.gen_range(0.0, 2.0 * std::f32::consts::PI),
+                    Vector3f {x: 0.0, y: 1.0, z: 0.0},
+                )),
+            });
+        }
+    }
+
+    let ground = Primative {
+        shape: Arc::new(Plane {}),
+        transform: unplaced(),
+        material: Arc::new(Lambertian::colour(Colour {r: 0.4, g: 0.35, b: 0.2})),
+    };
+
+    Scene::with_instances(
+        vec![ground],
+        instances,
+        Camera::create(
+            Point3f {x: 0.0, y: 12.0, z: 60.0},
+            Point3f {x: 0.0, y: 0.0, z: 20.0},
+            Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            40.0,
+            3.0 / 2.0,
+            0.0,
+            10.0,
+            time_0,
+            time_1,
+        ),
+        time_0,
+        time_1,
+        strategy,
+    )
 }
 
 // pub fn sphere_on_world_normals(time_0: f32, time_1: f32) -> Scene {
@@ -1,12 +1,15 @@
+use std::f32;
+
 use crate::material::HitRecord;
 
 use crate::ray::Ray;
 
 use crate::core::{
     Point3f,
-    Matrix4x4f,
     Normal3f,
 };
+#[cfg(test)]
+use crate::core::Vector3f;
 use crate::aabb::AABB;
 
 pub trait Hitable {
@@ -28,6 +31,14 @@ pub struct Interaction {
     pub v: f32,
 }
 
+// A point picked on a shape's surface for light sampling, in the shape's own
+// space, with the geometric normal there
+#[derive(Copy, Clone, Debug)]
+pub struct ShapeSample {
+    pub p: Point3f,
+    pub normal: Normal3f,
+}
+
 pub trait ShapeTrait {
     fn collide(
         &self,
@@ -41,8 +52,59 @@ pub trait ShapeTrait {
         time_0: f32,
         time_1: f32,
     ) -> Option<AABB>;
+
+    // Every crossing of the surface between t_min and t_max, nearest first,
+    // which CSG needs to know where the ray is inside a shape. By default
+    // collide is called again from just past each hit, so shapes that find
+    // all their roots at once can override it to do less work.
+    fn collide_all(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Vec<Interaction> {
+        let mut crossings = vec![];
+        let mut t_start = t_min;
+
+        while let Some(interaction) = self.collide(ray, t_start, t_max) {
+            t_start = interaction.t + CROSSING_EPSILON * interaction.t.abs().max(1.0);
+            crossings.push(interaction);
+        }
+
+        crossings
+    }
+
+    // Surface area in the shape's own space, or zero for shapes that can't
+    // be sampled as lights
+    fn area(&self) -> f32 {
+        0.0
+    }
+
+    // A point spread evenly over the surface by two uniform random numbers
+    // in 0..1, so its pdf with respect to area is 1 / area
+    fn sample(&self, _u: f32, _v: f32) -> Option<ShapeSample> {
+        None
+    }
+
+    // The t and point of the first crossing between t_min and t_max, as
+    // sample would have given it. Light pdfs are worked out from its normal,
+    // so shapes with shading normals have to give the geometric one here.
+    fn collide_sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, ShapeSample)> {
+        self.collide(ray, t_min, t_max).map(|interaction| {
+            (interaction.t, ShapeSample { p: interaction.p, normal: interaction.normal })
+        })
+    }
 }
 
+// How far past a crossing collide_all starts looking for the next one, scaled
+// by t so the step is not lost to rounding far along the ray
+pub const CROSSING_EPSILON: f32 = 1e-4;
+
 pub fn solve_quadratic(a: f32, b: f32, c: f32) -> (bool, f32, f32) {
     let discriminant = b * b - 4.0 * a * c;
 
@@ -59,3 +121,27 @@ pub fn solve_quadratic(a: f32, b: f32, c: f32) -> (bool, f32, f32) {
         return (true, t0, t1);
     }
 }
+
+// Flat shapes lying in an axis aligned plane would otherwise have boxes with
+// no thickness, which the slab test never reports as hit
+pub const BOUNDS_PADDING: f32 = 1e-4;
+
+// A ray at time 0 from tuples, to keep the shapes' tests short
+#[cfg(test)]
+pub fn ray(origin: (f32, f32, f32), direction: (f32, f32, f32)) -> Ray {
+    Ray {
+        a: Point3f {x: origin.0, y: origin.1, z: origin.2},
+        b: Vector3f {x: direction.0, y: direction.1, z: direction.2},
+        time: 0.0,
+    }
+}
+
+// Angle of the point around the z axis, from 0 to 2 pi
+pub fn get_phi(p: Point3f) -> f32 {
+    let phi = p.y.atan2(p.x);
+    if phi < 0.0 {
+        phi + 2.0 * f32::consts::PI
+    } else {
+        phi
+    }
+}
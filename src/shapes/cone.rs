@@ -0,0 +1,196 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{get_phi, solve_quadratic, Interaction, ShapeTrait};
+
+// A cone with its base of radius on the z = 0 plane, narrowing to its apex at
+// z = height, swept from phi = 0 up to phi_max radians. The base is left open.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Cone {
+    pub height: f32,
+    pub radius: f32,
+    pub phi_max: f32,
+}
+
+impl ShapeTrait for Cone {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let ray_o = ray.origin();
+        let ray_d = ray.direction();
+
+        // x^2 + y^2 = k (z - height)^2
+        let k = (self.radius / self.height) * (self.radius / self.height);
+        let o_z = ray_o.z - self.height;
+
+        let a: f32 = ray_d.x * ray_d.x + ray_d.y * ray_d.y - k * ray_d.z * ray_d.z;
+        let b: f32 = 2.0 * (ray_o.x * ray_d.x + ray_o.y * ray_d.y - k * ray_d.z * o_z);
+        let c: f32 = ray_o.x * ray_o.x + ray_o.y * ray_o.y - k * o_z * o_z;
+
+        let (t0, t1) = if a == 0.0 {
+            // Running parallel to the side of the cone, so the ray crosses it once
+            if b == 0.0 {
+                return None;
+            }
+            (-c / b, -c / b)
+        } else {
+            let (result, t0, t1) = solve_quadratic(a, b, c);
+            if !result {
+                return None;
+            }
+            (t0, t1)
+        };
+
+        for t in &[t0, t1] {
+            if *t <= t_min || *t >= t_max {
+                continue;
+            }
+
+            // Also rejects hits on the mirrored cone above the apex
+            let p = ray.point_at_parameter(*t);
+            if p.z < 0.0 || p.z > self.height {
+                continue;
+            }
+
+            let phi = get_phi(p);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            return Some(Interaction {
+                t: *t,
+                p,
+                normal: Normal3f { x: p.x, y: p.y, z: k * (self.height - p.z) },
+                u: phi / self.phi_max,
+                v: p.z / self.height,
+            });
+        }
+
+        None
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: 0.0,
+            },
+            maximum: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.height,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::cone::Cone;
+
+    fn unit_cone() -> Cone {
+        Cone {
+            height: 1.0,
+            radius: 1.0,
+            phi_max: 2.0 * f32::consts::PI,
+        }
+    }
+
+    // Scenario Outline: Intersecting a cone with a ray
+    //   Given shape ← cone()
+    //     And r ← ray(<origin>, <direction>)
+    //   When xs ← local_intersect(shape, r)
+    //   Then xs.count = 2
+    //     And xs[0].t = <t0>
+    #[test]
+    fn simple_intersection() {
+        let cone = unit_cone();
+
+        // Halfway up the cone is half the radius
+        let result = cone.collide(&ray((0.0, -5.0, 0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.v, 0.5));
+        assert!(approx_eq!(f32, result.u, 0.75, epsilon = 1e-6));
+
+        // Straight down onto the apex
+        let result = cone.collide(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn normal_points_out_and_up() {
+        let cone = unit_cone();
+
+        let result = cone.collide(&ray((5.0, 0.0, 0.5), (-1.0, 0.0, 0.0)), 0.0, 20.0).unwrap();
+        let normal = result.normal.unit_vector();
+        assert!(approx_eq!(f32, normal.x, 0.5f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, normal.y, 0.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, normal.z, 0.5f32.sqrt(), epsilon = 1e-5));
+    }
+
+    #[test]
+    fn missed_intersection() {
+        let cone = unit_cone();
+
+        // Beside the cone, above the apex and through the open base
+        assert!(cone.collide(&ray((0.0, -5.0, -0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(cone.collide(&ray((0.0, -5.0, 1.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(cone.collide(&ray((0.8, -5.0, 0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn ray_from_inside_the_base() {
+        let cone = unit_cone();
+
+        let result = cone.collide(&ray((0.0, 0.0, -1.0), (1.0, 0.0, 2.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 2.0 / 3.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.p.z, 1.0 / 3.0, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn ray_parallel_to_the_slant() {
+        let cone = unit_cone();
+
+        // Along the far side of the cone, so only the near side is crossed
+        let result = cone.collide(&ray((-0.5, 0.0, 0.8), (1.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 0.15, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.p.x, -0.35, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.p.z, 0.65, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let cone = Cone {phi_max: f32::consts::PI, ..unit_cone()};
+
+        // The half facing -y is gone, so the ray reaches the inside of the back
+        let result = cone.collide(&ray((0.0, -5.0, 0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 0.5, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let aabb = Cone {height: 2.0, radius: 0.5, ..unit_cone()}.bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (-0.5, -0.5, 0.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (0.5, 0.5, 2.0));
+    }
+}
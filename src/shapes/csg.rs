@@ -0,0 +1,406 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Point3f,
+    Transform,
+    dot_vn,
+};
+
+use crate::primative::to_world;
+use crate::ray::Ray;
+use crate::shapes::base::{Interaction, ShapeTrait};
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // The right child cut away from the left
+    Difference,
+}
+
+// One side of a CSG node, placed in the node's object space
+#[derive(Clone)]
+pub struct CsgChild {
+    pub shape: Arc<dyn ShapeTrait + Send + Sync>,
+    pub transform: Transform,
+}
+
+// Two shapes combined into one solid. Both children must be closed with
+// outward facing normals, as a crossing is taken as entering a child when the
+// ray runs against its normal.
+#[derive(Clone)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: CsgChild,
+    pub right: CsgChild,
+}
+
+// Crossings closer than this to running along the surface, as the cosine
+// between the ray and the normal, are taken as the ray only touching it
+const GRAZING_EPSILON: f32 = 1e-4;
+
+impl CsgChild {
+    // A ray touching the surface without going in is often found as a single
+    // crossing, which would leave every later one thought to be on the wrong
+    // side of it, so those are dropped
+    fn crossings(&self, ray: &Ray, t_min: f32, t_max: f32) -> Vec<Interaction> {
+        let direction = ray.b.unit_vector();
+
+        self.shape
+            .collide_all(&(self.transform.m_inv * ray), t_min, t_max)
+            .iter()
+            .map(|interaction| to_world(&self.transform, interaction))
+            .filter(|interaction| dot_vn(&direction, &interaction.normal.unit_vector()).abs() > GRAZING_EPSILON)
+            .collect()
+    }
+
+    fn bounding_box(&self, time_0: f32, time_1: f32) -> Option<AABB> {
+        self.shape
+            .bounding_box(time_0, time_1)
+            .map(|bounds| bounds.transform(&self.transform))
+    }
+}
+
+impl Csg {
+    fn keeps(&self, from_left: bool, in_left: bool, in_right: bool) -> bool {
+        match (self.operation, from_left) {
+            (CsgOperation::Union, true) => !in_right,
+            (CsgOperation::Union, false) => !in_left,
+            (CsgOperation::Intersection, true) => in_right,
+            (CsgOperation::Intersection, false) => in_left,
+            (CsgOperation::Difference, true) => !in_right,
+            (CsgOperation::Difference, false) => in_left,
+        }
+    }
+}
+
+// Whether the ray starts inside a child, worked out from the direction of its
+// first crossing. With no crossings it does not matter, as that child then has
+// no surfaces to keep or drop in range.
+fn starts_inside(ray: &Ray, crossings: &[Interaction]) -> bool {
+    crossings
+        .first()
+        .is_some_and(|interaction| dot_vn(&ray.b, &interaction.normal) > 0.0)
+}
+
+impl ShapeTrait for Csg {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        self.collide_all(ray, t_min, t_max).into_iter().next()
+    }
+
+    fn collide_all(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Vec<Interaction> {
+        let left = self.left.crossings(ray, t_min, t_max);
+        let right = self.right.crossings(ray, t_min, t_max);
+
+        let mut in_left = starts_inside(ray, &left);
+        let mut in_right = starts_inside(ray, &right);
+
+        let mut merged: Vec<(bool, Interaction)> = left
+            .into_iter()
+            .map(|interaction| (true, interaction))
+            .chain(right.into_iter().map(|interaction| (false, interaction)))
+            .collect();
+        merged.sort_by(|(_, a), (_, b)| a.t.partial_cmp(&b.t).unwrap());
+
+        let mut kept = vec![];
+        for (from_left, interaction) in merged {
+            if self.keeps(from_left, in_left, in_right) {
+                // A surface cut out of the left child faces into the hole
+                if self.operation == CsgOperation::Difference && !from_left {
+                    kept.push(Interaction { normal: -interaction.normal, ..interaction });
+                } else {
+                    kept.push(interaction);
+                }
+            }
+
+            if from_left {
+                in_left = !in_left;
+            } else {
+                in_right = !in_right;
+            }
+        }
+
+        kept
+    }
+
+    fn bounding_box(
+        &self,
+        time_0: f32,
+        time_1: f32,
+    ) -> Option<AABB> {
+        let left = self.left.bounding_box(time_0, time_1);
+        let right = self.right.bounding_box(time_0, time_1);
+
+        match self.operation {
+            CsgOperation::Union => match (left, right) {
+                (Some(left), Some(right)) => Some(AABB::join(&left, &right)),
+                _ => None,
+            },
+            // Either side bounds the result, so take whichever is given when
+            // the other is unbounded
+            CsgOperation::Intersection => match (left, right) {
+                (Some(left), Some(right)) => Some(overlap(&left, &right)),
+                (bounds, None) | (None, bounds) => bounds,
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+}
+
+// The boxes of two children that do not overlap leave an empty box, which is
+// kept as a point so that it is still valid
+fn overlap(a: &AABB, b: &AABB) -> AABB {
+    let minimum = Point3f {
+        x: a.minimum.x.max(b.minimum.x),
+        y: a.minimum.y.max(b.minimum.y),
+        z: a.minimum.z.max(b.minimum.z),
+    };
+    let maximum = Point3f {
+        x: a.maximum.x.min(b.maximum.x).max(minimum.x),
+        y: a.maximum.y.min(b.maximum.y).max(minimum.y),
+        z: a.maximum.z.min(b.maximum.z).max(minimum.z),
+    };
+
+    AABB { minimum, maximum }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::sync::Arc;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Point3f,
+        Transform,
+        Vector3f,
+        gen_rotate_y,
+        gen_translate,
+        indentity,
+    };
+
+    use crate::ray::Ray;
+
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::csg::{Csg, CsgChild, CsgOperation};
+    use crate::shapes::cuboid::Cuboid;
+    use crate::shapes::sphere2::Sphere;
+
+    fn unit_sphere_at(x: f32) -> CsgChild {
+        CsgChild {
+            shape: Arc::new(Sphere {radius: 1.0}),
+            transform: gen_translate(Vector3f {x, y: 0.0, z: 0.0}),
+        }
+    }
+
+    fn unit_cube() -> CsgChild {
+        CsgChild {
+            shape: Arc::new(Cuboid::new(
+                Point3f {x: -1.0, y: -1.0, z: -1.0},
+                Point3f {x: 1.0, y: 1.0, z: 1.0},
+            )),
+            transform: Transform { m: indentity(), m_inv: indentity() },
+        }
+    }
+
+    // Two unit spheres overlapping between x = -0.5 and 0.5
+    fn overlapping(operation: CsgOperation) -> Csg {
+        Csg {
+            operation,
+            left: unit_sphere_at(-0.5),
+            right: unit_sphere_at(0.5),
+        }
+    }
+
+    fn along_x() -> Ray {
+        Ray {
+            a: Point3f {x: -5.0, y: 0.0, z: 0.0},
+            b: Vector3f {x: 1.0, y: 0.0, z: 0.0},
+            time: 0.0,
+        }
+    }
+
+    fn crossings(csg: &Csg, ray: &Ray) -> Vec<f32> {
+        csg.collide_all(ray, 0.0, 100.0).iter().map(|interaction| interaction.p.x).collect()
+    }
+
+    fn assert_crossings(result: Vec<f32>, expected: &[f32]) {
+        assert_eq!(result.len(), expected.len(), "{:?} != {:?}", result, expected);
+        for (result, expected) in result.iter().zip(expected) {
+            assert!(approx_eq!(f32, *result, *expected, epsilon = 1e-4), "{:?} != {:?}", result, expected);
+        }
+    }
+
+    // Where the spheres overlap both surfaces are inside the other sphere, so
+    // only the outermost two crossings are left
+    #[test]
+    fn union_keeps_the_outside() {
+        let csg = overlapping(CsgOperation::Union);
+
+        assert_crossings(crossings(&csg, &along_x()), &[-1.5, 1.5]);
+        let first = csg.collide(&along_x(), 0.0, 100.0).unwrap();
+        assert!(approx_eq!(f32, first.t, 3.5, epsilon = 1e-4));
+        assert!(approx_eq!(f32, first.normal.x, -1.0, epsilon = 1e-4));
+    }
+
+    #[test]
+    fn intersection_keeps_the_overlap() {
+        let csg = overlapping(CsgOperation::Intersection);
+
+        // A lens, bounded by the far side of each sphere
+        assert_crossings(crossings(&csg, &along_x()), &[-0.5, 0.5]);
+        let result = csg.collide_all(&along_x(), 0.0, 100.0);
+        assert!(result[0].normal.x < 0.0);
+        assert!(result[1].normal.x > 0.0);
+    }
+
+    #[test]
+    fn difference_flips_the_cut_surface() {
+        let csg = overlapping(CsgOperation::Difference);
+
+        assert_crossings(crossings(&csg, &along_x()), &[-1.5, -0.5]);
+        let result = csg.collide_all(&along_x(), 0.0, 100.0);
+        assert!(approx_eq!(f32, result[0].normal.x, -1.0, epsilon = 1e-4));
+        // The right sphere's near side faces into the hole it leaves
+        assert!(approx_eq!(f32, result[1].normal.x, 1.0, epsilon = 1e-4));
+    }
+
+    // Scenario: A ray misses a CSG object
+    #[test]
+    fn ray_misses() {
+        let ray = Ray {
+            a: Point3f {x: 0.0, y: 2.0, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+
+        for operation in &[CsgOperation::Union, CsgOperation::Intersection, CsgOperation::Difference] {
+            assert!(overlapping(*operation).collide(&ray, 0.0, 100.0).is_none());
+        }
+
+        // The spheres do not meet when the ray only crosses one of them
+        let ray = Ray {
+            a: Point3f {x: -1.2, y: 0.0, z: -5.0},
+            b: Vector3f {x: 0.0, y: 0.0, z: 1.0},
+            time: 0.0,
+        };
+        assert!(overlapping(CsgOperation::Intersection).collide(&ray, 0.0, 100.0).is_none());
+    }
+
+    #[test]
+    fn ray_starting_inside() {
+        let csg = overlapping(CsgOperation::Union);
+        let ray = Ray {
+            a: Point3f {x: 0.0, y: 0.0, z: 0.0},
+            b: Vector3f {x: 1.0, y: 0.0, z: 0.0},
+            time: 0.0,
+        };
+
+        // The left sphere's far side at x = 0.5 is inside the right sphere
+        assert_crossings(crossings(&csg, &ray), &[1.5]);
+    }
+
+    #[test]
+    fn ray_grazing_one_side() {
+        let csg = Csg {
+            operation: CsgOperation::Union,
+            left: unit_sphere_at(0.0),
+            right: CsgChild {
+                shape: Arc::new(Sphere {radius: 1.0}),
+                transform: gen_translate(Vector3f {x: 2.0, y: 1.0, z: 0.0}),
+            },
+        };
+        // Touches the top of the left sphere on its way through the right one
+        let ray = Ray {
+            a: Point3f {x: -5.0, y: 1.0, z: 0.0},
+            b: Vector3f {x: 1.0, y: 0.0, z: 0.0},
+            time: 0.0,
+        };
+
+        assert_crossings(crossings(&csg, &ray), &[1.0, 3.0]);
+    }
+
+    #[test]
+    fn hollowed_box() {
+        let csg = Csg {
+            operation: CsgOperation::Difference,
+            left: unit_cube(),
+            right: CsgChild {
+                shape: Arc::new(Sphere {radius: 1.3}),
+                transform: Transform { m: indentity(), m_inv: indentity() },
+            },
+        };
+
+        // Straight through a face the sphere has cut open
+        assert_crossings(crossings(&csg, &along_x()), &[]);
+
+        // Across a corner the box is left solid
+        let ray = Ray {
+            a: Point3f {x: -5.0, y: 0.8, z: 0.8},
+            b: Vector3f {x: 1.0, y: 0.0, z: 0.0},
+            time: 0.0,
+        };
+        let cut = (1.69f32 - 2.0 * 0.8 * 0.8).sqrt();
+        assert_crossings(crossings(&csg, &ray), &[-1.0, -cut, cut, 1.0]);
+    }
+
+    #[test]
+    fn nested_csg() {
+        let lens = Csg {
+            operation: CsgOperation::Intersection,
+            left: unit_sphere_at(-0.5),
+            right: unit_sphere_at(0.5),
+        };
+        let csg = Csg {
+            operation: CsgOperation::Union,
+            left: CsgChild {
+                shape: Arc::new(lens),
+                transform: gen_translate(Vector3f {x: 2.0, y: 0.0, z: 0.0}),
+            },
+            right: unit_sphere_at(-2.0),
+        };
+
+        assert_crossings(crossings(&csg, &along_x()), &[-3.0, -1.0, 1.5, 2.5]);
+    }
+
+    #[test]
+    fn bounding_boxes() {
+        let union = overlapping(CsgOperation::Union).bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((union.minimum.x, union.maximum.x), (-1.5, 1.5));
+
+        let intersection = overlapping(CsgOperation::Intersection).bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((intersection.minimum.x, intersection.maximum.x), (-0.5, 0.5));
+        assert_eq!((intersection.minimum.y, intersection.maximum.y), (-1.0, 1.0));
+
+        let difference = overlapping(CsgOperation::Difference).bounding_box(0.0, 1.0).unwrap();
+        assert_eq!((difference.minimum.x, difference.maximum.x), (-1.5, 0.5));
+
+        // A rotated child is bounded by all of its corners
+        let turned = Csg {
+            operation: CsgOperation::Union,
+            left: CsgChild {
+                transform: gen_rotate_y(0.25 * f32::consts::PI),
+                ..unit_cube()
+            },
+            right: unit_sphere_at(0.0),
+        };
+        let bounds = turned.bounding_box(0.0, 1.0).unwrap();
+        assert!(approx_eq!(f32, bounds.maximum.x, 2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, bounds.minimum.z, -2.0f32.sqrt(), epsilon = 1e-5));
+    }
+}
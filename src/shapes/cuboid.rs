@@ -0,0 +1,170 @@
+use crate::aabb::AABB;
+use crate::core::Point3f;
+
+use crate::ray::Ray;
+use crate::shapes::base::{Interaction, ShapeTrait};
+use crate::shapes::rect::{XYRect, XZRect, YZRect};
+
+// An axis aligned box made of six rectangles, each facing out of the box
+#[derive(Copy, Clone)]
+pub struct Cuboid {
+    pub minimum: Point3f,
+    pub maximum: Point3f,
+    xy: [XYRect; 2],
+    xz: [XZRect; 2],
+    yz: [YZRect; 2],
+}
+
+impl Cuboid {
+    pub fn new(minimum: Point3f, maximum: Point3f) -> Cuboid {
+        let (p0, p1) = (minimum, maximum);
+
+        Cuboid {
+            minimum,
+            maximum,
+            xy: [
+                XYRect {x0: p0.x, x1: p1.x, y0: p0.y, y1: p1.y, k: p0.z, flip_normal: true},
+                XYRect {x0: p0.x, x1: p1.x, y0: p0.y, y1: p1.y, k: p1.z, flip_normal: false},
+            ],
+            xz: [
+                XZRect {x0: p0.x, x1: p1.x, z0: p0.z, z1: p1.z, k: p0.y, flip_normal: true},
+                XZRect {x0: p0.x, x1: p1.x, z0: p0.z, z1: p1.z, k: p1.y, flip_normal: false},
+            ],
+            yz: [
+                YZRect {y0: p0.y, y1: p1.y, z0: p0.z, z1: p1.z, k: p0.x, flip_normal: true},
+                YZRect {y0: p0.y, y1: p1.y, z0: p0.z, z1: p1.z, k: p1.x, flip_normal: false},
+            ],
+        }
+    }
+}
+
+impl ShapeTrait for Cuboid {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let sides: [&dyn ShapeTrait; 6] = [
+            &self.xy[0], &self.xy[1],
+            &self.xz[0], &self.xz[1],
+            &self.yz[0], &self.yz[1],
+        ];
+
+        let mut closest = None;
+        let mut t_max = t_max;
+        for side in sides.iter() {
+            if let Some(interaction) = side.collide(ray, t_min, t_max) {
+                t_max = interaction.t;
+                closest = Some(interaction);
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: self.minimum,
+            maximum: self.maximum,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Point3f,
+        Vector3f,
+        gen_rotate_y,
+        gen_translate,
+    };
+
+    use crate::ray::Ray;
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::cuboid::Cuboid;
+
+    fn unit_cube() -> Cuboid {
+        Cuboid::new(Point3f {x: -1.0, y: -1.0, z: -1.0}, Point3f {x: 1.0, y: 1.0, z: 1.0})
+    }
+
+    // Scenario Outline: A ray intersects a cube
+    //   Given c ← cube()
+    //     And r ← ray(<origin>, <direction>)
+    //   When xs ← local_intersect(c, r)
+    //   Then xs[0].t = <t1>
+    //     And xs[1].t = <t2>
+    #[test]
+    fn every_side() {
+        let cube = unit_cube();
+        let cases = [
+            ((5.0, 0.5, 0.0), (-1.0, 0.0, 0.0), (1.0, 0.0, 0.0)),
+            ((-5.0, 0.5, 0.0), (1.0, 0.0, 0.0), (-1.0, 0.0, 0.0)),
+            ((0.5, 5.0, 0.0), (0.0, -1.0, 0.0), (0.0, 1.0, 0.0)),
+            ((0.5, -5.0, 0.0), (0.0, 1.0, 0.0), (0.0, -1.0, 0.0)),
+            ((0.5, 0.0, 5.0), (0.0, 0.0, -1.0), (0.0, 0.0, 1.0)),
+            ((0.5, 0.0, -5.0), (0.0, 0.0, 1.0), (0.0, 0.0, -1.0)),
+        ];
+
+        for (origin, direction, normal) in cases.iter() {
+            let result = cube.collide(&ray(*origin, *direction), 0.0, 20.0).unwrap();
+            assert_eq!(result.t, 4.0);
+            assert_eq!((result.normal.x, result.normal.y, result.normal.z), *normal);
+        }
+    }
+
+    // Scenario: A ray intersects a cube from the inside
+    #[test]
+    fn internal_intersection() {
+        let cube = unit_cube();
+
+        let result = cube.collide(&ray((0.0, 0.5, 0.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 1.0);
+        assert_eq!(result.normal.z, 1.0);
+        assert!(approx_eq!(f32, result.v, 0.75));
+    }
+
+    // Scenario Outline: A ray misses a cube
+    #[test]
+    fn missed_intersection() {
+        let cube = unit_cube();
+
+        assert!(cube.collide(&ray((-2.0, 0.0, 0.0), (0.2673, 0.5345, 0.8018)), 0.0, 20.0).is_none());
+        assert!(cube.collide(&ray((2.0, 0.0, 2.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        assert!(cube.collide(&ray((2.0, 2.0, 0.0), (-1.0, 0.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let aabb = Cuboid::new(Point3f {x: 0.0, y: 1.0, z: 2.0}, Point3f {x: 3.0, y: 4.0, z: 5.0}).bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (0.0, 1.0, 2.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (3.0, 4.0, 5.0));
+    }
+
+    #[test]
+    fn rotated_cuboid() {
+        let cube = unit_cube();
+
+        // Turned 45 degrees, so the corner sticks out to x = sqrt(2)
+        let transform = gen_translate(Vector3f {x: 5.0, y: 0.0, z: 0.0}) * gen_rotate_y(0.25 * f32::consts::PI);
+
+        let local = Ray {
+            a: transform.m_inv * Point3f {x: 10.0, y: 0.0, z: 0.0},
+            b: transform.m_inv * Vector3f {x: -1.0, y: 0.0, z: 0.0},
+            time: 0.0,
+        };
+
+        let result = cube.collide(&local, 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.0 - 2.0f32.sqrt(), epsilon = 1e-5));
+    }
+}
@@ -0,0 +1,327 @@
+use std::f32;
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{get_phi, solve_quadratic, Interaction, ShapeTrait};
+
+// A cylinder around the z axis, from z_min to z_max, swept from phi = 0 up to
+// phi_max radians. The caps are disks swept through the same angle.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Cylinder {
+    pub radius: f32,
+    pub z_min: f32,
+    pub z_max: f32,
+    pub phi_max: f32,
+    pub capped: bool,
+}
+
+impl ShapeTrait for Cylinder {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let mut closest = self.collide_side(ray, t_min, t_max);
+
+        if self.capped {
+            for z in &[self.z_min, self.z_max] {
+                let t_max = closest.map_or(t_max, |interaction| interaction.t);
+                if let Some(interaction) = self.collide_cap(ray, *z, t_min, t_max) {
+                    closest = Some(interaction);
+                }
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: self.z_min,
+            },
+            maximum: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.z_max,
+            },
+        })
+    }
+}
+
+impl Cylinder {
+    fn collide_side(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<Interaction> {
+        let ray_o = ray.origin();
+        let ray_d = ray.direction();
+
+        let a: f32 = ray_d.x * ray_d.x + ray_d.y * ray_d.y;
+        // Running parallel to the axis, so the ray can only hit the caps
+        if a == 0.0 {
+            return None;
+        }
+        let b: f32 = 2.0 * (ray_o.x * ray_d.x + ray_o.y * ray_d.y);
+        let c: f32 = ray_o.x * ray_o.x + ray_o.y * ray_o.y - self.radius * self.radius;
+
+        let (result, t0, t1) = solve_quadratic(a, b, c);
+        if !result {
+            return None;
+        }
+
+        for t in &[t0, t1] {
+            if *t <= t_min || *t >= t_max {
+                continue;
+            }
+
+            let p = ray.point_at_parameter(*t);
+            if p.z < self.z_min || p.z > self.z_max {
+                continue;
+            }
+
+            let phi = get_phi(p);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            return Some(Interaction {
+                t: *t,
+                p,
+                normal: Normal3f { x: p.x, y: p.y, z: 0.0 },
+                u: phi / self.phi_max,
+                v: (p.z - self.z_min) / (self.z_max - self.z_min),
+            });
+        }
+
+        None
+    }
+
+    fn collide_cap(&self, ray: &Ray, z: f32, t_min: f32, t_max: f32) -> Option<Interaction> {
+        if ray.b.z == 0.0 {
+            return None;
+        }
+
+        let t = (z - ray.a.z) / ray.b.z;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let distance = (p.x * p.x + p.y * p.y).sqrt();
+        if distance > self.radius {
+            return None;
+        }
+
+        let phi = get_phi(p);
+        if phi > self.phi_max {
+            return None;
+        }
+
+        // Caps face away from the body of the cylinder
+        let normal_z = if z == self.z_min { -1.0 } else { 1.0 };
+        Some(Interaction {
+            t,
+            p,
+            normal: Normal3f { x: 0.0, y: 0.0, z: normal_z },
+            u: phi / self.phi_max,
+            v: distance / self.radius,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Vector3f,
+        gen_translate,
+        gen_rotate_x,
+    };
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::cylinder2::Cylinder;
+
+    fn unit_cylinder() -> Cylinder {
+        Cylinder {
+            radius: 1.0,
+            z_min: -1.0,
+            z_max: 1.0,
+            phi_max: 2.0 * f32::consts::PI,
+            capped: false,
+        }
+    }
+
+    // Scenario Outline: A ray misses a cylinder
+    //   Given cyl ← cylinder()
+    //     And r ← ray(<origin>, <direction>)
+    //   When xs ← local_intersect(cyl, r)
+    //   Then xs.count = 0
+    #[test]
+    fn missed_intersection() {
+        let cylinder = unit_cylinder();
+
+        // Outside, running parallel to the axis
+        assert!(cylinder.collide(&ray((1.5, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+        // Inside, running along the axis
+        assert!(cylinder.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+        // Passing by
+        assert!(cylinder.collide(&ray((0.0, -5.0, 0.0), (1.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    // Scenario Outline: A ray strikes a cylinder
+    //   Given cyl ← cylinder()
+    //     And r ← ray(<origin>, <direction>)
+    //   When xs ← local_intersect(cyl, r)
+    //   Then xs.count = 2
+    //     And xs[0].t = <t0>
+    #[test]
+    fn simple_intersection() {
+        let cylinder = unit_cylinder();
+
+        let result = cylinder.collide(&ray((0.0, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 4.0);
+
+        // Tangent
+        let result = cylinder.collide(&ray((1.0, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 5.0);
+    }
+
+    // Scenario: A ray originates inside a cylinder
+    #[test]
+    fn internal_intersection() {
+        let cylinder = unit_cylinder();
+
+        let result = cylinder.collide(&ray((0.0, 0.0, 0.0), (1.0, 0.0, 0.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 1.0);
+    }
+
+    // Scenario Outline: Normal vector on a cylinder
+    //   Given cyl ← cylinder()
+    //   When n ← local_normal_at(cyl, <point>)
+    //   Then n = <normal>
+    #[test]
+    fn normal_and_uv() {
+        let cylinder = unit_cylinder();
+
+        let result = cylinder.collide(&ray((0.0, -5.0, 0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.normal.x, 0.0));
+        assert!(approx_eq!(f32, result.normal.y, -1.0));
+        assert!(approx_eq!(f32, result.normal.z, 0.0));
+        assert!(approx_eq!(f32, result.u, 0.75));
+        assert!(approx_eq!(f32, result.v, 0.75));
+    }
+
+    // Scenario Outline: Intersecting a constrained cylinder
+    //   Given cyl ← cylinder()
+    //     And cyl.minimum ← 1
+    //     And cyl.maximum ← 2
+    //   When xs ← local_intersect(cyl, r)
+    //   Then xs.count = <count>
+    #[test]
+    fn constrained_intersection() {
+        let cylinder = Cylinder {z_min: 1.0, z_max: 2.0, ..unit_cylinder()};
+
+        // Diagonal from inside, leaving through the open top
+        assert!(cylinder.collide(&ray((0.0, 0.0, 1.5), (0.1, 0.0, 1.0)), 0.0, 20.0).is_none());
+        // Above and below the cylinder
+        assert!(cylinder.collide(&ray((0.0, -5.0, 3.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(cylinder.collide(&ray((0.0, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        // Skims the edges, which count as inside
+        assert!(cylinder.collide(&ray((0.0, -5.0, 2.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_some());
+        assert!(cylinder.collide(&ray((0.0, -5.0, 1.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_some());
+    }
+
+    // Scenario Outline: Intersecting the caps of a closed cylinder
+    //   Given cyl ← cylinder()
+    //     And cyl.minimum ← 1
+    //     And cyl.maximum ← 2
+    //     And cyl.closed ← true
+    //   When xs ← local_intersect(cyl, r)
+    //   Then xs.count = <count>
+    #[test]
+    fn capped_intersection() {
+        let cylinder = Cylinder {z_min: 1.0, z_max: 2.0, capped: true, ..unit_cylinder()};
+
+        // Straight down the axis through both caps
+        let result = cylinder.collide(&ray((0.0, 0.0, 3.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 1.0);
+        assert_eq!(result.normal.z, 1.0);
+        assert_eq!(result.v, 0.0);
+
+        // Diagonally through the bottom cap
+        let result = cylinder.collide(&ray((0.0, 0.0, -1.0), (0.0, 0.25, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 2.0));
+        assert_eq!(result.normal.z, -1.0);
+        assert!(approx_eq!(f32, result.v, 0.5));
+
+        // Leaving through the top cap from inside
+        let result = cylinder.collide(&ray((0.0, 0.0, 1.5), (0.1, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 0.5));
+        assert_eq!(result.normal.z, 1.0);
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let cylinder = Cylinder {phi_max: 0.5 * f32::consts::PI, ..unit_cylinder()};
+
+        // The front at phi = 3/2 pi is missing, so the ray hits the inside
+        // of the back wall
+        let result = cylinder.collide(&ray((0.5, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.0 + 0.75f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 2.0 / 3.0, epsilon = 1e-5));
+
+        // Passes through where the rest of the cylinder would be
+        assert!(cylinder.collide(&ray((-0.5, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn partial_caps() {
+        let cylinder = Cylinder {phi_max: f32::consts::PI, capped: true, ..unit_cylinder()};
+
+        assert!(cylinder.collide(&ray((0.0, 0.5, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_some());
+        assert!(cylinder.collide(&ray((0.0, -0.5, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let cylinder = Cylinder {radius: 2.0, z_min: -1.0, z_max: 3.0, ..unit_cylinder()};
+        let aabb = cylinder.bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (-2.0, -2.0, -1.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (2.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn transformed_cylinder() {
+        let cylinder = unit_cylinder();
+
+        // Stood upright along y, then moved along x
+        let transform = gen_translate(Vector3f {x: 5.0, y: 0.0, z: 0.0}) * gen_rotate_x(0.5 * f32::consts::PI);
+
+        let result = cylinder.collide(
+            &(transform.m_inv * ray((5.0, 0.5, -5.0), (0.0, 0.0, 1.0))),
+            0.0,
+            20.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0, epsilon = 1e-5));
+
+        assert!(cylinder.collide(
+            &(transform.m_inv * ray((5.0, 1.5, -5.0), (0.0, 0.0, 1.0))),
+            0.0,
+            20.0,
+        ).is_none());
+    }
+}
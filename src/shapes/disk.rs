@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{get_phi, BOUNDS_PADDING, Interaction, ShapeTrait};
+
+// A disk facing up the z axis at z = height, with a hole of inner_radius in
+// the middle, swept from phi = 0 up to phi_max radians
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Disk {
+    pub height: f32,
+    pub radius: f32,
+    pub inner_radius: f32,
+    pub phi_max: f32,
+}
+
+impl ShapeTrait for Disk {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        // Running parallel to the disk
+        if ray.b.z == 0.0 {
+            return None;
+        }
+
+        let t = (self.height - ray.a.z) / ray.b.z;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        let distance = (p.x * p.x + p.y * p.y).sqrt();
+        if distance > self.radius || distance < self.inner_radius {
+            return None;
+        }
+
+        let phi = get_phi(p);
+        if phi > self.phi_max {
+            return None;
+        }
+
+        // v is 0 on the outer edge and 1 on the inner one
+        Some(Interaction {
+            t,
+            p: Point3f { x: p.x, y: p.y, z: self.height },
+            normal: Normal3f { x: 0.0, y: 0.0, z: 1.0 },
+            u: phi / self.phi_max,
+            v: (self.radius - distance) / (self.radius - self.inner_radius),
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: self.height - BOUNDS_PADDING,
+            },
+            maximum: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.height + BOUNDS_PADDING,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Vector3f,
+        gen_translate,
+        gen_rotate_x,
+    };
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::disk::Disk;
+
+    fn unit_disk() -> Disk {
+        Disk {
+            height: 0.0,
+            radius: 1.0,
+            inner_radius: 0.0,
+            phi_max: 2.0 * f32::consts::PI,
+        }
+    }
+
+    // Scenario: A ray strikes a disk from above
+    //   Given d ← disk(height: 1)
+    //     And r ← ray(point(0, 0, 5), vector(0, 0, -1))
+    //   When xs ← local_intersect(d, r)
+    //   Then xs[0].t = 4
+    #[test]
+    fn simple_intersection() {
+        let disk = Disk {height: 1.0, ..unit_disk()};
+
+        let result = disk.collide(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 4.0);
+        assert_eq!(result.p.z, 1.0);
+        assert_eq!(result.normal.z, 1.0);
+
+        // From below, through the back of the disk
+        let result = disk.collide(&ray((0.5, 0.0, -1.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 2.0);
+    }
+
+    // Scenario: A ray parallel to a disk misses it
+    #[test]
+    fn parallel_ray_misses() {
+        assert!(unit_disk().collide(&ray((-5.0, 0.0, 0.0), (1.0, 0.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn missed_intersection() {
+        let disk = Disk {inner_radius: 0.5, ..unit_disk()};
+
+        // Outside the rim and through the hole
+        assert!(disk.collide(&ray((1.5, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        assert!(disk.collide(&ray((0.25, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        // Behind the ray
+        assert!(disk.collide(&ray((0.75, 0.0, 5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn uv() {
+        let disk = Disk {inner_radius: 0.5, ..unit_disk()};
+
+        let result = disk.collide(&ray((0.0, -0.75, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.u, 0.75));
+        assert!(approx_eq!(f32, result.v, 0.5));
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let disk = Disk {phi_max: 0.5 * f32::consts::PI, ..unit_disk()};
+
+        assert!(disk.collide(&ray((0.5, 0.5, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_some());
+        assert!(disk.collide(&ray((-0.5, 0.5, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        assert!(disk.collide(&ray((0.5, -0.5, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn bounding_box_is_padded() {
+        let aabb = Disk {height: 2.0, radius: 3.0, ..unit_disk()}.bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y), (-3.0, -3.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y), (3.0, 3.0));
+        assert!(aabb.minimum.z < 2.0 && aabb.maximum.z > 2.0);
+        assert!(aabb.hit(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0));
+    }
+
+    #[test]
+    fn transformed_disk() {
+        // Stood up to face -y, then moved along x
+        let transform = gen_translate(Vector3f {x: 5.0, y: 0.0, z: 0.0}) * gen_rotate_x(0.5 * f32::consts::PI);
+
+        let result = unit_disk().collide(
+            &(transform.m_inv * ray((5.0, -5.0, 0.5), (0.0, 1.0, 0.0))),
+            0.0,
+            20.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.0, epsilon = 1e-5));
+    }
+}
@@ -0,0 +1,265 @@
+use std::f32;
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{solve_quadratic, Interaction, ShapeTrait};
+
+// The surface swept out by turning the line from p1 to p2 around the z axis,
+// up to phi_max radians. Depending on the line this is a hyperboloid of one
+// sheet, a cone or a cylinder. p1 and p2 must be at different heights.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Hyperboloid {
+    pub p1: Point3f,
+    pub p2: Point3f,
+    pub phi_max: f32,
+}
+
+impl ShapeTrait for Hyperboloid {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        if self.p1.z == self.p2.z {
+            return None;
+        }
+
+        let ray_o = ray.origin();
+        let ray_d = ray.direction();
+        let (alpha, beta, gamma) = self.coefficients();
+
+        let a: f32 = ray_d.x * ray_d.x + ray_d.y * ray_d.y - alpha * ray_d.z * ray_d.z;
+        let b: f32 = 2.0 * (ray_o.x * ray_d.x + ray_o.y * ray_d.y - alpha * ray_o.z * ray_d.z) - beta * ray_d.z;
+        let c: f32 = ray_o.x * ray_o.x + ray_o.y * ray_o.y - alpha * ray_o.z * ray_o.z - beta * ray_o.z - gamma;
+
+        let (t0, t1) = if a == 0.0 {
+            // Running parallel to one of the lines making up the surface, so the
+            // ray crosses it once
+            if b == 0.0 {
+                return None;
+            }
+            (-c / b, -c / b)
+        } else {
+            let (result, t0, t1) = solve_quadratic(a, b, c);
+            if !result {
+                return None;
+            }
+            (t0, t1)
+        };
+
+        let (z_min, z_max) = (self.p1.z.min(self.p2.z), self.p1.z.max(self.p2.z));
+        for t in &[t0, t1] {
+            if *t <= t_min || *t >= t_max {
+                continue;
+            }
+
+            let p = ray.point_at_parameter(*t);
+            if p.z < z_min || p.z > z_max {
+                continue;
+            }
+
+            let v = (p.z - self.p1.z) / (self.p2.z - self.p1.z);
+            let phi = self.get_phi(p, v);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            return Some(Interaction {
+                t: *t,
+                p,
+                normal: Normal3f { x: p.x, y: p.y, z: -(alpha * p.z + 0.5 * beta) },
+                u: phi / self.phi_max,
+                v,
+            });
+        }
+
+        None
+    }
+
+    // The radius grows away from the narrowest point, so it is largest at
+    // one of the ends
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        let radius = (self.p1.x * self.p1.x + self.p1.y * self.p1.y)
+            .max(self.p2.x * self.p2.x + self.p2.y * self.p2.y)
+            .sqrt();
+
+        Some(AABB {
+            minimum: Point3f {
+                x: -radius,
+                y: -radius,
+                z: self.p1.z.min(self.p2.z),
+            },
+            maximum: Point3f {
+                x: radius,
+                y: radius,
+                z: self.p1.z.max(self.p2.z),
+            },
+        })
+    }
+}
+
+impl Hyperboloid {
+    // Along the line x = m_x z + c_x and y = m_y z + c_y, so the surface is
+    // x^2 + y^2 = alpha z^2 + beta z + gamma
+    fn coefficients(&self) -> (f32, f32, f32) {
+        let m_x = (self.p2.x - self.p1.x) / (self.p2.z - self.p1.z);
+        let m_y = (self.p2.y - self.p1.y) / (self.p2.z - self.p1.z);
+        let c_x = self.p1.x - m_x * self.p1.z;
+        let c_y = self.p1.y - m_y * self.p1.z;
+
+        (
+            m_x * m_x + m_y * m_y,
+            2.0 * (m_x * c_x + m_y * c_y),
+            c_x * c_x + c_y * c_y,
+        )
+    }
+
+    // Measured from where the line crosses the same height, since the line
+    // may twist around the axis
+    fn get_phi(&self, p: Point3f, v: f32) -> f32 {
+        let line_x = (1.0 - v) * self.p1.x + v * self.p2.x;
+        let line_y = (1.0 - v) * self.p1.y + v * self.p2.y;
+
+        let phi = (line_x * p.y - p.x * line_y).atan2(p.x * line_x + p.y * line_y);
+        if phi < 0.0 {
+            phi + 2.0 * f32::consts::PI
+        } else {
+            phi
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Point3f,
+    };
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::cone::Cone;
+    use crate::shapes::cylinder2::Cylinder;
+    use crate::shapes::hyperboloid::Hyperboloid;
+
+    // x^2 + y^2 = 1 + z^2, from z = -1 to 1
+    fn unit_hyperboloid() -> Hyperboloid {
+        Hyperboloid {
+            p1: Point3f {x: 1.0, y: -1.0, z: -1.0},
+            p2: Point3f {x: 1.0, y: 1.0, z: 1.0},
+            phi_max: 2.0 * f32::consts::PI,
+        }
+    }
+
+    // Scenario: A ray strikes a hyperboloid at its waist
+    //   Given h ← hyperboloid(point(1, -1, -1), point(1, 1, 1))
+    //     And r ← ray(point(0, -5, 0), vector(0, 1, 0))
+    //   When xs ← local_intersect(h, r)
+    //   Then xs[0].t = 4
+    #[test]
+    fn simple_intersection() {
+        let hyperboloid = unit_hyperboloid();
+
+        let result = hyperboloid.collide(&ray((0.0, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 0.75, epsilon = 1e-6));
+        assert!(approx_eq!(f32, result.v, 0.5));
+        assert!(approx_eq!(f32, result.normal.y, -1.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.normal.z, 0.0));
+
+        // Wider at the top
+        let result = hyperboloid.collide(&ray((0.0, -5.0, 1.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.0 - 2.0f32.sqrt(), epsilon = 1e-5));
+        assert!(result.normal.z < 0.0);
+    }
+
+    #[test]
+    fn missed_intersection() {
+        let hyperboloid = unit_hyperboloid();
+
+        assert!(hyperboloid.collide(&ray((0.0, -5.0, 1.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(hyperboloid.collide(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        assert!(hyperboloid.collide(&ray((1.2, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn vertical_line_is_a_cylinder() {
+        let hyperboloid = Hyperboloid {
+            p1: Point3f {x: 0.0, y: 1.0, z: -1.0},
+            p2: Point3f {x: 0.0, y: 1.0, z: 1.0},
+            ..unit_hyperboloid()
+        };
+        let cylinder = Cylinder {
+            radius: 1.0,
+            z_min: -1.0,
+            z_max: 1.0,
+            phi_max: 2.0 * f32::consts::PI,
+            capped: false,
+        };
+
+        for r in &[ray((0.3, -5.0, 0.5), (0.0, 1.0, 0.0)), ray((0.0, 0.0, 0.0), (1.0, 0.5, 0.2))] {
+            let expected = cylinder.collide(r, 0.0, 20.0).unwrap();
+            let result = hyperboloid.collide(r, 0.0, 20.0).unwrap();
+            assert!(approx_eq!(f32, result.t, expected.t, epsilon = 1e-5));
+            assert!(approx_eq!(f32, result.normal.z, 0.0));
+        }
+    }
+
+    #[test]
+    fn line_through_axis_is_a_cone() {
+        let hyperboloid = Hyperboloid {
+            p1: Point3f {x: 1.0, y: 0.0, z: 0.0},
+            p2: Point3f {x: 0.0, y: 0.0, z: 1.0},
+            ..unit_hyperboloid()
+        };
+        let cone = Cone {height: 1.0, radius: 1.0, phi_max: 2.0 * f32::consts::PI};
+
+        let r = ray((0.0, -5.0, 0.5), (0.0, 1.0, 0.0));
+        let expected = cone.collide(&r, 0.0, 20.0).unwrap();
+        let result = hyperboloid.collide(&r, 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, expected.t, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, expected.u, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let hyperboloid = Hyperboloid {phi_max: f32::consts::PI, ..unit_hyperboloid()};
+
+        // Only the y >= 0 half at the waist is left
+        let result = hyperboloid.collide(&ray((0.0, -5.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 6.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 0.5, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn flat_line_never_hits() {
+        let hyperboloid = Hyperboloid {
+            p1: Point3f {x: 1.0, y: 0.0, z: 0.0},
+            p2: Point3f {x: 2.0, y: 0.0, z: 0.0},
+            ..unit_hyperboloid()
+        };
+
+        assert!(hyperboloid.collide(&ray((1.5, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn bounding_box() {
+        let aabb = unit_hyperboloid().bounding_box(0.0, 1.0).unwrap();
+        let radius = 2.0f32.sqrt();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (-radius, -radius, -1.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (radius, radius, 1.0));
+    }
+}
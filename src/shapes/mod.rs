@@ -2,3 +2,14 @@ pub mod cylinder;
 pub mod sphere;
 pub mod base;
 pub mod sphere2;
+pub mod cylinder2;
+pub mod triangle_mesh;
+pub mod disk;
+pub mod cone;
+pub mod paraboloid;
+pub mod hyperboloid;
+pub mod rect;
+pub mod cuboid;
+pub mod plane;
+pub mod csg;
+pub mod sdf;
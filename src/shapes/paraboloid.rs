@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{get_phi, solve_quadratic, Interaction, ShapeTrait};
+
+// A paraboloid opening up the z axis from its tip at the origin, reaching
+// radius at z_max. It is clipped to z_min and z_max, which should both be
+// positive, and swept from phi = 0 up to phi_max radians.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Paraboloid {
+    pub radius: f32,
+    pub z_min: f32,
+    pub z_max: f32,
+    pub phi_max: f32,
+}
+
+impl ShapeTrait for Paraboloid {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let ray_o = ray.origin();
+        let ray_d = ray.direction();
+
+        // k (x^2 + y^2) = z
+        let k = self.z_max / (self.radius * self.radius);
+
+        let a: f32 = k * (ray_d.x * ray_d.x + ray_d.y * ray_d.y);
+        let b: f32 = 2.0 * k * (ray_d.x * ray_o.x + ray_d.y * ray_o.y) - ray_d.z;
+        let c: f32 = k * (ray_o.x * ray_o.x + ray_o.y * ray_o.y) - ray_o.z;
+
+        let (t0, t1) = if a == 0.0 {
+            // Running parallel to the axis, so the ray crosses the surface once
+            if b == 0.0 {
+                return None;
+            }
+            (-c / b, -c / b)
+        } else {
+            let (result, t0, t1) = solve_quadratic(a, b, c);
+            if !result {
+                return None;
+            }
+            (t0, t1)
+        };
+
+        for t in &[t0, t1] {
+            if *t <= t_min || *t >= t_max {
+                continue;
+            }
+
+            let p = ray.point_at_parameter(*t);
+            if p.z < self.z_min || p.z > self.z_max {
+                continue;
+            }
+
+            let phi = get_phi(p);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            return Some(Interaction {
+                t: *t,
+                p,
+                normal: Normal3f { x: 2.0 * k * p.x, y: 2.0 * k * p.y, z: -1.0 },
+                u: phi / self.phi_max,
+                v: (p.z - self.z_min) / (self.z_max - self.z_min),
+            });
+        }
+
+        None
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: self.z_min,
+            },
+            maximum: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.z_max,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::paraboloid::Paraboloid;
+
+    // z = x^2 + y^2, up to a radius of 1
+    fn unit_paraboloid() -> Paraboloid {
+        Paraboloid {
+            radius: 1.0,
+            z_min: 0.0,
+            z_max: 1.0,
+            phi_max: 2.0 * f32::consts::PI,
+        }
+    }
+
+    // Scenario: A ray strikes the side of a paraboloid
+    //   Given p ← paraboloid()
+    //     And r ← ray(point(0, -5, 0.25), vector(0, 1, 0))
+    //   When xs ← local_intersect(p, r)
+    //   Then xs[0].t = 4.5
+    #[test]
+    fn simple_intersection() {
+        let paraboloid = unit_paraboloid();
+
+        let result = paraboloid.collide(&ray((0.0, -5.0, 0.25), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 0.75, epsilon = 1e-6));
+        assert!(approx_eq!(f32, result.v, 0.25));
+
+        // The normal points away from the inside of the bowl
+        assert!(approx_eq!(f32, result.normal.x, 0.0, epsilon = 1e-6));
+        assert!(approx_eq!(f32, result.normal.y, -1.0, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.normal.z, -1.0));
+    }
+
+    // Scenario: A ray running parallel to the axis only crosses once
+    #[test]
+    fn axial_intersection() {
+        let paraboloid = unit_paraboloid();
+
+        let result = paraboloid.collide(&ray((0.5, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.75));
+
+        // Down onto the tip
+        let result = paraboloid.collide(&ray((0.0, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.0));
+        assert!(approx_eq!(f32, result.v, 0.0));
+    }
+
+    #[test]
+    fn missed_intersection() {
+        let paraboloid = Paraboloid {z_min: 0.5, ..unit_paraboloid()};
+
+        // Above the rim, below the clipped bottom, and outside the rim
+        assert!(paraboloid.collide(&ray((0.0, -5.0, 1.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(paraboloid.collide(&ray((0.0, -5.0, 0.25), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(paraboloid.collide(&ray((1.5, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        // Through the hole left at the bottom
+        assert!(paraboloid.collide(&ray((0.5, 0.0, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn partial_sweep() {
+        let paraboloid = Paraboloid {phi_max: f32::consts::PI, ..unit_paraboloid()};
+
+        let result = paraboloid.collide(&ray((0.0, -5.0, 0.25), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 5.5, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.u, 0.5, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn bounding_box() {
+        let aabb = Paraboloid {radius: 2.0, z_min: 1.0, z_max: 3.0, ..unit_paraboloid()}.bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (-2.0, -2.0, 1.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (2.0, 2.0, 3.0));
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{Interaction, ShapeTrait};
+
+// The infinite xz plane through the origin, facing up the y axis. It has no
+// bounding box, so the BVHTree tests it against every ray. u and v repeat
+// every unit along x and z.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct Plane {}
+
+impl ShapeTrait for Plane {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        // Parallel rays divide by zero, giving a t the comparison rejects
+        let t = -ray.a.y / ray.b.y;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+
+        Some(Interaction {
+            t,
+            p: Point3f { x: p.x, y: 0.0, z: p.z },
+            normal: Normal3f { x: 0.0, y: 1.0, z: 0.0 },
+            u: p.x - p.x.floor(),
+            v: p.z - p.z.floor(),
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::plane::Plane;
+
+    // Scenario: A ray intersecting a plane from above
+    //   Given p ← plane()
+    //     And r ← ray(point(0, 1, 0), vector(0, -1, 0))
+    //   When xs ← local_intersect(p, r)
+    //   Then xs.count = 1
+    //     And xs[0].t = 1
+    #[test]
+    fn intersect_from_above() {
+        let result = Plane {}.collide(&ray((0.25, 1.0, -1.5), (0.0, -1.0, 0.0)), 0.0, 20.0).unwrap();
+
+        assert_eq!(result.t, 1.0);
+        assert_eq!(result.p.y, 0.0);
+        assert_eq!(result.normal.y, 1.0);
+        assert!(approx_eq!(f32, result.u, 0.25));
+        assert!(approx_eq!(f32, result.v, 0.5));
+    }
+
+    // Scenario: A ray intersecting a plane from below
+    #[test]
+    fn intersect_from_below() {
+        let result = Plane {}.collide(&ray((0.0, -1.0, 0.0), (0.0, 2.0, 1.0)), 0.0, 20.0).unwrap();
+
+        assert_eq!(result.t, 0.5);
+        assert_eq!(result.normal.y, 1.0);
+    }
+
+    // Scenario: Intersect with a ray parallel to the plane
+    // Scenario: Intersect with a coplanar ray
+    #[test]
+    fn parallel_and_coplanar_rays_miss() {
+        assert!(Plane {}.collide(&ray((0.0, 10.0, 0.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+        assert!(Plane {}.collide(&ray((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn respects_t_range() {
+        assert!(Plane {}.collide(&ray((0.0, 1.0, 0.0), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(Plane {}.collide(&ray((0.0, 30.0, 0.0), (0.0, -1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn has_no_bounding_box() {
+        assert!(Plane {}.bounding_box(0.0, 1.0).is_none());
+    }
+}
@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{BOUNDS_PADDING, Interaction, ShapeSample, ShapeTrait};
+
+// Axis aligned rectangles in the plane where the remaining axis equals k. The
+// normal points along that axis, or against it when flip_normal is set, so
+// walls can be made to face into a room.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct XYRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub y0: f32,
+    pub y1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip_normal: bool,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct XZRect {
+    pub x0: f32,
+    pub x1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip_normal: bool,
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct YZRect {
+    pub y0: f32,
+    pub y1: f32,
+    pub z0: f32,
+    pub z1: f32,
+    pub k: f32,
+    #[serde(default)]
+    pub flip_normal: bool,
+}
+
+impl ShapeTrait for XYRect {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        // A ray parallel to the plane divides by zero, giving a t that is
+        // infinite or NaN, so the comparison is written to reject both
+        let t = (self.k - ray.a.z) / ray.b.z;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        if p.x < self.x0 || p.x > self.x1 || p.y < self.y0 || p.y > self.y1 {
+            return None;
+        }
+
+        Some(Interaction {
+            t,
+            p: Point3f { x: p.x, y: p.y, z: self.k },
+            normal: Normal3f { x: 0.0, y: 0.0, z: facing(self.flip_normal) },
+            u: (p.x - self.x0) / (self.x1 - self.x0),
+            v: (p.y - self.y0) / (self.y1 - self.y0),
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f { x: self.x0, y: self.y0, z: self.k - BOUNDS_PADDING },
+            maximum: Point3f { x: self.x1, y: self.y1, z: self.k + BOUNDS_PADDING },
+        })
+    }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.y1 - self.y0)
+    }
+
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        Some(ShapeSample {
+            p: Point3f {
+                x: self.x0 + u * (self.x1 - self.x0),
+                y: self.y0 + v * (self.y1 - self.y0),
+                z: self.k,
+            },
+            normal: Normal3f { x: 0.0, y: 0.0, z: facing(self.flip_normal) },
+        })
+    }
+}
+
+impl ShapeTrait for XZRect {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let t = (self.k - ray.a.y) / ray.b.y;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        if p.x < self.x0 || p.x > self.x1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+
+        Some(Interaction {
+            t,
+            p: Point3f { x: p.x, y: self.k, z: p.z },
+            normal: Normal3f { x: 0.0, y: facing(self.flip_normal), z: 0.0 },
+            u: (p.x - self.x0) / (self.x1 - self.x0),
+            v: (p.z - self.z0) / (self.z1 - self.z0),
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f { x: self.x0, y: self.k - BOUNDS_PADDING, z: self.z0 },
+            maximum: Point3f { x: self.x1, y: self.k + BOUNDS_PADDING, z: self.z1 },
+        })
+    }
+
+    fn area(&self) -> f32 {
+        (self.x1 - self.x0) * (self.z1 - self.z0)
+    }
+
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        Some(ShapeSample {
+            p: Point3f {
+                x: self.x0 + u * (self.x1 - self.x0),
+                y: self.k,
+                z: self.z0 + v * (self.z1 - self.z0),
+            },
+            normal: Normal3f { x: 0.0, y: facing(self.flip_normal), z: 0.0 },
+        })
+    }
+}
+
+impl ShapeTrait for YZRect {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let t = (self.k - ray.a.x) / ray.b.x;
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+
+        let p = ray.point_at_parameter(t);
+        if p.y < self.y0 || p.y > self.y1 || p.z < self.z0 || p.z > self.z1 {
+            return None;
+        }
+
+        Some(Interaction {
+            t,
+            p: Point3f { x: self.k, y: p.y, z: p.z },
+            normal: Normal3f { x: facing(self.flip_normal), y: 0.0, z: 0.0 },
+            u: (p.y - self.y0) / (self.y1 - self.y0),
+            v: (p.z - self.z0) / (self.z1 - self.z0),
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f { x: self.k - BOUNDS_PADDING, y: self.y0, z: self.z0 },
+            maximum: Point3f { x: self.k + BOUNDS_PADDING, y: self.y1, z: self.z1 },
+        })
+    }
+
+    fn area(&self) -> f32 {
+        (self.y1 - self.y0) * (self.z1 - self.z0)
+    }
+
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        Some(ShapeSample {
+            p: Point3f {
+                x: self.k,
+                y: self.y0 + u * (self.y1 - self.y0),
+                z: self.z0 + v * (self.z1 - self.z0),
+            },
+            normal: Normal3f { x: facing(self.flip_normal), y: 0.0, z: 0.0 },
+        })
+    }
+}
+
+fn facing(flip_normal: bool) -> f32 {
+    if flip_normal { -1.0 } else { 1.0 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use float_cmp::approx_eq;
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::rect::{XYRect, XZRect, YZRect};
+
+    // Scenario: A ray striking a rectangle from above
+    //   Given r ← xy_rect(0, 2, 0, 1, k: 1)
+    //     And r ← ray(point(1.5, 0.25, 5), vector(0, 0, -1))
+    //   When xs ← local_intersect(r, r)
+    //   Then xs[0].t = 4
+    #[test]
+    fn xy_intersection() {
+        let rect = XYRect {x0: 0.0, x1: 2.0, y0: 0.0, y1: 1.0, k: 1.0, flip_normal: false};
+
+        let result = rect.collide(&ray((1.5, 0.25, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 4.0);
+        assert_eq!(result.p.z, 1.0);
+        assert_eq!(result.normal.z, 1.0);
+        assert!(approx_eq!(f32, result.u, 0.75));
+        assert!(approx_eq!(f32, result.v, 0.25));
+
+        assert!(rect.collide(&ray((2.5, 0.25, 5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+        assert!(rect.collide(&ray((1.5, 0.25, 5.0), (0.0, 0.0, -1.0)), 0.0, 3.0).is_none());
+    }
+
+    #[test]
+    fn xz_intersection() {
+        let rect = XZRect {x0: -1.0, x1: 1.0, z0: -1.0, z1: 1.0, k: 0.0, flip_normal: true};
+
+        let result = rect.collide(&ray((0.0, -2.0, 0.5), (0.0, 1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 2.0);
+        assert_eq!(result.normal.y, -1.0);
+        assert!(approx_eq!(f32, result.u, 0.5));
+        assert!(approx_eq!(f32, result.v, 0.75));
+
+        assert!(rect.collide(&ray((0.0, -2.0, 1.5), (0.0, 1.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn yz_intersection() {
+        let rect = YZRect {y0: 0.0, y1: 4.0, z0: 0.0, z1: 4.0, k: 3.0, flip_normal: false};
+
+        let result = rect.collide(&ray((0.0, 1.0, 3.0), (1.0, 0.0, 0.0)), 0.0, 20.0).unwrap();
+        assert_eq!(result.t, 3.0);
+        assert_eq!(result.normal.x, 1.0);
+        assert!(approx_eq!(f32, result.u, 0.25));
+        assert!(approx_eq!(f32, result.v, 0.75));
+    }
+
+    // Scenario: Intersecting a rectangle with a parallel ray
+    #[test]
+    fn parallel_ray_misses() {
+        let rect = XYRect {x0: 0.0, x1: 2.0, y0: 0.0, y1: 1.0, k: 1.0, flip_normal: false};
+
+        assert!(rect.collide(&ray((-1.0, 0.5, 1.0), (1.0, 0.0, 0.0)), 0.0, 20.0).is_none());
+        assert!(rect.collide(&ray((-1.0, 0.5, 2.0), (1.0, 0.0, 0.0)), 0.0, 20.0).is_none());
+    }
+
+    #[test]
+    fn bounding_boxes_are_padded() {
+        let ray_down = ray((0.5, 5.0, 0.5), (0.0, -1.0, 0.0));
+        let aabb = XZRect {x0: 0.0, x1: 1.0, z0: 0.0, z1: 1.0, k: 2.0, flip_normal: false}.bounding_box(0.0, 1.0).unwrap();
+        assert!(aabb.minimum.y < 2.0 && aabb.maximum.y > 2.0);
+        assert!(aabb.hit(&ray_down, 0.0, 20.0));
+
+        let aabb = XYRect {x0: 0.0, x1: 1.0, y0: 0.0, y1: 1.0, k: 2.0, flip_normal: false}.bounding_box(0.0, 1.0).unwrap();
+        assert!(aabb.minimum.z < 2.0 && aabb.maximum.z > 2.0);
+
+        let aabb = YZRect {y0: 0.0, y1: 1.0, z0: 0.0, z1: 1.0, k: 2.0, flip_normal: false}.bounding_box(0.0, 1.0).unwrap();
+        assert!(aabb.minimum.x < 2.0 && aabb.maximum.x > 2.0);
+    }
+
+    #[test]
+    fn samples_cover_the_rectangle() {
+        let rect = XZRect {x0: 1.0, x1: 3.0, z0: -1.0, z1: 0.0, k: 4.0, flip_normal: true};
+        assert_eq!(rect.area(), 2.0);
+
+        let sample = rect.sample(0.0, 0.0).unwrap();
+        assert_eq!((sample.p.x, sample.p.y, sample.p.z), (1.0, 4.0, -1.0));
+        assert_eq!(sample.normal.y, -1.0);
+
+        let sample = rect.sample(0.75, 0.5).unwrap();
+        assert_eq!((sample.p.x, sample.p.y, sample.p.z), (2.5, 4.0, -0.5));
+
+        let sample = YZRect {y0: 0.0, y1: 1.0, z0: 0.0, z1: 2.0, k: 3.0, flip_normal: false}.sample(0.5, 0.5).unwrap();
+        assert_eq!((sample.p.x, sample.p.y, sample.p.z), (3.0, 0.5, 1.0));
+        assert_eq!(sample.normal.x, 1.0);
+    }
+}
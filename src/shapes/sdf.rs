@@ -0,0 +1,569 @@
+use std::f32;
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::core::{
+    Normal3f,
+    Point3f,
+    Vector3f,
+    dot_vv,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{Interaction, ShapeTrait};
+
+pub const DEFAULT_MAX_STEPS: u32 = 256;
+pub const DEFAULT_EPSILON: f32 = 1e-4;
+
+// Bisection steps used to pin down a surface the march stepped over
+const REFINE_STEPS: u32 = 16;
+
+// A signed distance function, negative inside the surface. Sphere tracing
+// relies on it never overestimating the distance, so combinators that bend
+// space, like twist, need the shape's step_scale turned down.
+pub trait SDFTrait {
+    fn distance(&self, p: Point3f) -> f32;
+}
+
+pub type SharedSDF = Arc<dyn SDFTrait + Send + Sync>;
+
+// Sphere traces an SDF between where a ray enters and leaves the given bounds,
+// which also place it in the BVHTree
+pub struct SDFShape {
+    pub sdf: SharedSDF,
+    pub bounds: AABB,
+    pub max_steps: u32,
+    pub epsilon: f32,
+    pub step_scale: f32,
+}
+
+impl SDFShape {
+    pub fn new(sdf: SharedSDF, bounds: AABB) -> SDFShape {
+        SDFShape {
+            sdf,
+            bounds,
+            max_steps: DEFAULT_MAX_STEPS,
+            epsilon: DEFAULT_EPSILON,
+            step_scale: 1.0,
+        }
+    }
+
+    // Central differences, pointing out of the surface
+    fn normal(&self, p: Point3f) -> Normal3f {
+        let h = self.epsilon;
+        let gradient = |offset: Vector3f| self.sdf.distance(p + offset) - self.sdf.distance(p - offset);
+
+        Normal3f {
+            x: gradient(Vector3f {x: h, y: 0.0, z: 0.0}),
+            y: gradient(Vector3f {x: 0.0, y: h, z: 0.0}),
+            z: gradient(Vector3f {x: 0.0, y: 0.0, z: h}),
+        }.unit_vector()
+    }
+
+    // The distance changed sign between t0 and t1, so the surface is between
+    fn refine(&self, ray: &Ray, mut t0: f32, mut t1: f32) -> f32 {
+        let outside = self.sdf.distance(ray.point_at_parameter(t0)) > 0.0;
+
+        for _ in 0..REFINE_STEPS {
+            let middle = 0.5 * (t0 + t1);
+            if (self.sdf.distance(ray.point_at_parameter(middle)) > 0.0) == outside {
+                t0 = middle;
+            } else {
+                t1 = middle;
+            }
+        }
+
+        0.5 * (t0 + t1)
+    }
+
+    fn interaction(&self, ray: &Ray, t: f32) -> Interaction {
+        let p = ray.point_at_parameter(t);
+        let normal = self.normal(p);
+
+        // No surface parameterisation to use, so u and v come from the
+        // direction the surface faces
+        let phi = normal.z.atan2(normal.x) + f32::consts::PI;
+        let theta = normal.y.clamp(-1.0, 1.0).acos();
+
+        Interaction {
+            t,
+            p,
+            normal,
+            u: phi / (2.0 * f32::consts::PI),
+            v: theta / f32::consts::PI,
+        }
+    }
+}
+
+impl ShapeTrait for SDFShape {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let (t_start, t_end) = self.bounds.range(ray, t_min, t_max)?;
+        // The ray direction is not normalised, so distances are scaled to t
+        let speed = ray.b.length();
+
+        // A ray leaving the surface it was scattered from would be caught at
+        // once, so hits only count once the ray has been clear of a surface
+        let mut clear = t_start > t_min;
+        let mut previous = None;
+        let mut t = t_start;
+
+        for _ in 0..self.max_steps {
+            let distance = self.sdf.distance(ray.point_at_parameter(t));
+
+            if clear {
+                if distance.abs() < self.epsilon {
+                    return Some(self.interaction(ray, t));
+                }
+
+                if let Some((t_previous, distance_previous)) = previous {
+                    if (distance > 0.0) != (distance_previous > 0.0) {
+                        return Some(self.interaction(ray, self.refine(ray, t_previous, t)));
+                    }
+                }
+            } else if distance.abs() >= self.epsilon {
+                clear = true;
+            }
+
+            previous = Some((t, distance));
+            t += self.step_scale * distance.abs().max(self.epsilon) / speed;
+            if t >= t_end {
+                return None;
+            }
+        }
+
+        None
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(self.bounds)
+    }
+}
+
+pub struct Sphere {
+    pub radius: f32,
+}
+
+// A box of half_extents centred on the origin, with edges rounded off by
+// radius. A radius of zero gives a sharp box.
+pub struct RoundedBox {
+    pub half_extents: Vector3f,
+    pub radius: f32,
+}
+
+// Lying in the xz plane around the y axis
+pub struct Torus {
+    pub major_radius: f32,
+    pub minor_radius: f32,
+}
+
+// The points within radius of the line from a to b
+pub struct Capsule {
+    pub a: Point3f,
+    pub b: Point3f,
+    pub radius: f32,
+}
+
+// The power 8 Mandelbulb fractal fits within a radius of about 1.2
+pub struct Mandelbulb {
+    pub power: f32,
+    pub iterations: u32,
+}
+
+pub struct Union {
+    pub a: SharedSDF,
+    pub b: SharedSDF,
+}
+
+// Blends the shapes together where they are within k of each other. With k
+// at zero or below it is a plain union.
+pub struct SmoothUnion {
+    pub a: SharedSDF,
+    pub b: SharedSDF,
+    pub k: f32,
+}
+
+pub struct Intersection {
+    pub a: SharedSDF,
+    pub b: SharedSDF,
+}
+
+// a with b carved out of it
+pub struct Subtraction {
+    pub a: SharedSDF,
+    pub b: SharedSDF,
+}
+
+// Subtraction with the edge rounded off by k, or left sharp when k is not
+// above zero
+pub struct SmoothSubtraction {
+    pub a: SharedSDF,
+    pub b: SharedSDF,
+    pub k: f32,
+}
+
+// Repeats the shape forever along each axis with a period above zero
+pub struct Repeat {
+    pub sdf: SharedSDF,
+    pub period: Vector3f,
+}
+
+// Turns the shape around the y axis by rate radians per unit of height
+pub struct Twist {
+    pub sdf: SharedSDF,
+    pub rate: f32,
+}
+
+pub struct Translate {
+    pub sdf: SharedSDF,
+    pub offset: Vector3f,
+}
+
+impl SDFTrait for Sphere {
+    fn distance(&self, p: Point3f) -> f32 {
+        Vector3f::from(p).length() - self.radius
+    }
+}
+
+impl SDFTrait for RoundedBox {
+    fn distance(&self, p: Point3f) -> f32 {
+        let q = Vector3f {
+            x: p.x.abs() - self.half_extents.x + self.radius,
+            y: p.y.abs() - self.half_extents.y + self.radius,
+            z: p.z.abs() - self.half_extents.z + self.radius,
+        };
+        let outside = Vector3f {x: q.x.max(0.0), y: q.y.max(0.0), z: q.z.max(0.0)};
+
+        outside.length() + q.x.max(q.y).max(q.z).min(0.0) - self.radius
+    }
+}
+
+impl SDFTrait for Torus {
+    fn distance(&self, p: Point3f) -> f32 {
+        let ring = (p.x * p.x + p.z * p.z).sqrt() - self.major_radius;
+
+        (ring * ring + p.y * p.y).sqrt() - self.minor_radius
+    }
+}
+
+impl SDFTrait for Capsule {
+    fn distance(&self, p: Point3f) -> f32 {
+        let pa = p - self.a;
+        let ba = self.b - self.a;
+        let h = (dot_vv(&pa, &ba) / dot_vv(&ba, &ba)).clamp(0.0, 1.0);
+
+        (pa - ba * h).length() - self.radius
+    }
+}
+
+impl SDFTrait for Mandelbulb {
+    fn distance(&self, p: Point3f) -> f32 {
+        let mut z = Vector3f::from(p);
+        let mut dr = 1.0;
+        let mut r = z.length();
+
+        for _ in 0..self.iterations {
+            if r > 2.0 || r == 0.0 {
+                break;
+            }
+
+            let theta = (z.z / r).acos() * self.power;
+            let phi = z.y.atan2(z.x) * self.power;
+            let zr = r.powf(self.power);
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            z = Vector3f {
+                x: zr * theta.sin() * phi.cos() + p.x,
+                y: zr * theta.sin() * phi.sin() + p.y,
+                z: zr * theta.cos() + p.z,
+            };
+            r = z.length();
+        }
+
+        if r == 0.0 {
+            return 0.0;
+        }
+
+        0.5 * r.ln() * r / dr
+    }
+}
+
+impl SDFTrait for Union {
+    fn distance(&self, p: Point3f) -> f32 {
+        self.a.distance(p).min(self.b.distance(p))
+    }
+}
+
+impl SDFTrait for SmoothUnion {
+    fn distance(&self, p: Point3f) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        if self.k <= 0.0 {
+            return a.min(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+
+        b + (a - b) * h - self.k * h * (1.0 - h)
+    }
+}
+
+impl SDFTrait for Intersection {
+    fn distance(&self, p: Point3f) -> f32 {
+        self.a.distance(p).max(self.b.distance(p))
+    }
+}
+
+impl SDFTrait for Subtraction {
+    fn distance(&self, p: Point3f) -> f32 {
+        self.a.distance(p).max(-self.b.distance(p))
+    }
+}
+
+impl SDFTrait for SmoothSubtraction {
+    fn distance(&self, p: Point3f) -> f32 {
+        // A smoothed max(a, -b)
+        let (a, b) = (self.a.distance(p), -self.b.distance(p));
+        if self.k <= 0.0 {
+            return a.max(b);
+        }
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+
+        a + (b - a) * h + self.k * h * (1.0 - h)
+    }
+}
+
+impl SDFTrait for Repeat {
+    fn distance(&self, p: Point3f) -> f32 {
+        let wrap = |value: f32, period: f32| {
+            if period > 0.0 {
+                value - period * (value / period).round()
+            } else {
+                value
+            }
+        };
+
+        self.sdf.distance(Point3f {
+            x: wrap(p.x, self.period.x),
+            y: wrap(p.y, self.period.y),
+            z: wrap(p.z, self.period.z),
+        })
+    }
+}
+
+impl SDFTrait for Twist {
+    fn distance(&self, p: Point3f) -> f32 {
+        let (sin, cos) = (self.rate * p.y).sin_cos();
+
+        self.sdf.distance(Point3f {
+            x: cos * p.x - sin * p.z,
+            y: p.y,
+            z: sin * p.x + cos * p.z,
+        })
+    }
+}
+
+impl SDFTrait for Translate {
+    fn distance(&self, p: Point3f) -> f32 {
+        self.sdf.distance(p - self.offset)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+    use std::sync::Arc;
+    use float_cmp::approx_eq;
+
+    use crate::aabb::AABB;
+    use crate::core::{
+        Point3f,
+        Vector3f,
+    };
+
+    use crate::shapes::base::{ShapeTrait, ray};
+    use crate::shapes::sdf::*;
+
+    fn bounds(extent: f32) -> AABB {
+        AABB {
+            minimum: Point3f {x: -extent, y: -extent, z: -extent},
+            maximum: Point3f {x: extent, y: extent, z: extent},
+        }
+    }
+
+    fn point(x: f32, y: f32, z: f32) -> Point3f {
+        Point3f {x, y, z}
+    }
+
+    fn unit_sphere() -> SharedSDF {
+        Arc::new(Sphere {radius: 1.0})
+    }
+
+    #[test]
+    fn primitive_distances() {
+        assert!(approx_eq!(f32, unit_sphere().distance(point(0.0, 3.0, 0.0)), 2.0));
+
+        let cube = RoundedBox {half_extents: Vector3f {x: 1.0, y: 1.0, z: 1.0}, radius: 0.0};
+        assert!(approx_eq!(f32, cube.distance(point(3.0, 0.0, 0.0)), 2.0));
+        assert!(approx_eq!(f32, cube.distance(point(2.0, 2.0, 1.0)), 2.0f32.sqrt()));
+        assert!(approx_eq!(f32, cube.distance(point(0.5, 0.0, 0.0)), -0.5));
+
+        // Rounding pulls the corner in, but leaves the faces in place
+        let rounded = RoundedBox {radius: 0.5, ..cube};
+        assert!(approx_eq!(f32, rounded.distance(point(3.0, 0.0, 0.0)), 2.0));
+        assert!(rounded.distance(point(1.0, 1.0, 1.0)) > 0.0);
+
+        let torus = Torus {major_radius: 2.0, minor_radius: 0.5};
+        assert!(approx_eq!(f32, torus.distance(point(0.0, 0.0, 0.0)), 1.5));
+        assert!(approx_eq!(f32, torus.distance(point(2.0, 0.0, 0.0)), -0.5));
+        assert!(approx_eq!(f32, torus.distance(point(0.0, 1.0, -2.0)), 0.5));
+
+        let capsule = Capsule {a: point(0.0, -1.0, 0.0), b: point(0.0, 1.0, 0.0), radius: 0.5};
+        assert!(approx_eq!(f32, capsule.distance(point(2.0, 0.5, 0.0)), 1.5));
+        assert!(approx_eq!(f32, capsule.distance(point(0.0, 3.0, 0.0)), 1.5));
+    }
+
+    #[test]
+    fn mandelbulb_distance() {
+        let bulb = Mandelbulb {power: 8.0, iterations: 10};
+
+        assert!(bulb.distance(point(0.0, 0.0, 3.0)) > 1.0);
+        assert!(bulb.distance(point(0.0, 0.0, 0.1)) <= 0.0);
+        assert_eq!(bulb.distance(point(0.0, 0.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn combinators() {
+        let a = Arc::new(Translate {sdf: unit_sphere(), offset: Vector3f {x: -0.5, y: 0.0, z: 0.0}});
+        let b = Arc::new(Translate {sdf: unit_sphere(), offset: Vector3f {x: 0.5, y: 0.0, z: 0.0}});
+        let left = point(-2.0, 0.0, 0.0);
+
+        assert!(approx_eq!(f32, Union {a: a.clone(), b: b.clone()}.distance(left), 0.5));
+        assert!(approx_eq!(f32, Intersection {a: a.clone(), b: b.clone()}.distance(left), 1.5));
+        assert!(approx_eq!(f32, Subtraction {a: a.clone(), b: b.clone()}.distance(point(0.0, 0.0, 0.0)), 0.5));
+
+        // Smoothing only changes things where both shapes are near
+        let smooth = SmoothUnion {a: a.clone(), b: b.clone(), k: 0.5};
+        assert!(approx_eq!(f32, smooth.distance(left), 0.5));
+        assert!(smooth.distance(point(0.0, 1.0, 0.0)) < Union {a: a.clone(), b: b.clone()}.distance(point(0.0, 1.0, 0.0)));
+
+        let smooth = SmoothSubtraction {a: a.clone(), b: b.clone(), k: 0.5};
+        assert!(approx_eq!(f32, smooth.distance(point(-3.0, 0.0, 0.0)), 1.5));
+        assert!(smooth.distance(point(0.0, 0.0, 0.0)) >= 0.5);
+
+        let repeated = Repeat {sdf: unit_sphere(), period: Vector3f {x: 4.0, y: 0.0, z: 0.0}};
+        assert!(approx_eq!(f32, repeated.distance(point(8.0, 0.0, 0.0)), -1.0));
+        assert!(approx_eq!(f32, repeated.distance(point(10.0, 0.0, 0.0)), 1.0));
+        assert!(approx_eq!(f32, repeated.distance(point(8.0, 3.0, 0.0)), 2.0));
+
+        // A bar along x, turned a quarter turn by the time it reaches y = 1
+        let bar = Arc::new(RoundedBox {half_extents: Vector3f {x: 2.0, y: 5.0, z: 0.25}, radius: 0.0});
+        let twisted = Twist {sdf: bar, rate: 0.5 * f32::consts::PI};
+        assert!(twisted.distance(point(1.5, 0.0, 0.0)) < 0.0);
+        assert!(twisted.distance(point(1.5, 1.0, 0.0)) > 0.0);
+        assert!(twisted.distance(point(0.0, 1.0, 1.5)) < 0.0);
+    }
+
+    #[test]
+    fn no_smoothing_is_sharp() {
+        let a = Arc::new(Translate {sdf: unit_sphere(), offset: Vector3f {x: -0.5, y: 0.0, z: 0.0}});
+        let b = Arc::new(Translate {sdf: unit_sphere(), offset: Vector3f {x: 0.5, y: 0.0, z: 0.0}});
+
+        for p in &[point(0.0, 1.0, 0.0), point(0.0, 0.0, 0.0), point(-2.0, 0.0, 0.0)] {
+            let union = Union {a: a.clone(), b: b.clone()}.distance(*p);
+            assert_eq!(SmoothUnion {a: a.clone(), b: b.clone(), k: 0.0}.distance(*p), union);
+            assert_eq!(SmoothUnion {a: a.clone(), b: b.clone(), k: -1.0}.distance(*p), union);
+
+            let subtraction = Subtraction {a: a.clone(), b: b.clone()}.distance(*p);
+            assert_eq!(SmoothSubtraction {a: a.clone(), b: b.clone(), k: 0.0}.distance(*p), subtraction);
+        }
+    }
+
+    // Scenario: A ray intersects a sphere at two points
+    #[test]
+    fn traces_sphere() {
+        let shape = SDFShape::new(unit_sphere(), bounds(1.5));
+
+        let result = shape.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0, epsilon = 1e-3));
+        assert!(approx_eq!(f32, result.normal.z, -1.0, epsilon = 1e-3));
+        assert!(approx_eq!(f32, result.p.z, -1.0, epsilon = 1e-3));
+
+        let crossings = shape.collide_all(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0);
+        assert_eq!(crossings.len(), 2);
+        assert!(approx_eq!(f32, crossings[0].t, 4.0, epsilon = 1e-3));
+        assert!(approx_eq!(f32, crossings[1].t, 6.0, epsilon = 1e-3));
+        assert!(approx_eq!(f32, crossings[1].normal.z, 1.0, epsilon = 1e-3));
+
+        // Unnormalised directions give t in the ray's own units
+        let result = shape.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 2.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 2.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn misses_and_t_range() {
+        let shape = SDFShape::new(unit_sphere(), bounds(1.5));
+
+        assert!(shape.collide(&ray((0.0, 1.2, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).is_none());
+        assert!(shape.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 3.5).is_none());
+        assert!(shape.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, -1.0)), 0.0, 20.0).is_none());
+    }
+
+    // Scenario: A ray originates inside a sphere
+    #[test]
+    fn ray_from_inside() {
+        let shape = SDFShape::new(unit_sphere(), bounds(1.5));
+
+        let result = shape.collide(&ray((0.0, 0.0, 0.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 1.0, epsilon = 1e-3));
+        assert!(approx_eq!(f32, result.normal.z, 1.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn ray_leaving_the_surface() {
+        let shape = SDFShape::new(unit_sphere(), bounds(1.5));
+
+        // Scattered outwards it should not hit the point it left
+        assert!(shape.collide(&ray((0.0, 0.0, -1.0), (0.0, 0.3, -1.0)), 0.0, 20.0).is_none());
+
+        // Refracted inwards it should reach the far side
+        let result = shape.collide(&ray((0.0, 0.0, -1.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 2.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn traces_torus_hole() {
+        let torus = Arc::new(Torus {major_radius: 2.0, minor_radius: 0.5});
+        let shape = SDFShape::new(torus, bounds(3.0));
+
+        // Straight down through the hole in the middle
+        assert!(shape.collide(&ray((0.0, 5.0, 0.0), (0.0, -1.0, 0.0)), 0.0, 20.0).is_none());
+
+        let result = shape.collide(&ray((2.0, 5.0, 0.0), (0.0, -1.0, 0.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.5, epsilon = 1e-3));
+        assert!(approx_eq!(f32, result.normal.y, 1.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn refines_overshoot() {
+        // Stepping twice as far as is safe still finds the surface
+        let shape = SDFShape {step_scale: 2.0, ..SDFShape::new(unit_sphere(), bounds(1.5))};
+
+        let result = shape.collide(&ray((0.0, 0.0, -5.0), (0.0, 0.0, 1.0)), 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn bounding_box_is_given_bounds() {
+        let aabb = SDFShape::new(unit_sphere(), bounds(1.5)).bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.maximum.x), (-1.5, 1.5));
+    }
+}
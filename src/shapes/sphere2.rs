@@ -11,13 +11,23 @@ use crate::core::{
 };
 
 use crate::ray::Ray;
-use crate::shapes::base::{Interaction, ShapeTrait};
+use crate::shapes::base::{get_phi, solve_quadratic, Interaction, ShapeSample, ShapeTrait};
 
 #[derive(Serialize, Deserialize, Copy, Clone)]
 pub struct Sphere {
     pub radius: f32,
 }
 
+// A sphere clipped to the slab between z_min and z_max, and swept from phi = 0
+// up to phi_max radians around the z axis. The clipped edges are left open.
+#[derive(Serialize, Deserialize, Copy, Clone)]
+pub struct PartialSphere {
+    pub radius: f32,
+    pub z_min: f32,
+    pub z_max: f32,
+    pub phi_max: f32,
+}
+
 impl ShapeTrait for Sphere {
     fn collide(
         &self,
@@ -91,6 +101,26 @@ impl ShapeTrait for Sphere {
             },
         })
     }
+
+    fn area(&self) -> f32 {
+        4.0 * f32::consts::PI * self.radius * self.radius
+    }
+
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * f32::consts::PI * v;
+        let normal = Normal3f { x: r * phi.cos(), y: r * phi.sin(), z };
+
+        Some(ShapeSample {
+            p: Point3f {
+                x: self.radius * normal.x,
+                y: self.radius * normal.y,
+                z: self.radius * normal.z,
+            },
+            normal,
+        })
+    }
 }
 
 impl Sphere {
@@ -105,9 +135,90 @@ impl Sphere {
     }
 }
 
+impl ShapeTrait for PartialSphere {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let ray_o = ray.origin();
+        let ray_d = ray.direction();
+
+        let a: f32 = dot_vv(&ray_d, &ray_d);
+        let b: f32 = 2.0 * (ray_o.x * ray_d.x + ray_o.y * ray_d.y + ray_o.z * ray_d.z);
+        let c: f32 = ray_o.x * ray_o.x + ray_o.y * ray_o.y + ray_o.z * ray_o.z - self.radius * self.radius;
+
+        let (result, t0, t1) = solve_quadratic(a, b, c);
+        if !result {
+            return None;
+        }
+
+        // The nearer hit may have been clipped away, showing the inside of the
+        // far side through the gap
+        for t in &[t0, t1] {
+            if *t <= t_min || *t >= t_max {
+                continue;
+            }
+
+            let p = ray.point_at_parameter(*t);
+            if p.z < self.z_min || p.z > self.z_max {
+                continue;
+            }
+
+            let phi = get_phi(p);
+            if phi > self.phi_max {
+                continue;
+            }
+
+            // v runs from the bottom of the clipped sphere to the top
+            let theta = self.get_theta(p.z);
+            let theta_min = self.get_theta(self.z_min);
+            let theta_max = self.get_theta(self.z_max);
+
+            return Some(Interaction {
+                t: *t,
+                p,
+                normal: Normal3f::from(&p - Point3f {x: 0.0, y: 0.0, z: 0.0}),
+                u: phi / self.phi_max,
+                v: (theta - theta_min) / (theta_max - theta_min),
+            });
+        }
+
+        None
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        Some(AABB {
+            minimum: Point3f {
+                x: -self.radius,
+                y: -self.radius,
+                z: self.z_min.max(-self.radius),
+            },
+            maximum: Point3f {
+                x: self.radius,
+                y: self.radius,
+                z: self.z_max.min(self.radius),
+            },
+        })
+    }
+}
+
+impl PartialSphere {
+    fn get_theta(&self, z: f32) -> f32 {
+        (z / self.radius).clamp(-1.0, 1.0).acos()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::f32;
+    use float_cmp::approx_eq;
+
     use crate::core::{
         Point3f,
         Vector3f,
@@ -123,7 +234,7 @@ mod tests {
     use crate::ray::Ray;
 
     use crate::shapes::base::ShapeTrait;
-    use crate::shapes::sphere2::Sphere;
+    use crate::shapes::sphere2::{PartialSphere, Sphere};
 
     // Scenario: A ray intersects a sphere at two points
     // Given r ← ray(point(0, 0, -5), vector(0, 0, 1))
@@ -137,7 +248,7 @@ mod tests {
         let sphere = Sphere {radius: 1.0};
 
         let result_option = sphere.collide(
-            Ray {
+            &Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  0.0,
@@ -150,8 +261,6 @@ mod tests {
                 },
                 time: 0.0,
             },
-            indentity(),
-            indentity(),
             0.0,
             20.0,
         );
@@ -171,7 +280,7 @@ mod tests {
         let sphere = Sphere {radius: 1.0};
 
         let result_option = sphere.collide(
-            Ray {
+            &Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  1.0,
@@ -184,8 +293,6 @@ mod tests {
                 },
                 time: 0.0,
             },
-            indentity(),
-            indentity(),
             4.999,
             5.001,
         );
@@ -203,7 +310,7 @@ mod tests {
         let sphere = Sphere {radius: 1.0};
 
         let result_option = sphere.collide(
-            Ray {
+            &Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  2.0,
@@ -216,8 +323,6 @@ mod tests {
                 },
                 time: 0.0,
             },
-            indentity(),
-            indentity(),
             0.0,
             20.0,
         );
@@ -237,7 +342,7 @@ mod tests {
         let sphere = Sphere {radius: 1.0};
 
         let result_option = sphere.collide(
-            Ray {
+            &Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  0.0,
@@ -250,8 +355,6 @@ mod tests {
                 },
                 time: 0.0,
             },
-            indentity(),
-            indentity(),
             0.0,
             20.0,
         );
@@ -307,9 +410,7 @@ mod tests {
         // let updated_b = transform.m_inv * ray.b;
 
         let result_option = sphere.collide(
-            ray,
-            transform.m,
-            transform.m_inv,
+            &(transform.m_inv * ray),
             original_t_min,
             original_t_max,
         );
@@ -333,7 +434,7 @@ mod tests {
         });
 
         let result_option = sphere.collide(
-            Ray {
+            &(transform.m_inv * Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  0.0,
@@ -345,9 +446,7 @@ mod tests {
                     z: 1.0,
                 },
                 time: 0.0,
-            },
-            transform.m,
-            transform.m_inv,
+            }),
             0.0,
             20.0,
         );
@@ -371,7 +470,7 @@ mod tests {
         });
 
         let result_option = sphere.collide(
-            Ray {
+            &(transform.m_inv * Ray {
                 a: Point3f {
                     x:  -5.0,
                     y:   0.0,
@@ -383,9 +482,7 @@ mod tests {
                     z: 0.0,
                 },
                 time: 0.0,
-            },
-            transform.m,
-            transform.m_inv,
+            }),
             0.0,
             20.0,
         );
@@ -403,7 +500,7 @@ mod tests {
         let transform = gen_rotate_x(0.0) * gen_rotate_y(0.0) * gen_rotate_z(0.0);
 
         let result_option = sphere.collide(
-            Ray {
+            &(transform.m_inv * Ray {
                 a: Point3f {
                     x:  0.0,
                     y:  0.0,
@@ -415,9 +512,7 @@ mod tests {
                     z: 1.0,
                 },
                 time: 0.0,
-            },
-            transform.m,
-            transform.m_inv,
+            }),
             0.0,
             20.0,
         );
@@ -441,7 +536,7 @@ mod tests {
         let transform = rotation * translation;
 
         let result_option = sphere.collide(
-            Ray {
+            &(transform.m_inv * Ray {
                 a: Point3f {
                     x: 0.0,
                     y: 0.0,
@@ -453,9 +548,7 @@ mod tests {
                     z: 0.0,
                 },
                 time: 0.0,
-            },
-            transform.m,
-            transform.m_inv,
+            }),
             0.0,
             50.0,
         );
@@ -492,7 +585,7 @@ mod tests {
         println!("We are aiming at: x:{0}, y:{1}, z:{2}", x_pos, y_pos, z_pos);
 
         let result_option = sphere.collide(
-            Ray {
+            &(transform.m_inv * Ray {
                 a: Point3f {
                     x: 0.0,
                     y: 0.0,
@@ -500,9 +593,7 @@ mod tests {
                 },
                 b: look_at,
                 time: 0.0,
-            },
-            transform.m,
-            transform.m_inv,
+            }),
             t_val * 0.99,
             t_val * 1.01,
         );
@@ -527,4 +618,114 @@ mod tests {
     //     And set_transform(s, m)
     //   When n ← normal_at(s, point(0, √2/2, -√2/2))
     //   Then n = vector(0, 0.97014, -0.24254)
+
+    fn partial_sphere() -> PartialSphere {
+        PartialSphere {
+            radius: 1.0,
+            z_min: -0.5,
+            z_max: 0.5,
+            phi_max: 2.0 * f32::consts::PI,
+        }
+    }
+
+    #[test]
+    fn full_partial_sphere_matches_sphere() {
+        let partial = PartialSphere {z_min: -1.0, z_max: 1.0, ..partial_sphere()};
+        let ray = Ray {
+            a: Point3f {x: 0.3, y: -5.0, z: 0.2},
+            b: Vector3f {x: 0.0, y: 1.0, z: 0.0},
+            time: 0.0,
+        };
+
+        let expected = Sphere {radius: 1.0}.collide(&ray, 0.0, 20.0).unwrap();
+        let result = partial.collide(&ray, 0.0, 20.0).unwrap();
+        assert!(approx_eq!(f32, result.t, expected.t, epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.normal.y, expected.normal.y, epsilon = 1e-5));
+    }
+
+    #[test]
+    fn clipped_to_z_range() {
+        let sphere = partial_sphere();
+
+        // Straight down through the open top and bottom
+        assert!(sphere.collide(
+            &Ray {a: Point3f {x: 0.0, y: 0.0, z: 5.0}, b: Vector3f {x: 0.0, y: 0.0, z: -1.0}, time: 0.0},
+            0.0,
+            20.0,
+        ).is_none());
+
+        // Through the band around the middle, where v is halfway
+        let result = sphere.collide(
+            &Ray {a: Point3f {x: -5.0, y: 0.0, z: 0.0}, b: Vector3f {x: 1.0, y: 0.0, z: 0.0}, time: 0.0},
+            0.0,
+            20.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, result.t, 4.0));
+        assert!(approx_eq!(f32, result.u, 0.5));
+        assert!(approx_eq!(f32, result.v, 0.5, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn clipped_front_shows_inside_of_back() {
+        let sphere = PartialSphere {z_min: -0.5, z_max: 1.0, ..partial_sphere()};
+
+        // Enters below z_min where the sphere is missing, leaves through the
+        // far side above it
+        let result = sphere.collide(
+            &Ray {a: Point3f {x: 0.0, y: -2.0, z: -2.0}, b: Vector3f {x: 0.0, y: 1.0, z: 1.0}, time: 0.0},
+            0.0,
+            20.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, result.t, 2.0 + 0.5f32.sqrt(), epsilon = 1e-5));
+        assert!(approx_eq!(f32, result.p.z, 0.5f32.sqrt(), epsilon = 1e-5));
+    }
+
+    #[test]
+    fn partial_sphere_sweep() {
+        let sphere = PartialSphere {phi_max: f32::consts::PI, ..partial_sphere()};
+
+        // Only y >= 0 is left, so a ray down the y axis hits the inside of
+        // the far wall
+        let result = sphere.collide(
+            &Ray {a: Point3f {x: 0.0, y: -5.0, z: 0.0}, b: Vector3f {x: 0.0, y: 1.0, z: 0.0}, time: 0.0},
+            0.0,
+            20.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, result.t, 6.0));
+        assert!(approx_eq!(f32, result.u, 0.5));
+
+        assert!(sphere.collide(
+            &Ray {a: Point3f {x: 5.0, y: -0.5, z: 0.0}, b: Vector3f {x: -1.0, y: 0.0, z: 0.0}, time: 0.0},
+            0.0,
+            20.0,
+        ).is_none());
+    }
+
+    #[test]
+    fn partial_sphere_bounding_box() {
+        let aabb = PartialSphere {z_min: -3.0, ..partial_sphere()}.bounding_box(0.0, 1.0).unwrap();
+
+        assert_eq!((aabb.minimum.x, aabb.minimum.y, aabb.minimum.z), (-1.0, -1.0, -1.0));
+        assert_eq!((aabb.maximum.x, aabb.maximum.y, aabb.maximum.z), (1.0, 1.0, 0.5));
+    }
+
+    #[test]
+    fn samples_lie_on_the_sphere() {
+        let sphere = Sphere {radius: 2.0};
+        assert!(approx_eq!(f32, sphere.area(), 16.0 * f32::consts::PI));
+
+        for i in 0..=10 {
+            for j in 0..10 {
+                let sample = sphere.sample(i as f32 / 10.0, j as f32 / 10.0).unwrap();
+                let distance = Vector3f::from(sample.p - Point3f {x: 0.0, y: 0.0, z: 0.0}).length();
+                assert!(approx_eq!(f32, distance, 2.0, epsilon = 1e-5));
+                assert!(approx_eq!(f32, sample.normal.length(), 1.0, epsilon = 1e-5));
+                assert!(approx_eq!(f32, sample.p.z, 2.0 * sample.normal.z, epsilon = 1e-5));
+            }
+        }
+
+        // u runs from pole to pole
+        assert!(approx_eq!(f32, sphere.sample(0.0, 0.3).unwrap().p.z, 2.0));
+        assert!(approx_eq!(f32, sphere.sample(1.0, 0.3).unwrap().p.z, -2.0));
+    }
 }
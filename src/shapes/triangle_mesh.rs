@@ -0,0 +1,576 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::bvh_tree::BVHTree;
+use crate::core::{
+    Normal3f,
+    Point2f,
+    Point3f,
+    cross,
+    dot_vv,
+};
+
+use crate::ray::Ray;
+use crate::shapes::base::{BOUNDS_PADDING, Interaction, ShapeSample, ShapeTrait};
+
+// One corner of a triangle, indexing into the mesh's buffers. Positions are
+// always present, normals and uvs are optional per corner as they are in OBJ
+// files.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MeshVertex {
+    pub position: usize,
+    pub normal: Option<usize>,
+    pub uv: Option<usize>,
+}
+
+// Buffers shared by every triangle of a mesh. Every index in triangles must
+// be within the buffers.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub positions: Vec<Point3f>,
+    pub normals: Vec<Normal3f>,
+    pub uvs: Vec<Point2f>,
+    pub triangles: Vec<[MeshVertex; 3]>,
+}
+
+// A single triangle of a mesh, only used as the item in the mesh's own BVH
+#[derive(Clone)]
+pub struct Triangle {
+    mesh: Arc<MeshData>,
+    index: usize,
+}
+
+// Triangles in object space with their own BVH, so a large model is a single
+// primative in the scene's tree
+pub struct TriangleMesh {
+    bvh_tree: BVHTree<Triangle>,
+    // The triangles again, less any with no area, alongside the total area of
+    // each and all those before it, for picking one in proportion to its area
+    // as a light
+    triangles: Vec<Triangle>,
+    cumulative_areas: Vec<f32>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData) -> TriangleMesh {
+        let data = Arc::new(data);
+        let triangles: Vec<Triangle> = (0..data.triangles.len())
+            .map(|index| Triangle { mesh: data.clone(), index })
+            .collect();
+        // Degenerate faces would be picked with a share of nothing, and their
+        // samples have no normal
+        let lights: Vec<Triangle> = triangles
+            .iter()
+            .filter(|triangle| triangle.area() > 0.0)
+            .cloned()
+            .collect();
+        let cumulative_areas = lights
+            .iter()
+            .scan(0.0, |total, triangle| {
+                *total += triangle.area();
+                Some(*total)
+            })
+            .collect();
+
+        TriangleMesh {
+            bvh_tree: BVHTree::new(triangles, 0.0, 0.0),
+            triangles: lights,
+            cumulative_areas,
+        }
+    }
+}
+
+impl ShapeTrait for TriangleMesh {
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        self.bvh_tree
+            .get_collision(ray, t_min, t_max)
+            .map(|collision| collision.interaction)
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        self.bvh_tree.bounds()
+    }
+
+    fn area(&self) -> f32 {
+        self.cumulative_areas.last().cloned().unwrap_or(0.0)
+    }
+
+    // Picks a triangle with u, then reuses what is left of u within that
+    // triangle's share so the point is still spread evenly over it
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        let area = self.area();
+        if area <= 0.0 {
+            return None;
+        }
+
+        let target = u * area;
+        let index = self.cumulative_areas
+            .partition_point(|&total| total <= target)
+            .min(self.triangles.len() - 1);
+        let start = if index > 0 { self.cumulative_areas[index - 1] } else { 0.0 };
+        let share = self.cumulative_areas[index] - start;
+        let u = ((target - start) / share).clamp(0.0, 1.0);
+
+        self.triangles[index].sample(u, v)
+    }
+
+    // Found through the mesh's BVH like collide, but asking the triangle hit
+    // for its geometric normal in place of the interpolated one
+    fn collide_sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, ShapeSample)> {
+        self.bvh_tree.traverse(ray, t_min, t_max, |_, triangle, t_max| {
+            triangle
+                .collide_sample(ray, t_min, t_max)
+                .map(|(t, sample)| (t, (t, sample)))
+        })
+    }
+}
+
+impl Triangle {
+    fn vertices(&self) -> &[MeshVertex; 3] {
+        &self.mesh.triangles[self.index]
+    }
+
+    fn position(&self, corner: usize) -> Point3f {
+        self.mesh.positions[self.vertices()[corner].position]
+    }
+
+    // Without uvs the corners are given pbrt's default parameterisation
+    fn uv(&self, corner: usize) -> Point2f {
+        match self.vertices()[corner].uv {
+            Some(uv) => self.mesh.uvs[uv],
+            None => [
+                Point2f { x: 0.0, y: 0.0 },
+                Point2f { x: 1.0, y: 0.0 },
+                Point2f { x: 1.0, y: 1.0 },
+            ][corner],
+        }
+    }
+
+    // Interpolated from the vertex normals when all three corners have one
+    fn shading_normal(&self, b0: f32, b1: f32, b2: f32) -> Option<Normal3f> {
+        let vertices = self.vertices();
+        let n0 = self.mesh.normals[vertices[0].normal?];
+        let n1 = self.mesh.normals[vertices[1].normal?];
+        let n2 = self.mesh.normals[vertices[2].normal?];
+
+        let normal = n0 * b0 + n1 * b1 + n2 * b2;
+        if normal.squared_length() > 0.0 {
+            Some(normal)
+        } else {
+            None
+        }
+    }
+
+    // Facing the way the corners wind anticlockwise, not normalised
+    fn geometric_normal(&self) -> Normal3f {
+        let p0 = self.position(0);
+        Normal3f::from(cross(&(self.position(1) - p0), &(self.position(2) - p0)))
+    }
+}
+
+impl ShapeTrait for Triangle {
+    // Möller–Trumbore, solving for the barycentric coordinates of the hit
+    // directly rather than intersecting the triangle's plane first
+    fn collide(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<Interaction> {
+        let p0 = self.position(0);
+        let edge_1 = self.position(1) - p0;
+        let edge_2 = self.position(2) - p0;
+
+        let p_vec = cross(&ray.b, &edge_2);
+        let determinant = dot_vv(&edge_1, &p_vec);
+        if determinant == 0.0 {
+            return None;
+        }
+        let inv_determinant = 1.0 / determinant;
+
+        let t_vec = ray.a - p0;
+        let b1 = dot_vv(&t_vec, &p_vec) * inv_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let q_vec = cross(&t_vec, &edge_1);
+        let b2 = dot_vv(&ray.b, &q_vec) * inv_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = dot_vv(&edge_2, &q_vec) * inv_determinant;
+        if t <= t_min || t >= t_max {
+            return None;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let (uv0, uv1, uv2) = (self.uv(0), self.uv(1), self.uv(2));
+
+        Some(Interaction {
+            t,
+            p: p0 + b1 * edge_1 + b2 * edge_2,
+            normal: self
+                .shading_normal(b0, b1, b2)
+                .unwrap_or_else(|| self.geometric_normal()),
+            u: b0 * uv0.x + b1 * uv1.x + b2 * uv2.x,
+            v: b0 * uv0.y + b1 * uv1.y + b2 * uv2.y,
+        })
+    }
+
+    fn bounding_box(
+        &self,
+        _time_0: f32,
+        _time_1: f32,
+    ) -> Option<AABB> {
+        let p0 = self.position(0);
+        let bounds = AABB::join_point(
+            &AABB::join_point(&AABB { minimum: p0, maximum: p0 }, &self.position(1)),
+            &self.position(2),
+        );
+
+        Some(AABB {
+            minimum: Point3f {
+                x: bounds.minimum.x - BOUNDS_PADDING,
+                y: bounds.minimum.y - BOUNDS_PADDING,
+                z: bounds.minimum.z - BOUNDS_PADDING,
+            },
+            maximum: Point3f {
+                x: bounds.maximum.x + BOUNDS_PADDING,
+                y: bounds.maximum.y + BOUNDS_PADDING,
+                z: bounds.maximum.z + BOUNDS_PADDING,
+            },
+        })
+    }
+
+    fn area(&self) -> f32 {
+        let p0 = self.position(0);
+        0.5 * cross(&(self.position(1) - p0), &(self.position(2) - p0)).length()
+    }
+
+    // Folds the unit square onto the triangle by taking the square root of
+    // u, which would otherwise bunch the points up towards the first corner
+    fn sample(&self, u: f32, v: f32) -> Option<ShapeSample> {
+        let p0 = self.position(0);
+        let edge_1 = self.position(1) - p0;
+        let edge_2 = self.position(2) - p0;
+
+        let root = u.sqrt();
+        let b1 = root * (1.0 - v);
+        let b2 = root * v;
+
+        Some(ShapeSample {
+            p: p0 + b1 * edge_1 + b2 * edge_2,
+            normal: self.geometric_normal().unit_vector(),
+        })
+    }
+
+    fn collide_sample(
+        &self,
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Option<(f32, ShapeSample)> {
+        self.collide(ray, t_min, t_max).map(|interaction| {
+            (interaction.t, ShapeSample { p: interaction.p, normal: self.geometric_normal().unit_vector() })
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use float_cmp::approx_eq;
+
+    use crate::core::{
+        Normal3f,
+        Point2f,
+        Point3f,
+        Vector3f,
+    };
+
+    use crate::obj::load_obj;
+    use crate::ray::Ray;
+
+    use crate::shapes::base::ShapeTrait;
+    use crate::shapes::triangle_mesh::{MeshData, MeshVertex, TriangleMesh};
+
+    fn vertex(position: usize, normal: Option<usize>, uv: Option<usize>) -> MeshVertex {
+        MeshVertex { position, normal, uv }
+    }
+
+    // The triangle (0, 1, 0), (-1, 0, 0), (1, 0, 0) in the z = 0 plane
+    fn single_triangle() -> MeshData {
+        MeshData {
+            positions: vec![
+                Point3f { x:  0.0, y: 1.0, z: 0.0 },
+                Point3f { x: -1.0, y: 0.0, z: 0.0 },
+                Point3f { x:  1.0, y: 0.0, z: 0.0 },
+            ],
+            normals: vec![],
+            uvs: vec![],
+            triangles: vec![[vertex(0, None, None), vertex(1, None, None), vertex(2, None, None)]],
+        }
+    }
+
+    // A unit square in the z = 0 plane split into two triangles
+    fn square(z: f32) -> MeshData {
+        MeshData {
+            positions: vec![
+                Point3f { x: 0.0, y: 0.0, z },
+                Point3f { x: 1.0, y: 0.0, z },
+                Point3f { x: 1.0, y: 1.0, z },
+                Point3f { x: 0.0, y: 1.0, z },
+            ],
+            normals: vec![
+                Normal3f { x: 0.0, y: 0.0, z: 1.0 },
+                Normal3f { x: 1.0, y: 0.0, z: 0.0 },
+            ],
+            uvs: vec![
+                Point2f { x: 0.0, y: 0.0 },
+                Point2f { x: 1.0, y: 0.0 },
+                Point2f { x: 1.0, y: 1.0 },
+                Point2f { x: 0.0, y: 1.0 },
+            ],
+            triangles: vec![
+                [vertex(0, Some(0), Some(0)), vertex(1, Some(1), Some(1)), vertex(2, Some(1), Some(2))],
+                [vertex(0, Some(0), Some(0)), vertex(2, Some(1), Some(2)), vertex(3, Some(0), Some(3))],
+            ],
+        }
+    }
+
+    fn ray(origin: Point3f, direction: Vector3f) -> Ray {
+        Ray { a: origin, b: direction, time: 0.0 }
+    }
+
+    // Scenario: A ray strikes a triangle
+    //   Given t ← triangle(point(0, 1, 0), point(-1, 0, 0), point(1, 0, 0))
+    //     And r ← ray(point(0, 0.5, -2), vector(0, 0, 1))
+    //   When xs ← local_intersect(t, r)
+    //   Then xs.count = 1
+    //     And xs[0].t = 2
+    #[test]
+    fn ray_strikes_triangle() {
+        let mesh = TriangleMesh::new(single_triangle());
+
+        let interaction = mesh.collide(
+            &ray(Point3f { x: 0.0, y: 0.5, z: -2.0 }, Vector3f { x: 0.0, y: 0.0, z: 1.0 }),
+            0.0,
+            10.0,
+        ).unwrap();
+
+        assert!(approx_eq!(f32, interaction.t, 2.0));
+        assert!(approx_eq!(f32, interaction.p.y, 0.5));
+        assert!(approx_eq!(f32, interaction.p.z, 0.0));
+
+        // Without vertex normals the geometric normal is used
+        assert!(approx_eq!(f32, interaction.normal.x, 0.0));
+        assert!(approx_eq!(f32, interaction.normal.y, 0.0));
+        assert!(interaction.normal.z != 0.0);
+    }
+
+    // Scenario: A ray misses the p1-p3, p1-p2 and p2-p3 edges
+    #[test]
+    fn ray_misses_edges() {
+        let mesh = TriangleMesh::new(single_triangle());
+        let direction = Vector3f { x: 0.0, y: 0.0, z: 1.0 };
+
+        for origin in &[
+            Point3f { x:  1.0, y:  1.0, z: -2.0 },
+            Point3f { x: -1.0, y:  1.0, z: -2.0 },
+            Point3f { x:  0.0, y: -1.0, z: -2.0 },
+        ] {
+            assert!(mesh.collide(&ray(*origin, direction), 0.0, 10.0).is_none());
+        }
+    }
+
+    // Scenario: Intersecting a ray parallel to the triangle
+    #[test]
+    fn parallel_ray_misses() {
+        let mesh = TriangleMesh::new(single_triangle());
+
+        let result = mesh.collide(
+            &ray(Point3f { x: 0.0, y: -1.0, z: -2.0 }, Vector3f { x: 0.0, y: 1.0, z: 0.0 }),
+            0.0,
+            10.0,
+        );
+
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn respects_t_range() {
+        let mesh = TriangleMesh::new(single_triangle());
+        let r = ray(Point3f { x: 0.0, y: 0.5, z: -2.0 }, Vector3f { x: 0.0, y: 0.0, z: 1.0 });
+
+        assert!(mesh.collide(&r, 0.0, 1.5).is_none());
+        assert!(mesh.collide(&r, 2.5, 10.0).is_none());
+    }
+
+    #[test]
+    fn interpolates_normals_and_uvs() {
+        let mesh = TriangleMesh::new(square(0.0));
+
+        // On the shared edge, halfway between a +z corner and a +x corner
+        let interaction = mesh.collide(
+            &ray(Point3f { x: 0.5, y: 0.5, z: 1.0 }, Vector3f { x: 0.0, y: 0.0, z: -1.0 }),
+            0.0,
+            10.0,
+        ).unwrap();
+
+        assert!(approx_eq!(f32, interaction.u, 0.5, epsilon = 1e-6));
+        assert!(approx_eq!(f32, interaction.v, 0.5, epsilon = 1e-6));
+        assert!(approx_eq!(f32, interaction.normal.x, 0.5, epsilon = 1e-6));
+        assert!(approx_eq!(f32, interaction.normal.z, 0.5, epsilon = 1e-6));
+
+        let interaction = mesh.collide(
+            &ray(Point3f { x: 0.25, y: 0.75, z: 1.0 }, Vector3f { x: 0.0, y: 0.0, z: -1.0 }),
+            0.0,
+            10.0,
+        ).unwrap();
+
+        assert!(approx_eq!(f32, interaction.u, 0.25, epsilon = 1e-6));
+        assert!(approx_eq!(f32, interaction.v, 0.75, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn flat_mesh_has_padded_bounds() {
+        let mesh = TriangleMesh::new(square(2.0));
+        let bounds = mesh.bounding_box(0.0, 0.0).unwrap();
+
+        assert!(bounds.minimum.z < 2.0);
+        assert!(bounds.maximum.z > 2.0);
+        assert!(approx_eq!(f32, bounds.maximum.x, 1.0, epsilon = 1e-3));
+    }
+
+    #[test]
+    fn closest_of_many_triangles() {
+        // 100 squares stacked one above the other, sharing the uvs
+        let mut data = square(0.0);
+        for layer in 1..100 {
+            let offset = data.positions.len();
+            let layer = square(layer as f32);
+
+            data.positions.extend(layer.positions);
+            data.triangles.extend(layer.triangles.iter().map(|triangle| {
+                let mut triangle = *triangle;
+                for vertex in triangle.iter_mut() {
+                    vertex.position += offset;
+                }
+                triangle
+            }));
+        }
+
+        assert_eq!(data.triangles.len(), 200);
+        let mesh = TriangleMesh::new(data);
+
+        let interaction = mesh.collide(
+            &ray(Point3f { x: 0.3, y: 0.6, z: 50.5 }, Vector3f { x: 0.0, y: 0.0, z: -1.0 }),
+            0.0,
+            100.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, interaction.p.z, 50.0));
+
+        let interaction = mesh.collide(
+            &ray(Point3f { x: 0.3, y: 0.6, z: 50.5 }, Vector3f { x: 0.0, y: 0.0, z: 1.0 }),
+            0.0,
+            100.0,
+        ).unwrap();
+        assert!(approx_eq!(f32, interaction.p.z, 51.0));
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let mesh = TriangleMesh::new(MeshData::default());
+
+        assert!(mesh.bounding_box(0.0, 0.0).is_none());
+        assert!(mesh.collide(
+            &ray(Point3f { x: 0.0, y: 0.0, z: 1.0 }, Vector3f { x: 0.0, y: 0.0, z: -1.0 }),
+            0.0,
+            10.0,
+        ).is_none());
+    }
+
+    #[test]
+    fn samples_by_area() {
+        let mut data = square(0.0);
+        // A third triangle four times the size of the others, off to one side
+        data.positions.extend(vec![
+            Point3f { x: 2.0, y: 0.0, z: 0.0 },
+            Point3f { x: 4.0, y: 0.0, z: 0.0 },
+            Point3f { x: 2.0, y: 2.0, z: 0.0 },
+        ]);
+        data.triangles.push([vertex(4, None, None), vertex(5, None, None), vertex(6, None, None)]);
+        let mesh = TriangleMesh::new(data);
+        assert!(approx_eq!(f32, mesh.area(), 3.0));
+
+        let mut in_large = 0;
+        for i in 0..30 {
+            for j in 0..30 {
+                let sample = mesh.sample((i as f32 + 0.5) / 30.0, (j as f32 + 0.5) / 30.0).unwrap();
+                assert_eq!(sample.p.z, 0.0);
+                assert_eq!((sample.normal.x, sample.normal.y, sample.normal.z), (0.0, 0.0, 1.0));
+
+                if sample.p.x >= 2.0 {
+                    assert!(sample.p.x + sample.p.y <= 4.0 + 1e-5);
+                    in_large += 1;
+                } else {
+                    assert!(sample.p.x <= 1.0 && sample.p.y >= 0.0 && sample.p.y <= 1.0);
+                }
+            }
+        }
+        assert_eq!(in_large, 600);
+
+        assert!(TriangleMesh::new(MeshData::default()).sample(0.5, 0.5).is_none());
+    }
+
+    #[test]
+    fn samples_skip_degenerate_triangles() {
+        let mut groups = load_obj("examples/meshes/degenerate.obj").unwrap();
+        let mesh = TriangleMesh::new(groups.remove(0).mesh);
+        assert!(approx_eq!(f32, mesh.area(), 1.0));
+
+        // Right at either end of u as well, where the flat faces sit
+        for u in &[0.0, 0.25, 0.5, 0.75, 1.0] {
+            for v in &[0.0, 0.5, 1.0] {
+                let sample = mesh.sample(*u, *v).unwrap();
+                assert!(sample.p.x >= 0.0 && sample.p.x <= 1.0);
+                assert!(sample.p.y >= 0.0 && sample.p.y <= 1.0);
+                assert_eq!((sample.normal.x, sample.normal.y, sample.normal.z), (0.0, 0.0, 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn samples_ignore_shading_normals() {
+        let mesh = TriangleMesh::new(square(0.0));
+        let r = ray(Point3f { x: 0.5, y: 0.5, z: 1.0 }, Vector3f { x: 0.0, y: 0.0, z: -1.0 });
+
+        // Halfway between the +z and +x vertex normals when shading
+        let interaction = mesh.collide(&r, 0.0, 10.0).unwrap();
+        assert!(approx_eq!(f32, interaction.normal.x, 0.5, epsilon = 1e-6));
+
+        let (t, sample) = mesh.collide_sample(&r, 0.0, 10.0).unwrap();
+        assert!(approx_eq!(f32, t, 1.0));
+        assert!(approx_eq!(f32, sample.p.x, 0.5, epsilon = 1e-6));
+        assert_eq!((sample.normal.x, sample.normal.y, sample.normal.z), (0.0, 0.0, 1.0));
+        assert_eq!(sample.normal, mesh.sample(0.2, 0.7).unwrap().normal);
+
+        assert!(mesh.collide_sample(&r, 0.0, 0.5).is_none());
+    }
+}
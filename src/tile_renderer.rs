@@ -0,0 +1,247 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread;
+
+use rand::thread_rng;
+use rand::Rng;
+
+use crate::core::Colour;
+use crate::render::RenderResult;
+use crate::scene2::{Integrator, Scene};
+
+
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub tile_size: u32,
+    pub threads: usize,
+    // Keep every individual sample alongside the accumulated pixels, for
+    // streaming to rust_ray_assemble
+    pub keep_samples: bool,
+    pub integrator: Integrator,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    pub x0: u32,
+    pub y0: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct TileResult {
+    pub tile: Tile,
+    // Averaged colour of each pixel in the tile, row by row starting at y0
+    pub pixels: Vec<Colour>,
+    pub samples: Vec<RenderResult>,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> u32 {
+        self.width * self.height
+    }
+}
+
+pub fn sample_coordinate(pixel: u32, offset: f32) -> f32 {
+    // pixel + offset can round up to pixel + 1 for offsets close to 1.0,
+    // which would put the sample into the neighbouring pixel
+    let coord = pixel as f32 + offset;
+    if coord as u32 > pixel {
+        f32::from_bits((pixel as f32 + 1.0).to_bits() - 1)
+    } else {
+        coord
+    }
+}
+
+// Splits the image into tiles, starting from the top row so the image fills
+// in the same order as it is read
+pub fn generate_tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let mut tiles = vec![];
+    let tile_size = tile_size.max(1);
+
+    let mut y_top = height;
+    while y_top > 0 {
+        let tile_height = tile_size.min(y_top);
+        let y0 = y_top - tile_height;
+
+        let mut x0 = 0;
+        while x0 < width {
+            let tile_width = tile_size.min(width - x0);
+            tiles.push(Tile { x0, y0, width: tile_width, height: tile_height });
+            x0 += tile_width;
+        }
+
+        y_top = y0;
+    }
+
+    tiles
+}
+
+fn render_tile(scene: &Scene, settings: &RenderSettings, tile: Tile) -> TileResult {
+    let mut rng = thread_rng();
+    let mut pixels = Vec::with_capacity(tile.pixel_count() as usize);
+    let mut samples = vec![];
+
+    for y_coord in tile.y0..tile.y0 + tile.height {
+        for x_coord in tile.x0..tile.x0 + tile.width {
+            let mut total = Colour { r: 0.0, g: 0.0, b: 0.0 };
+
+            for _ in 0..settings.samples {
+                let x_coord_precise = sample_coordinate(x_coord, rng.gen::<f64>() as f32);
+                let y_coord_precise = sample_coordinate(y_coord, rng.gen::<f64>() as f32);
+
+                let u: f32 = x_coord_precise / settings.width as f32;
+                let v: f32 = y_coord_precise / settings.height as f32;
+
+                let ray = scene.camera.get_ray(u, v);
+                let colour = settings.integrator.colour(scene, &ray);
+                total = total + colour;
+
+                if settings.keep_samples {
+                    samples.push(RenderResult {
+                        x_coord: x_coord_precise,
+                        y_coord: y_coord_precise,
+                        time: ray.time,
+                        colour,
+                    });
+                }
+            }
+
+            pixels.push(total / settings.samples.max(1) as f32);
+        }
+    }
+
+    TileResult { tile, pixels, samples }
+}
+
+// Renders the scene on a fixed pool of worker threads. Workers take the next
+// tile from a shared counter and send finished tiles back, and on_tile is
+// called on the current thread for each one as it arrives.
+pub fn render<F>(scene: Arc<Scene>, settings: RenderSettings, mut on_tile: F)
+where
+    F: FnMut(TileResult),
+{
+    let tiles = Arc::new(generate_tiles(settings.width, settings.height, settings.tile_size));
+    let next_tile = Arc::new(AtomicUsize::new(0));
+    let settings = Arc::new(settings);
+
+    let (tx, rx) = mpsc::channel();
+    let mut handles = vec![];
+
+    for _ in 0..settings.threads.max(1) {
+        let scene = Arc::clone(&scene);
+        let settings = Arc::clone(&settings);
+        let tiles = Arc::clone(&tiles);
+        let next_tile = Arc::clone(&next_tile);
+        let tx = mpsc::Sender::clone(&tx);
+
+        handles.push(thread::spawn(move || {
+            loop {
+                let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                if index >= tiles.len() {
+                    break;
+                }
+
+                let result = render_tile(&scene, &settings, tiles[index]);
+                if tx.send(result).is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+
+    drop(tx);
+
+    for result in rx.iter() {
+        on_tile(result);
+    }
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::thread_rng;
+    use rand::Rng;
+
+    use crate::bvh_tree::SplitStrategy;
+    use crate::scene2::Integrator;
+    use crate::scene_generator::three_sphere;
+    use crate::tile_renderer::{generate_tiles, render, sample_coordinate, RenderSettings, Tile};
+
+    #[test]
+    fn test_coordinate_bounding() {
+        let mut rng = thread_rng();
+        let initial_cord = 2000;
+        for attempt in 0..100000 {
+            let init_coord_f32 = initial_cord as f32;
+            let rnd_f32 = rng.gen::<f64>() as f32;
+            let f32_coord = sample_coordinate(initial_cord as u32, rnd_f32);
+            let converted_back = f32_coord as usize;
+
+            assert_eq!(converted_back, initial_cord, "Attempt {} converting {:.60} + {:.60} (becomes {:.60}) into int", attempt, init_coord_f32, rnd_f32, f32_coord)
+        }
+    }
+
+    #[test]
+    fn tiles_cover_image_once() {
+        let (width, height) = (37, 21);
+        let tiles = generate_tiles(width, height, 8);
+
+        let mut covered = vec![0; (width * height) as usize];
+        for tile in &tiles {
+            assert!(tile.width <= 8 && tile.height <= 8);
+            for y in tile.y0..tile.y0 + tile.height {
+                for x in tile.x0..tile.x0 + tile.width {
+                    covered[(y * width + x) as usize] += 1;
+                }
+            }
+        }
+
+        assert!(covered.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn tiles_start_at_top() {
+        let tiles = generate_tiles(20, 20, 16);
+
+        assert_eq!(tiles[0], Tile { x0: 0, y0: 4, width: 16, height: 16 });
+        assert_eq!(tiles[3], Tile { x0: 16, y0: 0, width: 4, height: 4 });
+    }
+
+    #[test]
+    fn empty_image_has_no_tiles() {
+        assert!(generate_tiles(0, 10, 16).is_empty());
+        assert!(generate_tiles(10, 0, 16).is_empty());
+    }
+
+    #[test]
+    fn render_returns_every_tile() {
+        let settings = RenderSettings {
+            width: 20,
+            height: 10,
+            samples: 2,
+            tile_size: 8,
+            threads: 3,
+            keep_samples: true,
+            integrator: Integrator::Path,
+        };
+
+        let mut pixel_count = 0;
+        let mut sample_count = 0;
+        render(Arc::new(three_sphere(0.0, 1.0, SplitStrategy::default())), settings, |result| {
+            assert_eq!(result.pixels.len() as u32, result.tile.pixel_count());
+            pixel_count += result.pixels.len();
+            sample_count += result.samples.len();
+        });
+
+        assert_eq!(pixel_count, 20 * 10);
+        assert_eq!(sample_count, 20 * 10 * 2);
+    }
+}